@@ -0,0 +1,49 @@
+// Benchmarks of the interpreter. Every benchmark runs the run() function of a
+// script from the js directory, with and without register instructions. The
+// properties script is also run without inline caches.
+
+#![feature(test)]
+
+extern crate test;
+extern crate rjs;
+
+use rjs::rt::JsEnvBuilder;
+use test::Bencher;
+
+fn bench(b: &mut Bencher, js: &str, builder: JsEnvBuilder) {
+    let mut env = builder.build().ok().unwrap();
+    
+    env.eval(js).ok().unwrap();
+    
+    b.iter(|| env.eval("run()").ok().unwrap());
+}
+
+macro_rules! benches {
+    ( $( $name:ident, $stack_name:ident, $file:expr );* ) => {
+        $(
+            #[bench]
+            fn $name(b: &mut Bencher) {
+                bench(b, include_str!($file), JsEnvBuilder::new());
+            }
+            
+            #[bench]
+            fn $stack_name(b: &mut Bencher) {
+                bench(b, include_str!($file), JsEnvBuilder::new().registers(false));
+            }
+        )*
+    }
+}
+
+benches!(
+    fib, fib_stack, "js/fib.js";
+    loops, loops_stack, "js/loops.js";
+    sieve, sieve_stack, "js/sieve.js";
+    strings, strings_stack, "js/strings.js";
+    objects, objects_stack, "js/objects.js";
+    properties, properties_stack, "js/properties.js"
+);
+
+#[bench]
+fn properties_uncached(b: &mut Bencher) {
+    bench(b, include_str!("js/properties.js"), JsEnvBuilder::new().inline_caches(false));
+}
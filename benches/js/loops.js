@@ -0,0 +1,10 @@
+function run() {
+    var sum = 0;
+    for (var i = 0; i < 100000; i++) {
+        sum = sum + i * 2;
+        if (sum > 1000000) {
+            sum = sum - 1000000;
+        }
+    }
+    return sum;
+}
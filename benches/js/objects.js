@@ -0,0 +1,17 @@
+function Point(x, y) {
+    this.x = x;
+    this.y = y;
+}
+
+Point.prototype.add = function (other) {
+    return new Point(this.x + other.x, this.y + other.y);
+};
+
+function run() {
+    var point = new Point(0, 0);
+    var delta = new Point(1, 2);
+    for (var i = 0; i < 10000; i++) {
+        point = point.add(delta);
+    }
+    return point.x + point.y;
+}
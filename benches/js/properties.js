@@ -0,0 +1,26 @@
+function Vector(x, y, z) {
+    this.x = x;
+    this.y = y;
+    this.z = z;
+}
+
+Vector.prototype.dot = function (other) {
+    return this.x * other.x + this.y * other.y + this.z * other.z;
+};
+
+Vector.prototype.scale = function (factor) {
+    this.x *= factor;
+    this.y *= factor;
+    this.z *= factor;
+};
+
+function run() {
+    var a = new Vector(1, 2, 3);
+    var b = new Vector(3, 2, 1);
+    var sum = 0;
+    for (var i = 0; i < 10000; i++) {
+        a.scale(1);
+        sum += a.dot(b) + b.x + b.y + b.z;
+    }
+    return sum;
+}
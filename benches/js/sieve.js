@@ -0,0 +1,17 @@
+function run() {
+    var size = 10000;
+    var flags = [];
+    for (var i = 0; i < size; i++) {
+        flags[i] = true;
+    }
+    var count = 0;
+    for (var i = 2; i < size; i++) {
+        if (flags[i]) {
+            count++;
+            for (var j = i + i; j < size; j = j + i) {
+                flags[j] = false;
+            }
+        }
+    }
+    return count;
+}
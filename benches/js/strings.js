@@ -0,0 +1,13 @@
+function run() {
+    var result = '';
+    for (var i = 0; i < 2000; i++) {
+        result = result + (i % 10);
+    }
+    var count = 0;
+    for (var i = 0; i < result.length; i++) {
+        if (result.charAt(i) === '7') {
+            count++;
+        }
+    }
+    return count;
+}
@@ -0,0 +1,684 @@
+extern crate rustc_serialize;
+
+use rt::{JsEnv, JsEnvBuilder, JsValue, JsRawValue, JsType, JsHandle, JsItem, FromJs};
+use rt::{Debugger, JsPauseReason, JsResume, JsBreakpoint, JsError, JsAbort};
+use gc::Root;
+use ::JsResult;
+use self::rustc_serialize::json::{Json, ToJson};
+use std::collections::{BTreeMap, HashMap};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::io::prelude::*;
+use std::fs;
+
+// All script code runs on a single thread, which is reported with this id.
+const THREAD_ID : u64 = 1;
+
+/// Debug Adapter Protocol server. The server reads requests from the input
+/// and writes responses and events to the output. The script is run when
+/// the client sends the configurationDone request; console output of the
+/// script is sent to the client as output events.
+pub struct DapServer {
+    env: JsEnv,
+    session: Rc<RefCell<Session>>,
+    file: String,
+    strict: bool
+}
+
+struct Session {
+    input: Box<BufRead>,
+    output: Box<Write>,
+    seq: u64,
+    // Variable references handed out while paused. Reference n is at
+    // offset n - 1. References are invalidated when execution resumes.
+    refs: Vec<VarRef>,
+    breakpoints: HashMap<String, Vec<JsBreakpoint>>,
+    started: bool,
+    disconnected: bool
+}
+
+enum VarRef {
+    Locals(usize),
+    Value(Root<JsRawValue>)
+}
+
+enum Message {
+    Request(Json),
+    // A message that could not be read. The session continues with the
+    // next message.
+    Invalid(String),
+    End
+}
+
+enum Action {
+    None,
+    Start,
+    Resume(JsResume),
+    Disconnect
+}
+
+struct DapDebugger {
+    session: Rc<RefCell<Session>>,
+    file: String
+}
+
+impl DapServer {
+    pub fn new(file: &str, strict: bool, input: Box<BufRead>, output: Box<Write>) -> JsResult<DapServer> {
+        let session = Rc::new(RefCell::new(Session {
+            input: input,
+            output: output,
+            seq: 1,
+            refs: Vec::new(),
+            breakpoints: HashMap::new(),
+            started: false,
+            disconnected: false
+        }));
+        
+        // Output of the script would corrupt the protocol stream, so it is
+        // sent to the client instead.
+        
+        let console_session = session.clone();
+        
+        let mut env = try!(JsEnvBuilder::new()
+            .strict(strict)
+            .optimize(false)
+            .console(Box::new(move |message: &str| {
+                send_event(&console_session, "output", Some(object(vec![
+                    ("category", "stdout".to_json()),
+                    ("output", format!("{}\n", message).to_json())
+                ])));
+            }))
+            .build());
+        
+        env.set_debugger(Some(Box::new(DapDebugger {
+            session: session.clone(),
+            file: file.to_string()
+        })));
+        
+        Ok(DapServer {
+            env: env,
+            session: session,
+            file: file.to_string(),
+            strict: strict
+        })
+    }
+    
+    /// Processes requests until the client disconnects or closes the input.
+    pub fn run(&mut self) {
+        loop {
+            if self.session.borrow().disconnected {
+                return;
+            }
+            
+            let request = match read_message(&self.session) {
+                Message::Request(request) => request,
+                Message::Invalid(error) => {
+                    send_protocol_error(&self.session, &error);
+                    continue;
+                }
+                Message::End => return
+            };
+            
+            match handle(&mut self.env, &self.session, &self.file, &request, false) {
+                Action::Start => self.launch(),
+                Action::Disconnect => return,
+                _ => {}
+            }
+        }
+    }
+    
+    fn launch(&mut self) {
+        if self.session.borrow().started {
+            return;
+        }
+        self.session.borrow_mut().started = true;
+        
+        let result = self.env.run_strict(&self.file, self.strict);
+        
+        // A disconnect while paused interrupts the script.
+        
+        if self.session.borrow().disconnected {
+            return;
+        }
+        
+        let exit_code = match result {
+            Ok(..) => 0,
+            Err(error) => {
+                let message = error_to_string(&mut self.env, error);
+                
+                send_event(&self.session, "output", Some(object(vec![
+                    ("category", "stderr".to_json()),
+                    ("output", format!("Uncaught {}\n", message).to_json())
+                ])));
+                
+                1
+            }
+        };
+        
+        send_event(&self.session, "terminated", None);
+        send_event(&self.session, "exited", Some(object(vec![
+            ("exitCode", exit_code.to_json())
+        ])));
+    }
+}
+
+impl Debugger for DapDebugger {
+    fn paused(&mut self, env: &mut JsEnv, reason: JsPauseReason) -> JsResume {
+        let (reason, description) = match reason {
+            JsPauseReason::DebuggerStatement => ("pause", "Paused on debugger statement"),
+            JsPauseReason::Breakpoint(..) => ("breakpoint", "Paused on breakpoint"),
+            JsPauseReason::Step => ("step", "Paused after step")
+        };
+        
+        send_event(&self.session, "stopped", Some(object(vec![
+            ("reason", reason.to_json()),
+            ("description", description.to_json()),
+            ("threadId", THREAD_ID.to_json()),
+            ("allThreadsStopped", true.to_json())
+        ])));
+        
+        loop {
+            let action = match read_message(&self.session) {
+                Message::Request(request) => handle(env, &self.session, &self.file, &request, true),
+                Message::Invalid(error) => {
+                    send_protocol_error(&self.session, &error);
+                    Action::None
+                }
+                Message::End => Action::Disconnect
+            };
+            
+            match action {
+                Action::Resume(resume) => {
+                    self.session.borrow_mut().refs.clear();
+                    
+                    return resume;
+                }
+                Action::Disconnect => {
+                    {
+                        let mut session = self.session.borrow_mut();
+                        session.refs.clear();
+                        session.disconnected = true;
+                    }
+                    
+                    env.interrupt_handle().interrupt();
+                    
+                    return JsResume::Continue;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn handle(env: &mut JsEnv, session: &Rc<RefCell<Session>>, file: &str, request: &Json, paused: bool) -> Action {
+    let command = request.find("command").and_then(|command| command.as_string()).unwrap_or("").to_string();
+    let null = Json::Null;
+    let arguments = request.find("arguments").unwrap_or(&null);
+    
+    match &*command {
+        "initialize" => {
+            respond(session, request, Ok(Some(object(vec![
+                ("supportsConfigurationDoneRequest", true.to_json()),
+                ("supportsEvaluateForHovers", true.to_json())
+            ]))));
+            send_event(session, "initialized", None);
+        }
+        "launch" | "attach" => respond(session, request, Ok(None)),
+        "configurationDone" => {
+            respond(session, request, Ok(None));
+            
+            return Action::Start;
+        }
+        "setBreakpoints" => {
+            let result = set_breakpoints(env, session, file, arguments);
+            respond(session, request, result);
+        }
+        "threads" => {
+            respond(session, request, Ok(Some(object(vec![
+                ("threads", Json::Array(vec![object(vec![
+                    ("id", THREAD_ID.to_json()),
+                    ("name", "main".to_json())
+                ])]))
+            ]))));
+        }
+        "stackTrace" => {
+            let frames = if paused { stack_trace(env) } else { Vec::new() };
+            
+            respond(session, request, Ok(Some(object(vec![
+                ("totalFrames", (frames.len() as u64).to_json()),
+                ("stackFrames", Json::Array(frames))
+            ]))));
+        }
+        "scopes" => {
+            let result = if paused {
+                let frame = arguments.find("frameId").and_then(|frame| frame.as_u64()).unwrap_or(0) as usize;
+                Ok(Some(scopes(env, session, frame)))
+            } else {
+                Err("not paused".to_string())
+            };
+            
+            respond(session, request, result);
+        }
+        "variables" => {
+            let reference = arguments.find("variablesReference").and_then(|reference| reference.as_u64()).unwrap_or(0) as usize;
+            let result = variables(env, session, reference);
+            
+            respond(session, request, result);
+        }
+        "evaluate" => {
+            let result = evaluate(env, session, arguments, paused);
+            respond(session, request, result);
+        }
+        "continue" | "next" | "stepIn" | "stepOut" => {
+            if !paused {
+                respond(session, request, Err("not paused".to_string()));
+            } else {
+                let resume = match &*command {
+                    "continue" => JsResume::Continue,
+                    "next" => JsResume::StepOver,
+                    "stepIn" => JsResume::StepInto,
+                    _ => JsResume::StepOut
+                };
+                
+                let body = if resume == JsResume::Continue {
+                    Some(object(vec![("allThreadsContinued", true.to_json())]))
+                } else {
+                    None
+                };
+                
+                respond(session, request, Ok(body));
+                
+                return Action::Resume(resume);
+            }
+        }
+        "disconnect" => {
+            respond(session, request, Ok(None));
+            
+            return Action::Disconnect;
+        }
+        _ => respond(session, request, Err(format!("unsupported command '{}'", command)))
+    }
+    
+    Action::None
+}
+
+fn set_breakpoints(env: &mut JsEnv, session: &Rc<RefCell<Session>>, file: &str, arguments: &Json) -> Result<Option<Json>, String> {
+    let path = match arguments.find_path(&["source", "path"]).and_then(|path| path.as_string()) {
+        Some(path) => path.to_string(),
+        None => return Err("missing source path".to_string())
+    };
+    
+    // Locations in the script use the file name the script was run with,
+    // which may be relative where the client sends an absolute path.
+    
+    let path = match (fs::canonicalize(&path), fs::canonicalize(file)) {
+        (Ok(ref source), Ok(ref script)) if source == script => file.to_string(),
+        _ => path
+    };
+    
+    let old = session.borrow_mut().breakpoints.remove(&path).unwrap_or_else(Vec::new);
+    for breakpoint in old {
+        env.remove_breakpoint(breakpoint);
+    }
+    
+    let mut breakpoints = Vec::new();
+    let mut result = Vec::new();
+    
+    if let Some(lines) = arguments.find("breakpoints").and_then(|breakpoints| breakpoints.as_array()) {
+        for line in lines {
+            if let Some(line) = line.find("line").and_then(|line| line.as_u64()) {
+                breakpoints.push(env.set_breakpoint(&path, line as u32));
+                
+                result.push(object(vec![
+                    ("verified", true.to_json()),
+                    ("line", line.to_json())
+                ]));
+            }
+        }
+    }
+    
+    session.borrow_mut().breakpoints.insert(path, breakpoints);
+    
+    Ok(Some(object(vec![("breakpoints", Json::Array(result))])))
+}
+
+fn stack_trace(env: &mut JsEnv) -> Vec<Json> {
+    let backtrace = env.backtrace();
+    let mut frames = Vec::new();
+    
+    for (id, frame) in backtrace.frames().iter().enumerate() {
+        let name = match frame.function {
+            Some(ref function) => function.clone(),
+            None if frame.location.is_some() => "(global)".to_string(),
+            None => "(native)".to_string()
+        };
+        
+        let mut fields = vec![
+            ("id", (id as u64).to_json()),
+            ("name", name.to_json())
+        ];
+        
+        if let Some(ref location) = frame.location {
+            let source_name = location.file.rsplit(|c: char| c == '/' || c == '\\').next().unwrap_or(&location.file).to_string();
+            
+            fields.push(("source", object(vec![
+                ("name", source_name.to_json()),
+                ("path", location.file.to_json())
+            ])));
+            fields.push(("line", (location.line as u64).to_json()));
+            fields.push(("column", (location.column as u64).to_json()));
+        } else {
+            fields.push(("line", 0u64.to_json()));
+            fields.push(("column", 0u64.to_json()));
+        }
+        
+        frames.push(object(fields));
+    }
+    
+    frames
+}
+
+fn scopes(env: &mut JsEnv, session: &Rc<RefCell<Session>>, frame: usize) -> Json {
+    let locals = add_ref(session, VarRef::Locals(frame));
+    
+    let global = {
+        let _scope = env.new_local_scope();
+        
+        let global = env.handle(JsHandle::Global).as_value();
+        add_ref(session, VarRef::Value(env.new_root(global)))
+    };
+    
+    object(vec![
+        ("scopes", Json::Array(vec![
+            object(vec![
+                ("name", "Locals".to_json()),
+                ("variablesReference", locals.to_json()),
+                ("expensive", false.to_json())
+            ]),
+            object(vec![
+                ("name", "Global".to_json()),
+                ("variablesReference", global.to_json()),
+                ("expensive", true.to_json())
+            ])
+        ]))
+    ])
+}
+
+fn variables(env: &mut JsEnv, session: &Rc<RefCell<Session>>, reference: usize) -> Result<Option<Json>, String> {
+    let _scope = env.new_local_scope();
+    
+    // Resolve the reference into the list of name/value pairs to report.
+    
+    let values = {
+        let reference = {
+            let session = session.borrow();
+            
+            if reference == 0 || reference > session.refs.len() {
+                return Err("invalid variables reference".to_string());
+            }
+            
+            match session.refs[reference - 1] {
+                VarRef::Locals(frame) => VarRef::Locals(frame),
+                VarRef::Value(ref value) => VarRef::Value(value.clone())
+            }
+        };
+        
+        match reference {
+            VarRef::Locals(frame) => {
+                let mut values = Vec::new();
+                
+                if let Some(this) = env.debug_this(frame) {
+                    values.push(("this".to_string(), this.as_value(env)));
+                }
+                
+                for (name, value) in env.debug_locals(frame).unwrap_or_else(Vec::new) {
+                    values.push((name, value.as_value(env)));
+                }
+                
+                values
+            }
+            VarRef::Value(value) => {
+                let value = value.as_value(env);
+                
+                let properties = match HashMap::<String, JsValue>::from_js(env, value) {
+                    Ok(properties) => properties,
+                    Err(error) => return Err(error_to_string(env, error))
+                };
+                
+                let mut values = properties.into_iter().collect::<Vec<_>>();
+                values.sort_by(|a, b| a.0.cmp(&b.0));
+                
+                values
+            }
+        }
+    };
+    
+    let mut variables = Vec::new();
+    
+    for (name, value) in values {
+        let (value, reference) = describe(env, session, value);
+        
+        variables.push(object(vec![
+            ("name", name.to_json()),
+            ("value", value.to_json()),
+            ("variablesReference", reference.to_json())
+        ]));
+    }
+    
+    Ok(Some(object(vec![("variables", Json::Array(variables))])))
+}
+
+fn evaluate(env: &mut JsEnv, session: &Rc<RefCell<Session>>, arguments: &Json, paused: bool) -> Result<Option<Json>, String> {
+    let expression = match arguments.find("expression").and_then(|expression| expression.as_string()) {
+        Some(expression) => expression.to_string(),
+        None => return Err("missing expression".to_string())
+    };
+    
+    let result = match arguments.find("frameId").and_then(|frame| frame.as_u64()) {
+        Some(frame) if paused => env.debug_eval(frame as usize, &expression),
+        _ => env.eval(&expression)
+    };
+    
+    let _scope = env.new_local_scope();
+    
+    match result {
+        Ok(value) => {
+            let value = value.as_value(env);
+            
+            // References are released when execution resumes. Outside of a
+            // pause there is nothing that releases them, so none are handed
+            // out.
+            
+            let (value, reference) = if paused {
+                describe(env, session, value)
+            } else {
+                (inspect(env, value), 0)
+            };
+            
+            Ok(Some(object(vec![
+                ("result", value.to_json()),
+                ("variablesReference", reference.to_json())
+            ])))
+        }
+        Err(error) => Err(error_to_string(env, error))
+    }
+}
+
+// Gets the display value of a value and the reference through which its
+// properties can be requested. Only objects have properties.
+fn describe(env: &mut JsEnv, session: &Rc<RefCell<Session>>, value: JsValue) -> (String, u64) {
+    let string = inspect(env, value);
+    
+    let reference = if value.ty() == JsType::Object {
+        add_ref(session, VarRef::Value(env.new_root(value)))
+    } else {
+        0
+    };
+    
+    (string, reference)
+}
+
+fn inspect(env: &mut JsEnv, value: JsValue) -> String {
+    match env.inspect(value) {
+        Ok(string) => string,
+        Err(..) => "(cannot inspect value)".to_string()
+    }
+}
+
+fn add_ref(session: &Rc<RefCell<Session>>, reference: VarRef) -> u64 {
+    let mut session = session.borrow_mut();
+    
+    session.refs.push(reference);
+    session.refs.len() as u64
+}
+
+fn error_to_string(env: &mut JsEnv, error: JsError) -> String {
+    if let JsError::Abort(abort) = error {
+        return match abort {
+            JsAbort::Interrupted => "execution was interrupted".to_string(),
+            JsAbort::BudgetExhausted => "instruction budget exhausted".to_string(),
+            JsAbort::OutOfMemory => "out of memory".to_string()
+        };
+    }
+    
+    let _scope = env.new_local_scope();
+    
+    let backtrace = error.backtrace().map(|backtrace| backtrace.to_string());
+    
+    let error = error.as_runtime(env).as_value(env);
+    
+    let mut message = match error.to_string(env) {
+        Ok(message) => message.to_string(),
+        Err(..) => "(cannot convert error to string)".to_string()
+    };
+    
+    if let Some(backtrace) = backtrace {
+        if backtrace.len() > 0 {
+            message.push('\n');
+            message.push_str(&backtrace);
+        }
+    }
+    
+    message
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    let mut object = BTreeMap::new();
+    
+    for (key, value) in fields {
+        object.insert(key.to_string(), value);
+    }
+    
+    Json::Object(object)
+}
+
+// Messages consist of a header with the length of the content, followed by
+// an empty line and the JSON content. Only the end of the input or an I/O
+// error ends the session.
+fn read_message(session: &Rc<RefCell<Session>>) -> Message {
+    let mut session = session.borrow_mut();
+    
+    let mut header = false;
+    let mut length = None;
+    
+    loop {
+        let mut line = String::new();
+        match session.input.read_line(&mut line) {
+            Ok(0) | Err(..) => return Message::End,
+            _ => {}
+        }
+        
+        // Empty lines before the header are skipped.
+        
+        let line = line.trim();
+        if line.len() == 0 {
+            if header {
+                break;
+            }
+            continue;
+        }
+        
+        header = true;
+        
+        if line.to_lowercase().starts_with("content-length:") {
+            length = line["content-length:".len()..].trim().parse::<u64>().ok();
+        }
+    }
+    
+    let length = match length {
+        Some(length) => length,
+        None => return Message::Invalid("missing or invalid Content-Length header".to_string())
+    };
+    
+    let mut content = Vec::new();
+    match session.input.by_ref().take(length).read_to_end(&mut content) {
+        Ok(read) if read as u64 == length => {},
+        _ => return Message::End
+    }
+    
+    let content = match String::from_utf8(content) {
+        Ok(content) => content,
+        Err(..) => return Message::Invalid("message is not valid UTF-8".to_string())
+    };
+    
+    match Json::from_str(&content) {
+        Ok(request) => Message::Request(request),
+        Err(error) => Message::Invalid(format!("message is not valid JSON: {}", error))
+    }
+}
+
+// Reports a message that could not be read. There is no request to respond
+// to, so the error is sent as debugger output.
+fn send_protocol_error(session: &Rc<RefCell<Session>>, error: &str) {
+    send_event(session, "output", Some(object(vec![
+        ("category", "console".to_json()),
+        ("output", format!("Invalid message: {}\n", error).to_json())
+    ])));
+}
+
+fn send(session: &Rc<RefCell<Session>>, mut message: BTreeMap<String, Json>) {
+    let mut session = session.borrow_mut();
+    
+    message.insert("seq".to_string(), session.seq.to_json());
+    session.seq += 1;
+    
+    let content = Json::Object(message).to_string();
+    
+    write!(session.output, "Content-Length: {}\r\n\r\n{}", content.len(), content).ok();
+    session.output.flush().ok();
+}
+
+fn respond(session: &Rc<RefCell<Session>>, request: &Json, result: Result<Option<Json>, String>) {
+    let mut message = BTreeMap::new();
+    
+    message.insert("type".to_string(), "response".to_json());
+    message.insert("request_seq".to_string(), request.find("seq").cloned().unwrap_or(Json::Null));
+    message.insert("command".to_string(), request.find("command").cloned().unwrap_or(Json::Null));
+    
+    match result {
+        Ok(body) => {
+            message.insert("success".to_string(), true.to_json());
+            if let Some(body) = body {
+                message.insert("body".to_string(), body);
+            }
+        }
+        Err(error) => {
+            message.insert("success".to_string(), false.to_json());
+            message.insert("message".to_string(), error.to_json());
+        }
+    }
+    
+    send(session, message);
+}
+
+fn send_event(session: &Rc<RefCell<Session>>, event: &str, body: Option<Json>) {
+    let mut message = BTreeMap::new();
+    
+    message.insert("type".to_string(), "event".to_json());
+    message.insert("event".to_string(), event.to_json());
+    if let Some(body) = body {
+        message.insert("body".to_string(), body);
+    }
+    
+    send(session, message);
+}
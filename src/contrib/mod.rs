@@ -1 +1,3 @@
+pub mod dap;
+pub mod repl;
 pub mod test262;
@@ -0,0 +1,178 @@
+use rt::{JsEnv, JsType, JsItem};
+use ::{JsResult, JsError};
+use gc::Root;
+use rt::JsRawValue;
+use std::io::prelude::*;
+use std::io;
+use std::mem;
+
+const HELP : &'static str = "\
+.help          Print this message
+.exit          Exit the REPL
+.break         Discard the current multi-line input
+.load <file>   Run a file in the current environment
+.ir [code]     Print the IR of the code, or of the last input, without running it
+";
+
+pub struct Repl {
+    env: JsEnv,
+    strict: bool,
+    last: Option<String>
+}
+
+impl Repl {
+    pub fn new(env: JsEnv, strict: bool) -> Repl {
+        Repl {
+            env: env,
+            strict: strict,
+            last: None
+        }
+    }
+    
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        let mut input = String::new();
+        
+        loop {
+            print!("{}", if input.len() == 0 { "> " } else { "... " });
+            io::stdout().flush().ok();
+            
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(..) => {
+                    println!("");
+                    break;
+                }
+                _ => {}
+            }
+            
+            let command = line.trim().to_string();
+            
+            if command == ".break" {
+                input.clear();
+                continue;
+            }
+            
+            if input.len() == 0 {
+                if command.starts_with(".") {
+                    if !self.command(&command) {
+                        break;
+                    }
+                    continue;
+                }
+                if command.len() == 0 {
+                    continue;
+                }
+            }
+            
+            input.push_str(&line);
+            
+            let result = self.env.eval_strict(&input, self.strict);
+            
+            // Incomplete input is not reported; we wait for more lines instead.
+            
+            if let Err(ref error) = result {
+                if is_incomplete(error) {
+                    continue;
+                }
+            }
+            
+            self.last = Some(mem::replace(&mut input, String::new()));
+            
+            self.print_result(result);
+        }
+    }
+    
+    fn command(&mut self, command: &str) -> bool {
+        let (name, arg) = match command.find(' ') {
+            Some(offset) => (&command[..offset], command[offset + 1..].trim()),
+            None => (command, "")
+        };
+        
+        match name {
+            ".exit" => return false,
+            ".help" => print!("{}", HELP),
+            ".load" => {
+                if arg.len() == 0 {
+                    println!("missing file name");
+                } else {
+                    let result = self.env.run_strict(arg, self.strict);
+                    self.print_result(result);
+                }
+            }
+            ".ir" => {
+                let js = if arg.len() > 0 {
+                    Some(arg.to_string())
+                } else {
+                    self.last.clone()
+                };
+                
+                match js {
+                    Some(js) => {
+                        match self.env.print_ir(&js, self.strict) {
+                            Ok(ir) => println!("{}", ir.trim()),
+                            Err(error) => self.print_error(error)
+                        }
+                    }
+                    None => println!("nothing to print")
+                }
+            }
+            _ => println!("unknown command '{}'; type .help for a list of commands", name)
+        }
+        
+        true
+    }
+    
+    fn print_result(&mut self, result: JsResult<Root<JsRawValue>>) {
+        match result {
+            Ok(value) => {
+                let _scope = self.env.new_local_scope();
+                
+                let value = value.as_value(&self.env);
+                
+                match self.env.inspect(value) {
+                    Ok(string) => println!("{}", string),
+                    Err(error) => self.print_error(error)
+                }
+            }
+            Err(error) => self.print_error(error)
+        }
+    }
+    
+    fn print_error(&mut self, error: JsError) {
+        let _scope = self.env.new_local_scope();
+        
+        let backtrace = error.backtrace().map(|backtrace| backtrace.to_string());
+        
+        let error = error.as_runtime(&mut self.env).as_value(&self.env);
+        
+        // Errors are printed using their string representation; other thrown
+        // values are inspected.
+        
+        let message = if error.ty() == JsType::Object {
+            error.to_string(&mut self.env).map(|message| message.to_string())
+        } else {
+            self.env.inspect(error)
+        };
+        
+        match message {
+            Ok(message) => println!("Uncaught {}", message),
+            Err(..) => println!("Uncaught (cannot convert error to string)")
+        }
+        
+        if let Some(backtrace) = backtrace {
+            if backtrace.len() > 0 {
+                println!("{}", backtrace);
+            }
+        }
+    }
+}
+
+// Input is incomplete when the lexer or parser ran into the end of the input,
+// e.g. because a block has not been closed yet.
+fn is_incomplete(error: &JsError) -> bool {
+    match *error {
+        JsError::Incomplete(..) => true,
+        _ => false
+    }
+}
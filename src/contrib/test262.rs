@@ -1,6 +1,8 @@
 extern crate rustc_serialize;
 
 use rt::*;
+use gc::{GcOpts, GcStrategy};
+use ::JsResult;
 use std::io::prelude::*;
 use std::fs::{File, OpenOptions, read_dir, metadata};
 use std::collections::{HashMap, HashSet};
@@ -13,8 +15,9 @@ use self::rustc_serialize::json::Json;
 pub struct Test262Runner;
 
 impl Test262Runner {
-    pub fn run() {
-        walk_dir(&mut Runner::new(), "");
+    /// Runs the test262 suite with the given garbage collector.
+    pub fn run(strategy: GcStrategy) {
+        walk_dir(&mut Runner::new(strategy), "");
     }
 }
 
@@ -118,14 +121,15 @@ fn walk_dir(runner: &mut Runner, dir: &str) {
 
 struct Runner {
     skip: HashSet<String>,
-    seen: usize
+    seen: usize,
+    strategy: GcStrategy
 }
 
 impl Runner {
-    fn new() -> Runner {
+    fn new(strategy: GcStrategy) -> Runner {
         // Load the list of tests that succeeded last time.
         
-        let mut skip = if let Ok(mut file) = File::open("succeeded") {
+        let mut skip = if let Ok(mut file) = File::open(succeeded_file(strategy)) {
             let mut content = String::new();
             file.read_to_string(&mut content).ok().unwrap();
             
@@ -151,7 +155,8 @@ impl Runner {
         
         Runner {
             skip: skip,
-            seen: 0
+            seen: 0,
+            strategy: strategy
         }
     }
     
@@ -168,7 +173,8 @@ impl Runner {
         
         let result = {
             let file = file.clone();
-            thread::spawn(move || run_safe(file)).join()
+            let strategy = self.strategy;
+            thread::spawn(move || run_safe(file, strategy)).join()
         };
         
         match result {
@@ -176,7 +182,7 @@ impl Runner {
                 let mut out = OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open("succeeded")
+                    .open(succeeded_file(self.strategy))
                     .ok()
                     .unwrap();
                 
@@ -200,7 +206,30 @@ impl Runner {
     }
 }
 
-fn run_safe(file: String) {
+// Tests that passed with one collector can still fail with another one, or
+// with the gcstress feature, so each of them keeps its own list.
+fn succeeded_file(strategy: GcStrategy) -> String {
+    let mut file = match strategy {
+        GcStrategy::Copying => "succeeded",
+        GcStrategy::MarkSweep => "succeeded-mark-sweep",
+        GcStrategy::Generational => "succeeded-generational"
+    }.to_string();
+    
+    if cfg!(feature = "gcstress") {
+        file.push_str("-gcstress");
+    }
+    
+    file
+}
+
+fn create_env(strategy: GcStrategy) -> JsResult<JsEnv> {
+    let mut opts = GcOpts::default();
+    opts.strategy = strategy;
+    
+    JsEnvBuilder::new().gc_opts(opts).build()
+}
+
+fn run_safe(file: String, strategy: GcStrategy) {
     let file = format!("tests/tc39/test/{}", file);
     
     let mut js = String::new();
@@ -262,7 +291,7 @@ fn run_safe(file: String) {
     
     // First try running with debug disabled. If this succeeds, we stop here.
     
-    if let Ok(mut env) = JsEnv::new() {
+    if let Ok(mut env) = create_env(strategy) {
         for include in &includes {
             env.run(&("tests/tc39/harness/".to_string() + include)).ok().unwrap();
         }
@@ -276,7 +305,7 @@ fn run_safe(file: String) {
     
     debug::debug_enable(true);
     
-    let mut env = JsEnv::new().ok().unwrap();
+    let mut env = create_env(strategy).ok().unwrap();
     
     for include in &includes {
         debugln!("INCLUDING {}", include);
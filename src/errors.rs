@@ -10,6 +10,8 @@ pub static TYPE_NOT_EXTENSIBLE : &'static str = "Object is not extensible";
 pub static TYPE_CANNOT_WRITE : &'static str = "Property is not writable";
 pub static TYPE_CANNOT_DELETE : &'static str = "Property cannot be deleted";
 pub static TYPE_NOT_A_CONSTRUCTOR : &'static str = "Not a constructor";
+pub static TYPE_NOT_HOST_OBJECT : &'static str = "Object is not an instance of the expected host class";
+pub static TYPE_HAS_HOST_PAYLOAD : &'static str = "Object already is a function or has a host payload";
 pub static TYPE_UNDEFINED : &'static str = "Invalid operation on undefined";
 pub static TYPE_NULL : &'static str = "Invalid operation on null";
 pub static TYPE_IN_RHS_NOT_OBJECT : &'static str = "Target of in must be an object";
@@ -24,5 +26,7 @@ pub static TYPE_NOT_COERCIBLE : &'static str = "Value is null or undefined";
 pub static TYPE_INVALID_ARGUMENTS_ARRAY : &'static str = "Arguments argument is not of a valid type";
 pub static TYPE_CYCLICAL_REFERENCE : &'static str = "Cannot stringify object structure with cyclical references";
 pub static TYPE_INVALID_REGEXP_ARGS : &'static str = "Flags cannot be combined with a RegExp object";
+pub static TYPE_REGEXP_DISABLED : &'static str = "Regular expressions are not available";
 pub static SYNTAX_INVALID_REGEXP_FLAGS : &'static str = "Invalid flags";
 pub static SYNTAX_INVALID_REGEX : &'static str = "Invalid regular expression";
+pub static RANGE_STACK_OVERFLOW : &'static str = "Maximum call stack size exceeded";
@@ -10,16 +10,21 @@ extern crate time;
 use std::ops::Index;
 use std::ptr;
 use std::mem::{size_of, transmute, swap};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use self::strategy::Strategy;
 use self::strategy::copying::Copying;
+use self::strategy::mark_sweep::MarkSweep;
+use self::strategy::generational::Generational;
 use std::rc::Rc;
 pub use self::handles::{ArrayLocal, ArrayRoot, Array, Local, Ptr, Root};
 pub use self::handles::{AsPtr, AsArray};
+pub use self::stats::{HeapStats, GcTypeStats};
+use self::stats::PauseStats;
 
 pub mod os;
 mod strategy;
 pub mod handles;
+mod stats;
 
 #[allow(non_camel_case_types)] 
 pub type ptr_t = *const u8;
@@ -50,7 +55,6 @@ impl LocalScopeData {
     }
     
     fn add(&mut self, ptr: ptr_t) -> *const ptr_t {
-        unsafe { assert!(*transmute::<_, *const usize>(ptr) != 0x30252d0); }
         if self.current.len() == self.current.capacity() {
             self.grow();
         }
@@ -68,20 +72,46 @@ impl LocalScopeData {
     }
 }
 
+/// The collector used by a heap.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GcStrategy {
+    /// Copies the live heap between two semispaces on every collection.
+    Copying,
+    /// Marks the live heap and reuses the space of dead objects, compacting
+    /// the heap occasionally. Uses half the memory of the copying collector
+    /// and does not move large, static object graphs on every collection.
+    MarkSweep,
+    /// Allocates new objects in a nursery that is collected separately from
+    /// the rest of the heap. Short-lived objects are reclaimed without tracing
+    /// the old objects.
+    Generational
+}
+
 pub struct GcOpts {
+    pub strategy: GcStrategy,
     pub initial_heap: usize,
+    pub max_heap: Option<usize>,
     pub init_gc: f64,
     pub slow_growth_factor: f64,
-    pub fast_growth_factor: f64
+    pub fast_growth_factor: f64,
+    // Part of the heap that can be lost to holes between live objects before
+    // the mark-sweep collector compacts the heap.
+    pub compact_threshold: f64,
+    // Size of the nursery of the generational collector.
+    pub nursery_size: usize
 }
 
 impl GcOpts {
     pub fn default() -> GcOpts {
         GcOpts {
+            strategy: GcStrategy::Copying,
             initial_heap: 16 * 1024 * 1024, // 16M
+            max_heap: None,
             init_gc: 0.95,
             slow_growth_factor: 1.5,
-            fast_growth_factor: 3.0
+            fast_growth_factor: 3.0,
+            compact_threshold: 0.25,
+            nursery_size: 2 * 1024 * 1024 // 2M
         }
     }
 }
@@ -187,9 +217,12 @@ impl GcMemHeader {
 
 pub struct GcHeap {
     handles: Rc<RootHandles>,
-    heap: RefCell<Copying>,
+    heap: RefCell<Box<Strategy>>,
     scopes: RefCell<Vec<LocalScopeData>>,
-    walker: Box<GcWalker>
+    walker: Box<GcWalker>,
+    out_of_memory: Cell<bool>,
+    generational: bool,
+    pauses: Cell<PauseStats>
 }
 
 impl GcHeap {
@@ -203,31 +236,98 @@ impl GcHeap {
         if opts.init_gc > 1.0 {
             panic!("init_gc must be less than or equal to 1");
         }
+        if opts.max_heap.map_or(false, |max_heap| max_heap < opts.initial_heap) {
+            panic!("max_heap must be at least initial_heap");
+        }
+        if opts.compact_threshold < 0.0 || opts.compact_threshold > 1.0 {
+            panic!("compact_threshold must be between 0 and 1");
+        }
+        if opts.strategy == GcStrategy::Generational && opts.nursery_size < 64 * 1024 {
+            panic!("nursery_size must be at least 64K");
+        }
+        if opts.strategy == GcStrategy::Generational && opts.max_heap.map_or(false, |max_heap| max_heap < opts.initial_heap + opts.nursery_size) {
+            panic!("max_heap must be at least initial_heap plus nursery_size");
+        }
+        
+        let generational = opts.strategy == GcStrategy::Generational;
+        
+        let heap : Box<Strategy> = match opts.strategy {
+            GcStrategy::Copying => Box::new(Copying::new(opts)),
+            GcStrategy::MarkSweep => Box::new(MarkSweep::new(opts)),
+            GcStrategy::Generational => Box::new(Generational::new(opts))
+        };
         
         GcHeap {
             handles: Rc::new(RootHandles::new()),
-            heap: RefCell::new(Copying::new(opts)),
+            heap: RefCell::new(heap),
             scopes: RefCell::new(Vec::new()),
-            walker: walker
+            walker: walker,
+            out_of_memory: Cell::new(false),
+            generational: generational,
+            pauses: Cell::new(PauseStats::new())
         }
     }
     
     unsafe fn alloc_raw(&self, size: usize) -> ptr_t {
-        let mut ptr = self.heap.borrow_mut().alloc_raw(size);
+        let mut ptr = self.alloc_from_heap(size);
         if ptr.is_null() {
-            self.gc();
+            // The heap cannot grow beyond max_heap. Satisfy the allocation
+            // from the reserve and flag the heap as out of memory. The flag
+            // stays set until the run ends, and makes the interpreter
+            // and every property access and definition fail with an out of
+            // memory abort, so native code that allocates in a loop stops
+            // within one iteration. Allocations that can be large go through
+            // try_alloc_array instead, so only small blocks end up here and
+            // the reserve only has to cover a single step.
             
-            ptr = self.heap.borrow_mut().alloc_raw(size);
+            ptr = self.heap.borrow_mut().alloc_reserve(size);
             if ptr.is_null() {
-                panic!("could not allocate memory after GC");
+                panic!("could not allocate {} bytes from the reserve", size);
             }
+            
+            self.out_of_memory.set(true);
         }
         
-        if ptr.is_null() {
-            ptr
-        } else {
-            ptr.offset(size_of::<GcMemHeader>() as isize)
+        ptr.offset(size_of::<GcMemHeader>() as isize)
+    }
+    
+    // Allocates from the heap, running a collection when the heap is full.
+    // Returns null when the heap cannot grow beyond max_heap.
+    unsafe fn alloc_from_heap(&self, size: usize) -> ptr_t {
+        // Collecting on every allocation frees or moves every block that is
+        // not rooted over an allocation, so the heap verification after the
+        // collection catches stale pointers before they cause a crash.
+        
+        if cfg!(feature = "gcstress") {
+            self.gc();
+        }
+        
+        let ptr = self.heap.borrow_mut().alloc_raw(size);
+        if !ptr.is_null() {
+            return ptr;
         }
+        
+        self.gc();
+        
+        self.heap.borrow_mut().alloc_raw(size)
+    }
+    
+    /// Returns whether an allocation failed or had to be satisfied from the
+    /// reserve because the heap reached max_heap.
+    pub fn is_out_of_memory(&self) -> bool {
+        self.out_of_memory.get()
+    }
+    
+    /// Flags the heap as out of memory after an allocation failed.
+    pub fn set_out_of_memory(&self) {
+        self.out_of_memory.set(true);
+    }
+    
+    /// Returns whether the heap was out of memory, and resets the flag.
+    pub fn take_out_of_memory(&self) -> bool {
+        let result = self.out_of_memory.get();
+        self.out_of_memory.set(false);
+        result
     }
     
     pub unsafe fn alloc<T>(&self, ty: u32) -> Ptr<T> {
@@ -296,12 +396,102 @@ impl GcHeap {
         Array::from_ptr(ptr)
     }
     
+    /// Allocates an array that may not fit the heap. Returns None instead of
+    /// using the reserve when the heap cannot grow beyond max_heap, or when
+    /// an earlier allocation already had to use the reserve.
+    pub unsafe fn try_alloc_array<T>(&self, ty: u32, size: usize) -> Option<Array<T>> {
+        let item_size = (size_of::<T>() + size_of::<usize>() - 1) / size_of::<usize>() * size_of::<usize>();
+        
+        let bytes = item_size.checked_mul(size).and_then(|bytes| {
+            bytes.checked_add(size_of::<usize>() + size_of::<GcMemHeader>())
+        });
+        
+        let ptr = match bytes {
+            Some(bytes) if !self.out_of_memory.get() => self.alloc_from_heap(bytes),
+            _ => ptr::null()
+        };
+        
+        if ptr.is_null() {
+            return None;
+        }
+        
+        let ptr = ptr.offset(size_of::<GcMemHeader>() as isize);
+        
+        *GcMemHeader::from_ptr(ptr) = GcMemHeader::new(ty, item_size, true);
+        *transmute::<_, *mut usize>(ptr) = size;
+        
+        Some(Array::from_ptr(ptr))
+    }
+    
     pub fn gc(&self) {
+        {
+            let mut handles = self.handles.data.borrow_mut();
+            let scopes = self.scopes.borrow();
+            let walkers = self.create_root_walkers(&mut *handles, &*scopes);
+            
+            let start = time::precise_time_ns();
+            
+            self.heap.borrow_mut().gc(walkers, &*self.walker);
+            
+            let mut pauses = self.pauses.get();
+            pauses.add(time::precise_time_ns() - start);
+            self.pauses.set(pauses);
+        }
+        
+        self.walker.drop_finalized();
+        
+        if cfg!(feature = "gcstress") {
+            self.verify();
+        }
+    }
+    
+    // Verifies the heap after a collection. The walker must still match the
+    // layout of the GC types, and every root and every pointer in a live
+    // block must point to a live block with a valid header. Blocks are
+    // verified before they are traced, so a stale pointer panics here
+    // instead of being followed.
+    fn verify(&self) {
+        self.walker.validate();
+        
+        let heap = self.heap.borrow();
+        
+        let mut roots = Vec::new();
+        self.walk_roots(&mut |ptr| roots.push(ptr));
+        
+        for root in roots {
+            unsafe { self.verify_pointer(&**heap, ptr::null(), root) };
+        }
+        
+        self.trace(&mut |ptr| {
+            unsafe {
+                stats::children(ptr, &*self.walker, &mut |child| self.verify_pointer(&**heap, ptr, child));
+            }
+        });
+    }
+    
+    unsafe fn verify_pointer(&self, heap: &Strategy, from: ptr_t, ptr: ptr_t) {
+        let location = if from.is_null() {
+            "a root".to_string()
+        } else {
+            format!("block {:?} of type {}", from, self.walker.type_name(GcMemHeader::from_ptr(from).get_type_id()))
+        };
+        
+        if !heap.verify_block(ptr) {
+            panic!("heap verification failed: {:?} in {} does not point to a live block", ptr, location);
+        }
+        
+        let ty = GcMemHeader::from_ptr(ptr).get_type_id();
+        
+        if ty == 0 || self.walker.type_name(ty) == "unknown" {
+            panic!("heap verification failed: block {:?} referenced from {} has invalid GC type {}", ptr, location, ty);
+        }
+    }
+    
+    fn create_root_walkers(&self, handles: &mut RootHandlesData, scopes: &[LocalScopeData]) -> Vec<Box<GcRootWalker>> {
         let mut walkers = self.walker.create_root_walkers();
         
         // Add the root handles walker if there are root handles.
         
-        let mut handles = self.handles.data.borrow_mut();
         if handles.ptrs.len() != handles.free.len() {
             let ptr = (*handles.ptrs).as_mut_ptr();
             let end = unsafe { ptr.offset(handles.ptrs.len() as isize) };
@@ -314,17 +504,116 @@ impl GcHeap {
         
         // Add the local scopes walker if there are any.
         
-        let scopes = self.scopes.borrow();
         if scopes.len() > 0 {
             walkers.push(Box::new(LocalScopesWalker {
-                scopes: unsafe { transmute::<&[LocalScopeData], *const [LocalScopeData]>(&**scopes) },
+                scopes: unsafe { transmute::<&[LocalScopeData], *const [LocalScopeData]>(scopes) },
                 scope: 0,
                 vec: 0,
                 index: 0
             }));
         }
         
-        self.heap.borrow_mut().gc(walkers, &*self.walker);
+        walkers
+    }
+    
+    /// Gets statistics of the collections and the live objects of the heap.
+    /// The live objects are found by tracing the heap from the roots, so this
+    /// takes time proportional to the size of the live heap.
+    pub fn stats(&self) -> HeapStats {
+        let mut live = vec![(0, 0); 0x80];
+        
+        self.trace(&mut |ptr| {
+            let ty = unsafe { GcMemHeader::from_ptr(ptr).get_type_id() } as usize;
+            live[ty].0 += 1;
+            live[ty].1 += unsafe { stats::block_size(ptr) };
+        });
+        
+        let mut types = Vec::new();
+        
+        for (ty, &(count, bytes)) in live.iter().enumerate() {
+            if count > 0 {
+                types.push(GcTypeStats {
+                    ty: ty as u32,
+                    name: self.walker.type_name(ty as u32),
+                    count: count,
+                    bytes: bytes
+                });
+            }
+        }
+        
+        let pauses = self.pauses.get();
+        
+        HeapStats {
+            collections: pauses.collections,
+            total_pause_ns: pauses.total,
+            max_pause_ns: pauses.max,
+            last_pause_ns: pauses.last,
+            mem_allocated: self.mem_allocated(),
+            mem_used: self.mem_used(),
+            types: types
+        }
+    }
+    
+    // Calls the callback for every block that is reachable from the roots.
+    fn trace(&self, callback: &mut FnMut(ptr_t)) {
+        let mut handles = self.handles.data.borrow_mut();
+        let scopes = self.scopes.borrow();
+        let walkers = self.create_root_walkers(&mut *handles, &*scopes);
+        
+        unsafe { stats::trace(walkers, &*self.walker, callback) };
+    }
+    
+    /// Calls the callback with the block every root points to. The heap must
+    /// not be used from the callback.
+    pub fn walk_roots(&self, callback: &mut FnMut(ptr_t)) {
+        let mut handles = self.handles.data.borrow_mut();
+        let scopes = self.scopes.borrow();
+        let walkers = self.create_root_walkers(&mut *handles, &*scopes);
+        
+        for mut walker in walkers {
+            loop {
+                let ptr = unsafe { walker.next() };
+                if ptr.is_null() {
+                    break;
+                }
+                
+                let target = unsafe { *ptr };
+                if !target.is_null() {
+                    callback(target);
+                }
+            }
+        }
+    }
+    
+    /// Calls the callback with every pointer stored in the block, as reported
+    /// by the GC walker.
+    pub unsafe fn walk_children(&self, ptr: ptr_t, callback: &mut FnMut(ptr_t)) {
+        stats::children(ptr, &*self.walker, callback);
+    }
+    
+    /// Gets the GC type of the block.
+    pub unsafe fn type_id(&self, ptr: ptr_t) -> u32 {
+        GcMemHeader::from_ptr(ptr).get_type_id()
+    }
+    
+    /// Gets the name of a GC type.
+    pub fn type_name(&self, ty: u32) -> &'static str {
+        self.walker.type_name(ty)
+    }
+    
+    /// Gets the size of the block, including its GC header.
+    pub unsafe fn block_size(&self, ptr: ptr_t) -> usize {
+        stats::block_size(ptr)
+    }
+    
+    /// Must be called after a pointer is stored into an existing block, with
+    /// the pointer to that block. Stores made before anything else is allocated
+    /// after the block do not need one, because no collection can have run.
+    #[inline(always)]
+    pub fn write_barrier(&self, ptr: ptr_t) {
+        if self.generational {
+            self.heap.borrow_mut().write_barrier(ptr);
+        }
     }
     
     pub fn mem_allocated(&self) -> usize {
@@ -448,6 +737,18 @@ pub trait GcWalker {
     fn finalize(&self, ty: u32, ptr: ptr_t) -> GcFinalize;
     
     fn create_root_walkers(&self) -> Vec<Box<GcRootWalker>>;
+    
+    // Drops the values taken out of blocks by finalize. Dropping these can
+    // release root handles, so this is called after the collection, once
+    // the heap and the root handles aren't borrowed anymore.
+    fn drop_finalized(&self);
+    
+    // Gets the name of a GC type for reporting, or "unknown" for a type the
+    // walker does not know.
+    fn type_name(&self, ty: u32) -> &'static str;
+    
+    // Panics when the walker does not match the layout of the GC types.
+    fn validate(&self);
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
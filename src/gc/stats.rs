@@ -0,0 +1,165 @@
+use gc::{GcRootWalker, GcWalker, GcWalk, GcMemHeader, ptr_t};
+use std::collections::HashSet;
+use std::mem::{size_of, transmute};
+
+/// Statistics of a heap as returned by `GcHeap::stats`.
+#[derive(Clone, Debug)]
+pub struct HeapStats {
+    /// Number of collections since the heap was created.
+    pub collections: u64,
+    /// Time spent in all collections, in nanoseconds.
+    pub total_pause_ns: u64,
+    /// Time spent in the longest collection, in nanoseconds.
+    pub max_pause_ns: u64,
+    /// Time spent in the last collection, in nanoseconds.
+    pub last_pause_ns: u64,
+    /// Memory reserved by the heap.
+    pub mem_allocated: usize,
+    /// Memory taken by objects, including garbage that has not been
+    /// collected yet.
+    pub mem_used: usize,
+    /// The live objects per GC type, ordered by type id. Types without live
+    /// objects are left out.
+    pub types: Vec<GcTypeStats>
+}
+
+impl HeapStats {
+    /// Gets the statistics of the GC type with the name.
+    pub fn get(&self, name: &str) -> Option<&GcTypeStats> {
+        self.types.iter().find(|stats| stats.name == name)
+    }
+    
+    /// Gets the number of live objects of all types.
+    pub fn live_count(&self) -> usize {
+        self.types.iter().fold(0, |count, stats| count + stats.count)
+    }
+    
+    /// Gets the bytes taken by live objects of all types.
+    pub fn live_bytes(&self) -> usize {
+        self.types.iter().fold(0, |bytes, stats| bytes + stats.bytes)
+    }
+}
+
+/// The live objects of a single GC type.
+#[derive(Clone, Debug)]
+pub struct GcTypeStats {
+    pub ty: u32,
+    pub name: &'static str,
+    pub count: usize,
+    /// Bytes taken by the objects, including their GC header but not the
+    /// header of the collector.
+    pub bytes: usize
+}
+
+#[derive(Copy, Clone)]
+pub struct PauseStats {
+    pub collections: u64,
+    pub total: u64,
+    pub max: u64,
+    pub last: u64
+}
+
+impl PauseStats {
+    pub fn new() -> PauseStats {
+        PauseStats {
+            collections: 0,
+            total: 0,
+            max: 0,
+            last: 0
+        }
+    }
+    
+    pub fn add(&mut self, pause: u64) {
+        self.collections += 1;
+        self.total += pause;
+        self.last = pause;
+        if pause > self.max {
+            self.max = pause;
+        }
+    }
+}
+
+pub unsafe fn block_size(ptr: ptr_t) -> usize {
+    let header = GcMemHeader::from_ptr(ptr);
+    
+    let size = if header.is_array() {
+        size_of::<usize>() + *transmute::<_, *const usize>(ptr) * header.get_size()
+    } else {
+        header.get_size()
+    };
+    
+    size + size_of::<GcMemHeader>()
+}
+
+// Calls the callback for every block that is reachable from the roots. This
+// does not move or mark any block, so it can run outside of a collection.
+pub unsafe fn trace(walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker, callback: &mut FnMut(ptr_t)) {
+    let mut seen = HashSet::new();
+    let mut pending = Vec::new();
+    
+    for mut root in walkers {
+        loop {
+            let ptr = root.next();
+            if ptr.is_null() {
+                break;
+            }
+            
+            if !(*ptr).is_null() && seen.insert(*ptr) {
+                pending.push(*ptr);
+            }
+        }
+    }
+    
+    while let Some(ptr) = pending.pop() {
+        callback(ptr);
+        
+        children(ptr, walker, &mut |child| {
+            if seen.insert(child) {
+                pending.push(child);
+            }
+        });
+    }
+}
+
+// Calls the callback for every pointer in the block.
+pub unsafe fn children(ptr: ptr_t, walker: &GcWalker, callback: &mut FnMut(ptr_t)) {
+    let header = GcMemHeader::from_ptr(ptr);
+    let ty = header.get_type_id();
+    let size = header.get_size();
+    let ptrs = size / size_of::<usize>();
+    
+    if header.is_array() {
+        let count = *transmute::<_, *const usize>(ptr);
+        
+        let mut child = ptr.offset(size_of::<usize>() as isize);
+        let end = child.offset((count * size) as isize);
+        
+        while child < end {
+            if !block_children(child, ty, ptrs, walker, callback) {
+                break;
+            }
+            
+            child = child.offset(size as isize);
+        }
+    } else {
+        block_children(ptr, ty, ptrs, walker, callback);
+    }
+}
+
+unsafe fn block_children(ptr: ptr_t, ty: u32, ptrs: usize, walker: &GcWalker, callback: &mut FnMut(ptr_t)) -> bool {
+    for i in 0..ptrs {
+        match walker.walk(ty, ptr, i as u32) {
+            GcWalk::End => return true,
+            GcWalk::EndArray => return false,
+            GcWalk::Skip => {},
+            GcWalk::Pointer => {
+                let child = *(ptr as *const ptr_t).offset(i as isize);
+                if !child.is_null() {
+                    callback(child);
+                }
+            }
+        }
+    }
+    
+    true
+}
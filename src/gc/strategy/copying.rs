@@ -1,29 +1,33 @@
 extern crate libc;
 extern crate time;
 
-use gc::strategy::Strategy;
+use gc::strategy::{Strategy, nice_size};
 use gc::os::Memory;
 use gc::{GcRootWalker, GcOpts, GcMemHeader, GcWalker, GcWalk, GcFinalize, ptr_t};
+use gc::stats::block_size;
 use std::ptr;
 use std::mem::{size_of, transmute, swap};
-use std::cmp::max;
+use std::cmp::{max, min};
+use std::usize;
 
 const PAGE_SIZE : usize = 4 * 1024;
 
-struct Header {
-    forward: ptr_t,
-    size: usize
+// The generational strategy uses the same header for blocks in its nursery,
+// so they can be forwarded into the copying heap.
+pub struct Header {
+    pub forward: ptr_t,
+    pub size: usize
 }
 
 impl Header {
-    fn new(size: usize) -> Header {
+    pub fn new(size: usize) -> Header {
         Header {
             forward: ptr::null(),
             size: size
         }
     }
     
-    unsafe fn from_ptr<'a>(ptr: ptr_t) -> &'a mut Header {
+    pub unsafe fn from_ptr<'a>(ptr: ptr_t) -> &'a mut Header {
         transmute(ptr.offset(-((size_of::<Header>() + size_of::<GcMemHeader>()) as isize)))
     }
     
@@ -31,7 +35,7 @@ impl Header {
         ptr.offset(-((size_of::<Header>() + size_of::<GcMemHeader>()) as isize))
     }
     
-    unsafe fn offset_to_user(ptr: ptr_t) -> ptr_t {
+    pub unsafe fn offset_to_user(ptr: ptr_t) -> ptr_t {
         ptr.offset((size_of::<Header>() + size_of::<GcMemHeader>()) as isize)
     }
 }
@@ -43,10 +47,10 @@ struct Block {
 }
 
 impl Block {
-    unsafe fn alloc(&mut self, size: usize) -> ptr_t {
+    unsafe fn alloc(&mut self, size: usize, limit: usize) -> ptr_t {
         let size = size + size_of::<Header>();
         
-        if self.offset + size > self.high_mark {
+        if self.offset + size > limit {
             return ptr::null_mut();
         }
         
@@ -65,28 +69,61 @@ pub struct Copying {
     from: Block,
     to: Memory,
     last_used: f64,
-    last_failed: usize
+    last_failed: usize,
+    // Room below max_heap that is kept free for blocks that are copied in
+    // from outside of the from space, i.e. the nursery of the generational
+    // collector.
+    headroom: usize
 }
 
 impl Copying {
     pub fn new(opts: GcOpts) -> Copying {
         let memory = Memory::alloc(opts.initial_heap).unwrap();
-        let high_mark = (opts.initial_heap * (opts.init_gc * 100.0) as usize) / 100;
         
-        Copying {
+        let mut result = Copying {
             opts: opts,
             from: Block {
                 memory: memory,
                 offset: 0,
-                high_mark: high_mark
+                high_mark: 0
             },
             to: Memory::empty(),
             last_used: 0.0,
-            last_failed: 0
+            last_failed: 0,
+            headroom: 0
+        };
+        
+        result.from.high_mark = result.high_mark();
+        
+        result
+    }
+    
+    // Keeps room for size bytes of blocks from outside of the from space
+    // below max_heap, so a collection that copies them never needs a to
+    // space that is larger than max_heap.
+    pub fn reserve_headroom(&mut self, size: usize) {
+        self.headroom = size;
+        self.from.high_mark = self.high_mark();
+    }
+    
+    // Gets the end of the memory in the from space that can be allocated,
+    // including the reserve.
+    fn limit(&self) -> usize {
+        let size = self.from.memory.size();
+        
+        match self.opts.max_heap {
+            Some(max_heap) => min(size, max_heap.saturating_sub(self.headroom)),
+            None => size
         }
     }
     
-    unsafe fn copy(&mut self, mut walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker) {
+    fn high_mark(&self) -> usize {
+        (self.limit() * (self.opts.init_gc * 100.0) as usize) / 100
+    }
+    
+    // Copies the live blocks into the to space. Extra is the size of blocks
+    // outside of the from space that may be copied too.
+    pub unsafe fn copy(&mut self, mut walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker, extra: usize) {
         // Calculate the new size of the heap. We use the fill factor of the previous
         // run as a basis and ensure that we have at least enough room to accept the
         // allocation that failed last (were we not able to reclaim any memory).
@@ -98,7 +135,7 @@ impl Copying {
         // We need at least enough room to fit the last allocation. If we're not
         // able to reclaim any memory, the current offset plus the last allocation
         // need to fit.
-        let mut target_size = max(self.from.memory.size(), self.from.offset + self.last_failed);
+        let mut target_size = max(self.from.memory.size(), self.from.offset + extra + self.last_failed);
 
         tracegc!("last offset {} last failed {} target size {}", self.from.offset, self.last_failed, target_size);
         
@@ -128,6 +165,16 @@ impl Copying {
         
         target_size = (target_size + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
         
+        // We don't grow beyond the maximum heap size. Allocations, including
+        // those from the reserve, stay below the limit minus the headroom, so
+        // everything in the from space plus the extra blocks still fits.
+        if let Some(max_heap) = self.opts.max_heap {
+            target_size = min(target_size, max_heap);
+            tracegc!("max heap {} target_size {}", max_heap, target_size);
+        }
+        
+        assert!(self.from.offset + extra <= target_size, "to space cannot fit the from space");
+        
         // Ensure that the target heap is large enough.
         
         if self.to.size() < target_size {
@@ -136,9 +183,7 @@ impl Copying {
             self.to = Memory::alloc(target_size).unwrap();
         }
         
-        let mut forwarder = Forwarder {
-            target: self.to.ptr()
-        };
+        let mut forwarder = Forwarder::new(self.to.ptr());
         
         // Walk all GC roots.
         
@@ -157,88 +202,13 @@ impl Copying {
         
         // Walk the to space.
         
-        let mut ptr = Header::offset_to_user(self.to.ptr());
-        
-        while ptr < forwarder.target {
-            let header = Header::from_ptr(ptr);
-            let gc_header = GcMemHeader::from_ptr(ptr);
-            let ty = gc_header.get_type_id();
-            let size = gc_header.get_size();
-            let ptrs = size / size_of::<usize>();
-            
-            if gc_header.is_array() {
-                let count = *transmute::<_, *const usize>(ptr);
-
-                let mut child = ptr.offset(size_of::<usize>() as isize);
-                let end = child.offset((count * size) as isize);
-                
-                let mut index = 0;
-                
-                while child < end {
-                    tracegc!("processing index {}", index);
-                    index += 1;
-                    
-                    // Stop processing arrays when we receive a GcWalk::EndArray. This is
-                    // to signlify that none of the elements of the array will ever
-                    // contain a pointer.
-                    if !process_block(child, ty, ptrs, &mut forwarder, walker) {
-                        break;
-                    }
-                    
-                    child = child.offset(size as isize);
-                }
-                
-            } else {
-                process_block(ptr, ty, ptrs, &mut forwarder, walker);
-            }
-            
-            ptr = ptr.offset(header.size as isize);
-        }
+        scan(self.to.ptr(), &mut forwarder, walker);
         
         // Walk the from space and find all not-forwarded blocks to
         // allow them to be finalized.
         
-        let mut ptr = Header::offset_to_user(self.from.memory.ptr());
-        let end = ptr.offset(self.from.offset as isize);
-        
-        while ptr < end {
-            let header = Header::from_ptr(ptr);
-            
-            if header.forward.is_null() {
-                let gc_header = GcMemHeader::from_ptr(ptr);
-                let ty = gc_header.get_type_id();
-                let size = gc_header.get_size();
-                
-                if gc_header.is_array() {
-                    let count = *transmute::<_, *const usize>(ptr);
-    
-                    let mut child = ptr.offset(size_of::<usize>() as isize);
-                    let end = child.offset((count * size) as isize);
-                    
-                    let mut index = 0;
-                    
-                    while child < end {
-                        tracegc!("finalizing index {}", index);
-                        index += 1;
-                        
-                        // Stop processing arrays when we receive a GcFinalize::NotFinalizable.
-                        // This signifies that none of the array elements will ever
-                        // be finalizable.
-                        match walker.finalize(ty, child) {
-                            GcFinalize::NotFinalizable => break,
-                            GcFinalize::Finalized => {}
-                        }
-                        
-                        child = child.offset(size as isize);
-                    }
-                    
-                } else {
-                    walker.finalize(ty, ptr);
-                }
-            }
-            
-            ptr = ptr.offset(header.size as isize);
-        }
+        let from = self.from.memory.ptr();
+        finalize(from, from.offset(self.from.offset as isize), walker);
         
         // Invalidate the from space in debug mode.
         
@@ -256,7 +226,7 @@ impl Copying {
         
         self.from.offset = forwarder.target as usize - self.to.ptr() as usize;
         swap(&mut self.from.memory, &mut self.to);
-        self.from.high_mark = (self.from.memory.size() * (self.opts.init_gc * 100.0) as usize) / 100;
+        self.from.high_mark = self.high_mark();
         
         // Calculate the current fill rate.
         
@@ -265,12 +235,57 @@ impl Copying {
     }
 }
 
-struct Forwarder {
-    target: ptr_t
+impl Copying {
+    // Gets the room left in the from space before the next collection.
+    pub fn free(&self) -> usize {
+        self.from.high_mark.saturating_sub(self.from.offset)
+    }
+    
+    // Creates a forwarder that moves the blocks between start and end to the
+    // end of the from space.
+    pub unsafe fn promoter(&self, start: ptr_t, end: ptr_t) -> Forwarder {
+        Forwarder {
+            target: self.from.memory.ptr().offset(self.from.offset as isize),
+            start: start,
+            end: end
+        }
+    }
+    
+    pub unsafe fn end_promotion(&mut self, forwarder: &Forwarder) {
+        self.from.offset = forwarder.target as usize - self.from.memory.ptr() as usize;
+    }
+}
+
+pub struct Forwarder {
+    target: ptr_t,
+    // Only blocks in this range are forwarded. Pointers to other blocks are
+    // left alone.
+    start: ptr_t,
+    end: ptr_t
 }
 
 impl Forwarder {
-    unsafe fn forward(&mut self, ptr: ptr_t) -> ptr_t {
+    fn new(target: ptr_t) -> Forwarder {
+        Forwarder {
+            target: target,
+            start: ptr::null(),
+            end: usize::MAX as ptr_t
+        }
+    }
+    
+    pub fn target(&self) -> ptr_t {
+        self.target
+    }
+    
+    pub fn in_range(&self, ptr: ptr_t) -> bool {
+        ptr >= self.start && ptr < self.end
+    }
+    
+    pub unsafe fn forward(&mut self, ptr: ptr_t) -> ptr_t {
+        if !self.in_range(ptr) {
+            return ptr;
+        }
+        
         let header = Header::from_ptr(ptr);
         
         if header.forward.is_null() {
@@ -291,6 +306,54 @@ impl Forwarder {
     }
 }
 
+// Forwards the children of all blocks from start up to the target of the
+// forwarder, including the blocks that are forwarded while doing so.
+pub unsafe fn scan(start: ptr_t, forwarder: &mut Forwarder, walker: &GcWalker) {
+    let mut ptr = Header::offset_to_user(start);
+    
+    while ptr < forwarder.target {
+        let header = Header::from_ptr(ptr);
+        
+        process(ptr, forwarder, walker);
+        
+        ptr = ptr.offset(header.size as isize);
+    }
+}
+
+// Forwards the children of a single block.
+pub unsafe fn process(ptr: ptr_t, forwarder: &mut Forwarder, walker: &GcWalker) {
+    let gc_header = GcMemHeader::from_ptr(ptr);
+    let ty = gc_header.get_type_id();
+    let size = gc_header.get_size();
+    let ptrs = size / size_of::<usize>();
+    
+    if gc_header.is_array() {
+        let count = *transmute::<_, *const usize>(ptr);
+        
+        let mut child = ptr.offset(size_of::<usize>() as isize);
+        let end = child.offset((count * size) as isize);
+        
+        let mut index = 0;
+        
+        while child < end {
+            tracegc!("processing index {}", index);
+            index += 1;
+            
+            // Stop processing arrays when we receive a GcWalk::EndArray. This is
+            // to signlify that none of the elements of the array will ever
+            // contain a pointer.
+            if !process_block(child, ty, ptrs, forwarder, walker) {
+                break;
+            }
+            
+            child = child.offset(size as isize);
+        }
+        
+    } else {
+        process_block(ptr, ty, ptrs, forwarder, walker);
+    }
+}
+
 unsafe fn process_block(ptr: ptr_t, ty: u32, ptrs: usize, forwarder: &mut Forwarder, walker: &GcWalker) -> bool {
     for i in 0..ptrs {
         match walker.walk(ty, ptr, i as u32) {
@@ -311,12 +374,58 @@ unsafe fn process_block(ptr: ptr_t, ty: u32, ptrs: usize, forwarder: &mut Forwar
     true
 }
 
+// Finalizes the blocks from start up to end that were not forwarded.
+pub unsafe fn finalize(start: ptr_t, end: ptr_t, walker: &GcWalker) {
+    let mut ptr = Header::offset_to_user(start);
+    let end = Header::offset_to_user(end);
+    
+    while ptr < end {
+        let header = Header::from_ptr(ptr);
+        
+        if header.forward.is_null() {
+            let gc_header = GcMemHeader::from_ptr(ptr);
+            let ty = gc_header.get_type_id();
+            let size = gc_header.get_size();
+            
+            if gc_header.is_array() {
+                let count = *transmute::<_, *const usize>(ptr);
+                
+                let mut child = ptr.offset(size_of::<usize>() as isize);
+                let end = child.offset((count * size) as isize);
+                
+                let mut index = 0;
+                
+                while child < end {
+                    tracegc!("finalizing index {}", index);
+                    index += 1;
+                    
+                    // Stop processing arrays when we receive a GcFinalize::NotFinalizable.
+                    // This signifies that none of the array elements will ever
+                    // be finalizable.
+                    match walker.finalize(ty, child) {
+                        GcFinalize::NotFinalizable => break,
+                        GcFinalize::Finalized => {}
+                    }
+                    
+                    child = child.offset(size as isize);
+                }
+                
+            } else {
+                walker.finalize(ty, ptr);
+            }
+        }
+        
+        ptr = ptr.offset(header.size as isize);
+    }
+}
+
 impl Strategy for Copying {
     unsafe fn alloc_raw(&mut self, size: usize) -> ptr_t {
         // Round the size to the next pointer.
         let size = (size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
         
-        let result = self.from.alloc(size);
+        let high_mark = self.from.high_mark;
+        let result = self.from.alloc(size, high_mark);
         
         if result.is_null() {
             self.last_failed = size;
@@ -327,6 +436,38 @@ impl Strategy for Copying {
         result
     }
     
+    unsafe fn alloc_reserve(&mut self, size: usize) -> ptr_t {
+        // The reserve is the memory between the high mark and the end
+        // of the from space, or max_heap minus the headroom.
+        let size = (size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+        
+        let end = self.limit();
+        let result = self.from.alloc(size, end);
+        
+        if !result.is_null() {
+            ptr::write_bytes(transmute::<_, *mut u8>(result), 0, size);
+        }
+        
+        result
+    }
+    
+    unsafe fn verify_block(&self, ptr: ptr_t) -> bool {
+        let start = self.from.memory.ptr();
+        let end = start.offset(self.from.offset as isize);
+        let block = Header::offset_from_user(ptr);
+        
+        if block < start || ptr >= end || (block as usize - start as usize) % size_of::<usize>() != 0 {
+            return false;
+        }
+        
+        let header = Header::from_ptr(ptr);
+        let size = (block_size(ptr) + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+        
+        header.forward.is_null() &&
+            header.size == size + size_of::<Header>() &&
+            block.offset(header.size as isize) <= end
+    }
+    
     fn mem_allocated(&self) -> usize {
         self.from.memory.size() + self.to.size()
     }
@@ -341,7 +482,7 @@ impl Strategy for Copying {
         tracegc!("=== GC === start");
         
         unsafe {
-            self.copy(walkers, walker);
+            self.copy(walkers, walker, 0);
         }
         
         let elapsed = (time::precise_time_ns() - start) / 1_000_000;
@@ -349,22 +490,3 @@ impl Strategy for Copying {
         tracegc!("=== GC === allocated {} ({}) used {} ms {}", nice_size(self.from.memory.size()), nice_size(self.mem_allocated()), nice_size(self.mem_used()), elapsed);
     }
 }
-
-fn nice_size(size: usize) -> String {
-    if size < 1024 {
-        format!("{} B", size)
-    } else {
-        let mut size = size as f64 / 1024.0;
-        if size < 1024.0 {
-            format!("{:.1} KB", size)
-        } else {
-            size /= 1024.0;
-            if size < 1024.0 {
-                format!("{:.1} MB", size)
-            } else {
-                size /= 1024.0;
-                format!("{:.1} GB", size)
-            }
-        }
-    }
-}
@@ -0,0 +1,242 @@
+extern crate time;
+
+use gc::strategy::{Strategy, nice_size};
+use gc::strategy::copying::{Copying, Header, scan, process, finalize};
+use gc::os::Memory;
+use gc::{GcRootWalker, GcOpts, GcWalker, GcMemHeader, ptr_t};
+use std::ptr;
+use std::mem::{size_of, transmute};
+
+// Marks an old block that is in the remembered set. Old blocks only use the
+// forward field of their header while a major collection runs.
+const REMEMBERED : usize = 1;
+
+/// Generational collector. New blocks are allocated in a nursery. A minor
+/// collection copies the live blocks of the nursery into the old generation,
+/// which is a copying heap that is only collected by major collections.
+///
+/// Minor collections only scan the roots, the blocks the roots point to and
+/// the remembered set: the old blocks that had a pointer stored into them
+/// since the last collection, as recorded by the write barrier. Blocks that
+/// are only referenced through a root, like the value of a Root, therefore
+/// do not need a write barrier.
+pub struct Generational {
+    old: Copying,
+    nursery: Memory,
+    offset: usize,
+    // Blocks at least this large are allocated in the old generation.
+    large: usize,
+    remembered: Vec<ptr_t>,
+    major: bool
+}
+
+impl Generational {
+    pub fn new(opts: GcOpts) -> Generational {
+        let nursery = Memory::alloc(opts.nursery_size).unwrap();
+        let large = opts.nursery_size / 8;
+        
+        // A major collection copies the nursery into the old generation.
+        
+        let mut old = Copying::new(opts);
+        old.reserve_headroom(nursery.size());
+        
+        Generational {
+            old: old,
+            nursery: nursery,
+            offset: 0,
+            large: large,
+            remembered: Vec::new(),
+            major: false
+        }
+    }
+    
+    fn in_nursery(&self, ptr: ptr_t) -> bool {
+        unsafe {
+            let start = self.nursery.ptr();
+            ptr >= start && ptr < start.offset(self.offset as isize)
+        }
+    }
+    
+    unsafe fn remember(&mut self, ptr: ptr_t) {
+        let header = Header::from_ptr(ptr);
+        
+        if header.forward.is_null() {
+            header.forward = REMEMBERED as ptr_t;
+            self.remembered.push(ptr);
+        }
+    }
+    
+    unsafe fn forget(&mut self) {
+        for &ptr in &self.remembered {
+            Header::from_ptr(ptr).forward = ptr::null();
+        }
+        
+        self.remembered.clear();
+    }
+    
+    // Blocks allocated in the old generation can be written to before the
+    // next collection without a write barrier, so they are remembered.
+    unsafe fn alloc_old(&mut self, size: usize, reserve: bool) -> ptr_t {
+        let result = if reserve {
+            self.old.alloc_reserve(size)
+        } else {
+            self.old.alloc_raw(size)
+        };
+        
+        if result.is_null() {
+            self.major = true;
+        } else {
+            self.remember(result.offset(size_of::<GcMemHeader>() as isize));
+        }
+        
+        result
+    }
+    
+    unsafe fn minor(&mut self, mut walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker) {
+        let start = self.nursery.ptr();
+        let end = start.offset(self.offset as isize);
+        
+        let mut forwarder = self.old.promoter(start, end);
+        let promoted = forwarder.target();
+        
+        // Forward the roots into the nursery and remember the old blocks the
+        // roots point to.
+        
+        for walker in &mut walkers {
+            loop {
+                let ptr = walker.next();
+                if ptr.is_null() {
+                    break;
+                }
+                
+                if forwarder.in_range(*ptr) {
+                    *ptr = forwarder.forward(*ptr);
+                } else if !(*ptr).is_null() {
+                    self.remember(*ptr);
+                }
+            }
+        }
+        
+        // Forward the pointers of the remembered blocks and the blocks that
+        // were promoted.
+        
+        for &ptr in &self.remembered {
+            process(ptr, &mut forwarder, walker);
+        }
+        
+        scan(promoted, &mut forwarder, walker);
+        
+        self.forget();
+        self.old.end_promotion(&forwarder);
+        
+        self.clear_nursery(walker);
+    }
+    
+    unsafe fn major(&mut self, walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker) {
+        // The major collection uses the forward field of the old blocks.
+        
+        self.forget();
+        
+        self.old.copy(walkers, walker, self.offset);
+        
+        self.clear_nursery(walker);
+        
+        self.major = false;
+    }
+    
+    unsafe fn clear_nursery(&mut self, walker: &GcWalker) {
+        let start = self.nursery.ptr();
+        finalize(start, start.offset(self.offset as isize), walker);
+        
+        // Invalidate the nursery in debug mode.
+        
+        if cfg!(not(ndebug)) {
+            let mut ptr = transmute::<_, *mut u32>(start);
+            let end = ptr.offset((self.offset / size_of::<u32>()) as isize);
+            
+            while ptr < end {
+                *ptr = 0xdeadbeef;
+                ptr = ptr.offset(1);
+            }
+        }
+        
+        self.offset = 0;
+    }
+}
+
+impl Strategy for Generational {
+    unsafe fn alloc_raw(&mut self, size: usize) -> ptr_t {
+        // Round the size to the next pointer.
+        let size = (size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+        
+        if size >= self.large {
+            return self.alloc_old(size, false);
+        }
+        
+        let block_size = size + size_of::<Header>();
+        
+        if self.offset + block_size > self.nursery.size() {
+            return ptr::null();
+        }
+        
+        let memory = self.nursery.ptr().offset(self.offset as isize);
+        
+        (*(memory as *mut Header)) = Header::new(block_size);
+        
+        self.offset += block_size;
+        
+        let result = memory.offset(size_of::<Header>() as isize);
+        
+        ptr::write_bytes(transmute::<_, *mut u8>(result), 0, size);
+        
+        result
+    }
+    
+    unsafe fn alloc_reserve(&mut self, size: usize) -> ptr_t {
+        self.alloc_old(size, true)
+    }
+    
+    unsafe fn verify_block(&self, ptr: ptr_t) -> bool {
+        // After a collection the nursery is empty, so every live block must
+        // be in the old generation.
+        
+        self.offset == 0 && self.old.verify_block(ptr)
+    }
+    
+    fn mem_allocated(&self) -> usize {
+        self.old.mem_allocated() + self.nursery.size()
+    }
+    
+    fn mem_used(&self) -> usize {
+        self.old.mem_used() + self.offset
+    }
+    
+    fn write_barrier(&mut self, ptr: ptr_t) {
+        if !self.in_nursery(ptr) {
+            unsafe { self.remember(ptr) };
+        }
+    }
+    
+    fn gc(&mut self, walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker) {
+        let start = time::precise_time_ns();
+        
+        // A minor collection needs room in the old generation for everything
+        // in the nursery.
+        
+        let major = self.major || self.old.free() < self.offset;
+        
+        tracegc!("=== GC === start {}", if major { "major" } else { "minor" });
+        
+        unsafe {
+            if major {
+                self.major(walkers, walker);
+            } else {
+                self.minor(walkers, walker);
+            }
+        }
+        
+        let elapsed = (time::precise_time_ns() - start) / 1_000_000;
+        
+        tracegc!("=== GC === allocated {} used {} ms {}", nice_size(self.mem_allocated()), nice_size(self.mem_used()), elapsed);
+    }
+}
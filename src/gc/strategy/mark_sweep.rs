@@ -0,0 +1,613 @@
+extern crate time;
+
+use gc::strategy::{Strategy, nice_size};
+use gc::os::Memory;
+use gc::{GcRootWalker, GcOpts, GcMemHeader, GcWalker, GcWalk, GcFinalize, ptr_t};
+use gc::stats::block_size;
+use std::ptr;
+use std::mem::{size_of, transmute};
+use std::cmp::{max, min};
+
+const PAGE_SIZE : usize = 4 * 1024;
+
+// Free blocks up to this number of words are kept in a list per size. Larger
+// blocks are kept in a single list that is searched first fit.
+const SMALL_WORDS : usize = 32;
+
+// Flags stored in the low bits of the size of a block. Sizes are always a
+// multiple of the pointer size, so these bits are free.
+const MARKED : usize = 1;
+const FREE : usize = 2;
+const FLAGS : usize = MARKED | FREE;
+
+struct Header {
+    // The next block of a free list for free blocks, or the new location of
+    // a live block while compacting.
+    link: ptr_t,
+    size: usize
+}
+
+impl Header {
+    unsafe fn from_ptr<'a>(ptr: ptr_t) -> &'a mut Header {
+        transmute(ptr.offset(-((size_of::<Header>() + size_of::<GcMemHeader>()) as isize)))
+    }
+    
+    unsafe fn from_block<'a>(block: ptr_t) -> &'a mut Header {
+        transmute(block)
+    }
+    
+    unsafe fn offset_to_user(block: ptr_t) -> ptr_t {
+        block.offset((size_of::<Header>() + size_of::<GcMemHeader>()) as isize)
+    }
+    
+    fn size(&self) -> usize {
+        self.size & !FLAGS
+    }
+    
+    fn is_marked(&self) -> bool {
+        self.size & MARKED != 0
+    }
+    
+    fn is_free(&self) -> bool {
+        self.size & FREE != 0
+    }
+}
+
+// Free blocks are never handed out when the rest would be smaller than this.
+fn min_block() -> usize {
+    size_of::<Header>() + size_of::<GcMemHeader>() + size_of::<usize>()
+}
+
+/// Mark-sweep collector. Live blocks stay where they are and the space of
+/// dead blocks is reused through free lists. The heap is compacted when too
+/// much of it is lost to holes between live blocks, when an allocation does
+/// not fit any hole, or when the heap has to grow.
+pub struct MarkSweep {
+    opts: GcOpts,
+    memory: Memory,
+    // End of the last block. Memory after it has never been allocated or was
+    // released by a compaction.
+    top: usize,
+    high_mark: usize,
+    small: Vec<ptr_t>,
+    large: ptr_t,
+    // Total size of the blocks in the free lists.
+    free_size: usize,
+    last_failed: usize
+}
+
+impl MarkSweep {
+    pub fn new(opts: GcOpts) -> MarkSweep {
+        let memory = Memory::alloc(opts.initial_heap).unwrap();
+        let high_mark = (opts.initial_heap * (opts.init_gc * 100.0) as usize) / 100;
+        
+        MarkSweep {
+            opts: opts,
+            memory: memory,
+            top: 0,
+            high_mark: high_mark,
+            small: vec![ptr::null(); SMALL_WORDS + 1],
+            large: ptr::null(),
+            free_size: 0,
+            last_failed: 0
+        }
+    }
+    
+    unsafe fn alloc_block(&mut self, size: usize, limit: usize) -> ptr_t {
+        let words = size / size_of::<usize>();
+        
+        // An exact fit from the small free lists.
+        
+        if words <= SMALL_WORDS && !self.small[words].is_null() {
+            let block = self.small[words];
+            self.small[words] = Header::from_block(block).link;
+            
+            return self.take(block, size);
+        }
+        
+        // Allocate from the end of the heap.
+        
+        if self.top + size <= limit {
+            let block = self.memory.ptr().offset(self.top as isize);
+            self.top += size;
+            
+            *Header::from_block(block) = Header {
+                link: ptr::null(),
+                size: size
+            };
+            
+            return block.offset(size_of::<Header>() as isize);
+        }
+        
+        // Split a larger free block.
+        
+        for i in min(words + min_block() / size_of::<usize>(), SMALL_WORDS + 1)..SMALL_WORDS + 1 {
+            let block = self.small[i];
+            
+            if !block.is_null() {
+                self.small[i] = Header::from_block(block).link;
+                
+                return self.take(block, size);
+            }
+        }
+        
+        let mut prev : *mut ptr_t = &mut self.large;
+        
+        while !(*prev).is_null() {
+            let block = *prev;
+            let header = Header::from_block(block);
+            
+            if header.size() == size || header.size() >= size + min_block() {
+                *prev = header.link;
+                
+                return self.take(block, size);
+            }
+            
+            prev = &mut header.link;
+        }
+        
+        ptr::null()
+    }
+    
+    // Takes a block that was removed from a free list, returning the rest to
+    // the free lists when it is large enough.
+    unsafe fn take(&mut self, block: ptr_t, size: usize) -> ptr_t {
+        let header = Header::from_block(block);
+        let block_size = header.size();
+        
+        self.free_size -= block_size;
+        
+        header.link = ptr::null();
+        header.size = block_size;
+        
+        if block_size >= size + min_block() {
+            header.size = size;
+            self.add_free(block.offset(size as isize), block_size - size);
+        }
+        
+        block.offset(size_of::<Header>() as isize)
+    }
+    
+    unsafe fn add_free(&mut self, block: ptr_t, size: usize) {
+        let words = size / size_of::<usize>();
+        
+        self.free_size += size;
+        
+        let list = if words <= SMALL_WORDS {
+            &mut self.small[words]
+        } else {
+            &mut self.large
+        };
+        
+        *Header::from_block(block) = Header {
+            link: *list,
+            size: size | FREE
+        };
+        
+        *list = block;
+    }
+    
+    unsafe fn collect(&mut self, mut walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker) {
+        // Mark all blocks reachable from the roots. The locations of the
+        // roots are kept for when the heap is compacted.
+        
+        let mut roots = Vec::new();
+        let mut stack = Vec::new();
+        
+        for walker in &mut walkers {
+            loop {
+                let ptr = walker.next();
+                if ptr.is_null() {
+                    break;
+                }
+                
+                roots.push(ptr);
+                mark(*ptr, &mut stack);
+            }
+        }
+        
+        while let Some(ptr) = stack.pop() {
+            each_pointer(ptr, walker, |child| mark(*child, &mut stack));
+        }
+        
+        // Finalize the dead blocks and find the size of the live blocks and
+        // of the holes between them.
+        
+        let base = self.memory.ptr();
+        let end = base.offset(self.top as isize);
+        let mut block = base;
+        let mut live = 0;
+        let mut hole : ptr_t = ptr::null();
+        let mut largest = 0;
+        
+        while block < end {
+            let header = Header::from_block(block);
+            
+            if header.is_marked() {
+                live += header.size();
+                
+                if !hole.is_null() {
+                    largest = max(largest, block as usize - hole as usize);
+                    hole = ptr::null();
+                }
+            } else {
+                if !header.is_free() {
+                    finalize(Header::offset_to_user(block), walker);
+                }
+                
+                if hole.is_null() {
+                    hole = block;
+                }
+            }
+            
+            block = block.offset(header.size() as isize);
+        }
+        
+        // A hole at the end is given back to the end of the heap.
+        
+        let top = if hole.is_null() { self.top } else { hole as usize - base as usize };
+        
+        // Calculate the new size of the heap the same way the copying
+        // collector does, based on the live size of this run.
+        
+        let used = (live + self.last_failed) as f64 / self.memory.size() as f64;
+        let mut target_size = max(self.memory.size(), live + self.last_failed);
+        
+        if used > 0.5 {
+            let growth_factor = if used > 0.85 {
+                self.opts.fast_growth_factor
+            } else {
+                self.opts.slow_growth_factor
+            };
+            
+            target_size = (target_size * (growth_factor * 100.0) as usize) / 100;
+        }
+        
+        target_size = (target_size + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
+        
+        // The heap never grows beyond max_heap. The live blocks always fit,
+        // because they fit the current heap.
+        
+        if let Some(max_heap) = self.opts.max_heap {
+            target_size = min(target_size, max_heap);
+        }
+        
+        tracegc!("live {} used {} target size {}", live, used, target_size);
+        
+        if target_size > self.memory.size() {
+            let memory = Memory::alloc(target_size).unwrap();
+            
+            self.compact(memory.ptr(), &roots, walker);
+            
+            self.memory = memory;
+        } else {
+            // Compact when the holes take too much of the heap, or when the
+            // allocation that failed does not fit anywhere.
+            
+            let holes = top - live;
+            let fits = largest >= self.last_failed || top + self.last_failed <= self.high_mark;
+            
+            if holes as f64 > self.memory.size() as f64 * self.opts.compact_threshold || !fits {
+                tracegc!("compacting holes {} fits {}", holes, fits);
+                
+                self.compact(base, &roots, walker);
+            } else {
+                self.sweep();
+            }
+        }
+        
+        self.high_mark = (self.memory.size() * (self.opts.init_gc * 100.0) as usize) / 100;
+        self.last_failed = 0;
+    }
+    
+    // Turns the dead blocks into free blocks, merging adjacent ones, and
+    // clears the marks.
+    unsafe fn sweep(&mut self) {
+        for list in &mut self.small {
+            *list = ptr::null();
+        }
+        self.large = ptr::null();
+        self.free_size = 0;
+        
+        let base = self.memory.ptr();
+        let end = base.offset(self.top as isize);
+        let mut block = base;
+        let mut free : ptr_t = ptr::null();
+        
+        while block < end {
+            let header = Header::from_block(block);
+            let size = header.size();
+            
+            if header.is_marked() {
+                header.size = size;
+                
+                if !free.is_null() {
+                    let free_size = block as usize - free as usize;
+                    self.add_free(free, free_size);
+                    free = ptr::null();
+                }
+            } else if free.is_null() {
+                free = block;
+            }
+            
+            block = block.offset(size as isize);
+        }
+        
+        if !free.is_null() {
+            self.top = free as usize - base as usize;
+        }
+        
+        // Invalidate the free blocks in debug mode.
+        
+        if cfg!(not(ndebug)) {
+            let mut block = base;
+            let end = base.offset(self.top as isize);
+            
+            while block < end {
+                let header = Header::from_block(block);
+                
+                if header.is_free() {
+                    invalidate(block.offset(size_of::<Header>() as isize), header.size() - size_of::<Header>());
+                }
+                
+                block = block.offset(header.size() as isize);
+            }
+            
+            invalidate(end, self.memory.size() - self.top);
+        }
+    }
+    
+    // Slides the live blocks to the start of the memory at target, which is
+    // either the current memory or a new, larger one.
+    unsafe fn compact(&mut self, target: ptr_t, roots: &[*mut ptr_t], walker: &GcWalker) {
+        let base = self.memory.ptr();
+        let end = base.offset(self.top as isize);
+        
+        // Calculate the new location of every live block.
+        
+        let mut offset = 0;
+        let mut block = base;
+        
+        while block < end {
+            let header = Header::from_block(block);
+            
+            if header.is_marked() {
+                header.link = target.offset(offset as isize);
+                offset += header.size();
+            }
+            
+            block = block.offset(header.size() as isize);
+        }
+        
+        // Update the roots and the pointers in the live blocks. The walker
+        // only looks at the block itself, so blocks can be walked while the
+        // blocks they point to have not been moved yet.
+        
+        for &root in roots {
+            *root = forward(*root);
+        }
+        
+        let mut block = base;
+        
+        while block < end {
+            let header = Header::from_block(block);
+            
+            if header.is_marked() {
+                each_pointer(Header::offset_to_user(block), walker, |child| *child = forward(*child));
+            }
+            
+            block = block.offset(header.size() as isize);
+        }
+        
+        // Move the blocks. Blocks only move to lower addresses within the
+        // same memory, so a block never overwrites one that has not been
+        // moved yet.
+        
+        let mut block = base;
+        
+        while block < end {
+            let header = Header::from_block(block);
+            let size = header.size();
+            
+            if header.is_marked() {
+                let to = header.link;
+                
+                header.link = ptr::null();
+                header.size = size;
+                
+                ptr::copy(block, transmute(to), size);
+            }
+            
+            block = block.offset(size as isize);
+        }
+        
+        self.top = offset;
+        
+        for list in &mut self.small {
+            *list = ptr::null();
+        }
+        self.large = ptr::null();
+        self.free_size = 0;
+        
+        if cfg!(not(ndebug)) {
+            if target == base {
+                invalidate(base.offset(self.top as isize), self.memory.size() - self.top);
+            } else {
+                invalidate(base, self.memory.size());
+            }
+        }
+    }
+}
+
+unsafe fn mark(ptr: ptr_t, stack: &mut Vec<ptr_t>) {
+    if ptr.is_null() {
+        return;
+    }
+    
+    let header = Header::from_ptr(ptr);
+    
+    if !header.is_marked() {
+        header.size |= MARKED;
+        stack.push(ptr);
+    }
+}
+
+unsafe fn forward(ptr: ptr_t) -> ptr_t {
+    if ptr.is_null() {
+        ptr
+    } else {
+        Header::offset_to_user(Header::from_ptr(ptr).link)
+    }
+}
+
+// Calls f for every pointer in the block that is not null.
+unsafe fn each_pointer<F: FnMut(*mut ptr_t)>(ptr: ptr_t, walker: &GcWalker, mut f: F) {
+    let gc_header = GcMemHeader::from_ptr(ptr);
+    let ty = gc_header.get_type_id();
+    let size = gc_header.get_size();
+    let ptrs = size / size_of::<usize>();
+    
+    if gc_header.is_array() {
+        let count = *transmute::<_, *const usize>(ptr);
+        
+        let mut child = ptr.offset(size_of::<usize>() as isize);
+        let end = child.offset((count * size) as isize);
+        
+        while child < end {
+            // Stop processing arrays when we receive a GcWalk::EndArray.
+            if !process_block(child, ty, ptrs, walker, &mut f) {
+                break;
+            }
+            
+            child = child.offset(size as isize);
+        }
+    } else {
+        process_block(ptr, ty, ptrs, walker, &mut f);
+    }
+}
+
+unsafe fn process_block<F: FnMut(*mut ptr_t)>(ptr: ptr_t, ty: u32, ptrs: usize, walker: &GcWalker, f: &mut F) -> bool {
+    for i in 0..ptrs {
+        match walker.walk(ty, ptr, i as u32) {
+            GcWalk::End => return true,
+            GcWalk::EndArray => return false,
+            GcWalk::Skip => {},
+            GcWalk::Pointer => {
+                let offset = (ptr as *mut ptr_t).offset(i as isize);
+                if !(*offset).is_null() {
+                    f(offset);
+                }
+            }
+        }
+    }
+    
+    true
+}
+
+unsafe fn finalize(ptr: ptr_t, walker: &GcWalker) {
+    let gc_header = GcMemHeader::from_ptr(ptr);
+    let ty = gc_header.get_type_id();
+    let size = gc_header.get_size();
+    
+    if gc_header.is_array() {
+        let count = *transmute::<_, *const usize>(ptr);
+        
+        let mut child = ptr.offset(size_of::<usize>() as isize);
+        let end = child.offset((count * size) as isize);
+        
+        while child < end {
+            // Stop processing arrays when we receive a GcFinalize::NotFinalizable.
+            match walker.finalize(ty, child) {
+                GcFinalize::NotFinalizable => break,
+                GcFinalize::Finalized => {}
+            }
+            
+            child = child.offset(size as isize);
+        }
+    } else {
+        walker.finalize(ty, ptr);
+    }
+}
+
+unsafe fn invalidate(ptr: ptr_t, size: usize) {
+    let mut ptr = transmute::<_, *mut u32>(ptr);
+    let end = ptr.offset((size / size_of::<u32>()) as isize);
+    
+    while ptr < end {
+        *ptr = 0xdeadbeef;
+        ptr = ptr.offset(1);
+    }
+}
+
+impl Strategy for MarkSweep {
+    unsafe fn alloc_raw(&mut self, size: usize) -> ptr_t {
+        // Round the size to the next pointer.
+        let size = (size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+        
+        let high_mark = self.high_mark;
+        let result = self.alloc_block(size + size_of::<Header>(), high_mark);
+        
+        if result.is_null() {
+            self.last_failed = size + size_of::<Header>();
+        } else {
+            ptr::write_bytes(transmute::<_, *mut u8>(result), 0, size);
+        }
+        
+        result
+    }
+    
+    unsafe fn alloc_reserve(&mut self, size: usize) -> ptr_t {
+        // The reserve is the memory between the high mark and the end
+        // of the heap.
+        let size = (size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+        
+        let end = self.memory.size();
+        let result = self.alloc_block(size + size_of::<Header>(), end);
+        
+        if !result.is_null() {
+            ptr::write_bytes(transmute::<_, *mut u8>(result), 0, size);
+        }
+        
+        result
+    }
+    
+    unsafe fn verify_block(&self, ptr: ptr_t) -> bool {
+        let start = self.memory.ptr();
+        let end = start.offset(self.top as isize);
+        let block = ptr.offset(-((size_of::<Header>() + size_of::<GcMemHeader>()) as isize));
+        
+        if block < start || ptr >= end || (block as usize - start as usize) % size_of::<usize>() != 0 {
+            return false;
+        }
+        
+        let header = Header::from_block(block);
+        let size = (block_size(ptr) + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+        
+        !header.is_free() &&
+            !header.is_marked() &&
+            header.size() == size + size_of::<Header>() &&
+            block.offset(header.size() as isize) <= end
+    }
+    
+    fn mem_allocated(&self) -> usize {
+        self.memory.size()
+    }
+    
+    fn mem_used(&self) -> usize {
+        self.top - self.free_size
+    }
+    
+    fn gc(&mut self, walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker) {
+        let start = time::precise_time_ns();
+        
+        tracegc!("=== GC === start");
+        
+        unsafe {
+            self.collect(walkers, walker);
+        }
+        
+        let elapsed = (time::precise_time_ns() - start) / 1_000_000;
+        
+        tracegc!("=== GC === allocated {} used {} ms {}", nice_size(self.mem_allocated()), nice_size(self.mem_used()), elapsed);
+    }
+}
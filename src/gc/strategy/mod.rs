@@ -1,4 +1,6 @@
 pub mod copying;
+pub mod mark_sweep;
+pub mod generational;
 
 extern crate libc;
 
@@ -7,9 +9,39 @@ use gc::{GcRootWalker, GcWalker, ptr_t};
 pub trait Strategy {
     unsafe fn alloc_raw(&mut self, size: usize) -> ptr_t;
     
+    // Allocates from memory that is kept in reserve for when the heap
+    // cannot grow anymore.
+    unsafe fn alloc_reserve(&mut self, size: usize) -> ptr_t;
+    
     fn mem_allocated(&self) -> usize;
     
     fn mem_used(&self) -> usize;
     
+    // Called after a pointer was stored into the block at ptr.
+    fn write_barrier(&mut self, _ptr: ptr_t) {}
+    
+    // Checks that ptr points to a live block of the heap with a valid header.
+    // Only used to verify the heap after a collection.
+    unsafe fn verify_block(&self, ptr: ptr_t) -> bool;
+    
     fn gc(&mut self, walkers: Vec<Box<GcRootWalker>>, walker: &GcWalker);
 }
+
+pub fn nice_size(size: usize) -> String {
+    if size < 1024 {
+        format!("{} B", size)
+    } else {
+        let mut size = size as f64 / 1024.0;
+        if size < 1024.0 {
+            format!("{:.1} KB", size)
+        } else {
+            size /= 1024.0;
+            if size < 1024.0 {
+                format!("{:.1} MB", size)
+            } else {
+                size /= 1024.0;
+                format!("{:.1} GB", size)
+            }
+        }
+    }
+}
@@ -1,17 +1,96 @@
-use syntax::Name;
+use syntax::{Name, Span};
 use syntax::ast::FunctionRef;
 use util::interner::StrInterner;
 use std::fmt::Write;
 use std::cmp::Ordering;
-use rt::JsPreferredType;
+use std::mem;
+use rt::{JsPreferredType, PropertyCache};
+use ir::register::RegisterInstruction;
+use std::cell::Cell;
 
 pub struct Block {
     pub ir: Vec<Ir>,
     pub locals: Vec<Option<Name>>,
-    pub try_catches: Vec<TryCatch>
+    pub try_catches: Vec<TryCatch>,
+    // Line table mapping IR offsets to source locations. An entry applies
+    // to all instructions from its offset up to the offset of the next entry.
+    pub spans: Vec<(IrOffset, Span)>,
+    // Register instructions by offset, or empty when they are not compiled.
+    pub registers: Vec<Option<RegisterInstruction>>,
+    // Property caches of the instructions by offset.
+    pub caches: Vec<Cell<PropertyCache>>
 }
 
 impl Block {
+    /// Gets the location in the source of the instruction at the offset.
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        let index = match self.spans.binary_search_by(|&(start, _)| start.offset().cmp(&offset)) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1
+        };
+        
+        Some(self.spans[index].1)
+    }
+    
+    /// Removes the instructions marked in removed. Jump targets, try/catch
+    /// ranges and the line table that point to a removed instruction are
+    /// moved to the next instruction that is kept.
+    pub fn remove(&mut self, removed: &[bool]) {
+        let mut offsets = Vec::with_capacity(self.ir.len() + 1);
+        let mut offset = 0;
+        
+        for i in 0..self.ir.len() {
+            offsets.push(IrOffset(offset));
+            if !removed[i] {
+                offset += 1;
+            }
+        }
+        
+        offsets.push(IrOffset(offset));
+        
+        let ir = mem::replace(&mut self.ir, Vec::new());
+        
+        for (i, mut ir) in ir.into_iter().enumerate() {
+            if !removed[i] {
+                ir.fixup_label(&offsets);
+                self.ir.push(ir);
+            }
+        }
+        
+        for try_catch in &mut self.try_catches {
+            try_catch.try = try_catch.try.remap(&offsets);
+            try_catch.catch = try_catch.catch.map(|catch| catch.remap(&offsets));
+            try_catch.finally = try_catch.finally.map(|finally| finally.remap(&offsets));
+        }
+        
+        // Entries that end up at the same offset are merged, keeping the
+        // last one like IrBuilder::set_span does.
+        
+        let mut spans = Vec::<(IrOffset, Span)>::with_capacity(self.spans.len());
+        
+        for &(offset, span) in &self.spans {
+            let offset = offsets[offset.0];
+            
+            if let Some(last) = spans.last_mut() {
+                if last.0.offset() == offset.offset() {
+                    last.1 = span;
+                    continue;
+                }
+            }
+            
+            spans.push((offset, span));
+        }
+        
+        self.spans = spans;
+        
+        // Register instructions and caches refer to offsets and must be
+        // created again.
+        
+        self.registers.clear();
+        self.caches.clear();
+    }
+    
     fn print_local(&self, string: &mut String, local: Local, interner: &StrInterner) {
         write!(string, "{}", local.0).ok();
         
@@ -305,6 +384,10 @@ impl Block {
 pub struct IrOffset(usize);
 
 impl IrOffset {
+    pub fn from_offset(offset: usize) -> IrOffset {
+        IrOffset(offset)
+    }
+    
     pub fn offset(&self) -> usize {
         self.0
     }
@@ -314,6 +397,10 @@ impl IrOffset {
 pub struct IrRange(IrOffset, IrOffset);
 
 impl IrRange {
+    pub fn new(start: IrOffset, end: IrOffset) -> IrRange {
+        IrRange(start, end)
+    }
+    
     pub fn start(&self) -> IrOffset {
         self.0
     }
@@ -325,12 +412,20 @@ impl IrRange {
     pub fn contains(&self, offset: usize) -> bool {
         offset >= (self.0).0 && offset < (self.1).0
     }
+    
+    fn remap(&self, offsets: &[IrOffset]) -> IrRange {
+        IrRange(offsets[(self.0).0], offsets[(self.1).0])
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct Local(usize);
 
 impl Local {
+    pub fn from_offset(offset: usize) -> Local {
+        Local(offset)
+    }
+    
     pub fn offset(&self) -> usize {
         self.0
     }
@@ -340,6 +435,10 @@ impl Local {
 pub struct Label(usize);
 
 impl Label {
+    pub fn from_offset(offset: usize) -> Label {
+        Label(offset)
+    }
+    
     pub fn offset(&self) -> usize {
         self.0
     }
@@ -386,7 +485,8 @@ pub struct IrBuilder {
     locals: Vec<Option<Name>>,
     labels: Vec<IrOffset>,
     try_catch_stack: Vec<TryCatch>,
-    try_catches: Vec<TryCatch>
+    try_catches: Vec<TryCatch>,
+    spans: Vec<(IrOffset, Span)>
 }
 
 impl IrBuilder {
@@ -396,10 +496,32 @@ impl IrBuilder {
             locals: Vec::new(),
             labels: Vec::new(),
             try_catch_stack: Vec::new(),
-            try_catches: Vec::new()
+            try_catches: Vec::new(),
+            spans: Vec::new()
         }
     }
     
+    /// Sets the location of the instructions emitted from here on.
+    pub fn set_span(&mut self, span: Span) {
+        let offset = self.ir.len();
+        
+        // When no instructions were emitted since the last location was set,
+        // the new location replaces the last one.
+        
+        if let Some(last) = self.spans.last_mut() {
+            if last.0.offset() == offset {
+                last.1 = span;
+                return;
+            }
+        }
+        
+        self.spans.push((IrOffset(offset), span));
+    }
+    
+    pub fn span(&self) -> Option<Span> {
+        self.spans.last().map(|&(_, span)| span)
+    }
+    
     pub fn local(&mut self, name: Option<Name>) -> Local {
         let local = Local(self.locals.len());
         
@@ -465,7 +587,10 @@ impl IrBuilder {
         Block {
             ir: self.ir,
             locals: self.locals,
-            try_catches: self.try_catches
+            try_catches: self.try_catches,
+            spans: self.spans,
+            registers: Vec::new(),
+            caches: Vec::new()
         }
     }
     
@@ -524,6 +649,7 @@ impl IrBuilder {
     }
 }
 
+#[derive(Copy, Clone)]
 pub enum Ir {
     Add,
     BitAnd,
@@ -632,6 +758,20 @@ pub enum Ir {
 }
 
 impl Ir {
+    /// Gets the label the instruction jumps to, if any.
+    pub fn label(&self) -> Option<Label> {
+        match *self {
+            Ir::Jump(label) |
+            Ir::JumpStrictEq(label) |
+            Ir::JumpFalse(label) |
+            Ir::JumpTrue(label) |
+            Ir::Leave(label) |
+            Ir::NextIter(_, label)
+                => Some(label),
+            _ => None
+        }
+    }
+    
     fn fixup_label(&mut self, labels: &[IrOffset]) {
         match self {
             &mut Ir::Jump(ref mut target) |
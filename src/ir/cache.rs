@@ -0,0 +1,1131 @@
+// Binary cache of the functions of a program.
+//
+// Lexing, parsing and building the IR of large scripts takes most of their
+// startup time. The cache stores the function table and the IR of every
+// function of a program, so the program can be loaded without parsing it.
+// The cache carries a hash of the source and of the options the IR was built
+// with; a cache with another hash or another version is ignored and written
+// again.
+//
+// Names are stored in a table of strings and interned again when the cache is
+// loaded, and function references are relative to the program, so a cache
+// can be loaded into any context. The AST of cached functions is not
+// restored; the IR of all functions is built before the cache is written.
+// Register instructions and property caches are not stored but created when
+// the cache is loaded.
+
+use ir::{IrContext, read_file};
+use ir::builder::{Block, Ir, IrOffset, IrRange, Label, Local, TryCatch};
+use syntax::{Name, Span};
+use syntax::ast::{Function, FunctionRef, RootBlock, RootBlockState, Block as AstBlock, Slot, SlotState, ScopeType};
+use syntax::parser::ParseMode;
+use syntax::reader::StringReader;
+use util::interner::StrInterner;
+use rt::JsPreferredType;
+use ::JsResult;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::mem::transmute;
+use std::rc::Rc;
+
+const MAGIC : &'static [u8] = b"RJSC";
+
+// Must be incremented whenever the format or the IR changes.
+const VERSION : u32 = 1;
+
+type CacheResult<T> = Result<T, ()>;
+
+impl IrContext {
+    /// Parses the file like parse_file, but loads the functions from the cache
+    /// file when it was written for the same source and options. Otherwise
+    /// the IR of all functions of the file is built and written to the cache
+    /// file. Failing to write the cache file does not fail the parse.
+    pub fn parse_file_cached(&mut self, file_name: &str, cache_file_name: &str, strict: bool, privileged: bool) -> JsResult<FunctionRef> {
+        let js = try!(read_file(file_name));
+        
+        let hash = self.hash_source(file_name, &js, strict, privileged);
+        
+        if let Some(program_ref) = self.read_cache(cache_file_name, hash) {
+            return Ok(program_ref);
+        }
+        
+        let offset = self.ast.functions.len();
+        
+        let program_ref = try!(self.build_ir(&mut StringReader::new(file_name, &js), strict, ParseMode::Normal, privileged));
+        
+        for i in offset..self.ast.functions.len() {
+            try!(self.build_function_ir(FunctionRef(i as u32), ParseMode::Normal));
+        }
+        
+        let cache = self.write_cache(hash, offset, program_ref);
+        
+        // The cache is only an optimization, so it doesn't matter when it
+        // cannot be written; the next run parses the file again.
+        
+        let _ = write_cache_file(cache_file_name, &cache);
+        
+        Ok(program_ref)
+    }
+    
+    // FNV-1a hash of everything the cached IR depends on. The file name is
+    // included because it is part of the line table.
+    fn hash_source(&self, file_name: &str, js: &str, strict: bool, privileged: bool) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        
+        let separator = [0u8];
+        let flags = [strict as u8, privileged as u8, self.optimize as u8];
+        let bytes = file_name.as_bytes().iter().chain(&separator).chain(js.as_bytes()).chain(&flags);
+        
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        
+        hash
+    }
+    
+    fn write_cache(&self, hash: u64, offset: usize, program_ref: FunctionRef) -> Vec<u8> {
+        let mut writer = CacheWriter {
+            buffer: Vec::new(),
+            names: Vec::new(),
+            indexes: HashMap::new(),
+            offset: offset
+        };
+        
+        writer.u32((self.ast.functions.len() - offset) as u32);
+        writer.function(program_ref);
+        
+        for i in offset..self.ast.functions.len() {
+            let function = &self.ast.functions[i];
+            let state = function.block.state.borrow();
+            
+            writer.bool(function.global);
+            writer.option_name(function.name);
+            writer.u32(function.args);
+            writer.bool(function.block.strict);
+            writer.u32(function.block.args.len() as u32);
+            for &arg in &function.block.args {
+                writer.name(arg);
+            }
+            writer.u32(state.slots.len() as u32);
+            for slot in &state.slots {
+                writer.slot(slot);
+            }
+            writer.bool(state.take_scope);
+            writer.scope_type(state.build_scope);
+            writer.span(function.span);
+            writer.block(self.functions[i].as_ref().unwrap());
+        }
+        
+        // The name table goes before the functions that refer to it.
+        
+        let mut result = Vec::new();
+        
+        result.extend(MAGIC);
+        
+        let body = writer.buffer;
+        writer.buffer = result;
+        
+        writer.u32(VERSION);
+        writer.u64(hash);
+        
+        let names = writer.names.len();
+        writer.u32(names as u32);
+        for i in 0..names {
+            let name = self.interner.get(writer.names[i]);
+            writer.string(&*name);
+        }
+        
+        let mut result = writer.buffer;
+        result.extend(body);
+        
+        result
+    }
+    
+    // Loads the functions from the cache file. Returns None when there is no
+    // usable cache, i.e. when it does not exist, has another version or hash
+    // or cannot be read.
+    fn read_cache(&mut self, cache_file_name: &str, hash: u64) -> Option<FunctionRef> {
+        let mut buffer = Vec::new();
+        
+        match File::open(cache_file_name) {
+            Ok(mut file) => {
+                if file.read_to_end(&mut buffer).is_err() {
+                    return None;
+                }
+            }
+            Err(..) => return None
+        }
+        
+        let offset = self.ast.functions.len();
+        
+        let mut reader = CacheReader {
+            buffer: &buffer,
+            position: 0,
+            names: Vec::new(),
+            interned: 0,
+            offset: offset,
+            functions: 0,
+            ir: 0,
+            locals: 0
+        };
+        
+        // Nothing is added to the context before the whole cache is read.
+        
+        let (program_ref, functions) = match reader.read(&self.interner, hash) {
+            Ok(result) => result,
+            Err(..) => return None
+        };
+        
+        for (function, mut block) in functions {
+            self.prepare_block(&mut block);
+            
+            self.ast.functions.push(Box::new(function));
+            self.functions.push(Some(Rc::new(block)));
+        }
+        
+        Some(program_ref)
+    }
+}
+
+// Writes the cache to a temporary file that then replaces the cache file, so
+// a failed write never leaves a partial cache file behind.
+fn write_cache_file(cache_file_name: &str, cache: &[u8]) -> io::Result<()> {
+    let temp_file_name = format!("{}.tmp", cache_file_name);
+    
+    let result = File::create(&temp_file_name).and_then(|mut file| {
+        try!(file.write_all(cache));
+        file.sync_all()
+    });
+    
+    if result.is_ok() {
+        fs::rename(&temp_file_name, cache_file_name)
+    } else {
+        let _ = fs::remove_file(&temp_file_name);
+        result
+    }
+}
+
+struct CacheWriter {
+    buffer: Vec<u8>,
+    names: Vec<Name>,
+    indexes: HashMap<Name, u32>,
+    offset: usize
+}
+
+impl CacheWriter {
+    fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+    
+    fn u32(&mut self, value: u32) {
+        for i in 0..4 {
+            self.buffer.push((value >> (i * 8)) as u8);
+        }
+    }
+    
+    fn u64(&mut self, value: u64) {
+        for i in 0..8 {
+            self.buffer.push((value >> (i * 8)) as u8);
+        }
+    }
+    
+    fn i32(&mut self, value: i32) {
+        self.u32(value as u32);
+    }
+    
+    fn i64(&mut self, value: i64) {
+        self.u64(value as u64);
+    }
+    
+    fn f64(&mut self, value: f64) {
+        self.u64(unsafe { transmute(value) });
+    }
+    
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+    
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buffer.extend(value.as_bytes());
+    }
+    
+    // Index names are stored as is; other names are stored as a negative
+    // index into the name table.
+    fn name(&mut self, name: Name) {
+        if let Some(index) = name.index() {
+            self.i64(index as i64);
+            return;
+        }
+        
+        let index = match self.indexes.get(&name) {
+            Some(&index) => index,
+            None => {
+                let index = self.names.len() as u32;
+                self.names.push(name);
+                self.indexes.insert(name, index);
+                index
+            }
+        };
+        
+        self.i64(-(index as i64) - 1);
+    }
+    
+    fn option_name(&mut self, name: Option<Name>) {
+        match name {
+            Some(name) => {
+                self.bool(true);
+                self.name(name);
+            }
+            None => self.bool(false)
+        }
+    }
+    
+    fn function(&mut self, function: FunctionRef) {
+        let index = function.usize() - self.offset;
+        self.u32(index as u32);
+    }
+    
+    fn local(&mut self, local: Local) {
+        self.u32(local.offset() as u32);
+    }
+    
+    fn label(&mut self, label: Label) {
+        self.u32(label.offset() as u32);
+    }
+    
+    fn preferred_type(&mut self, preferred_type: JsPreferredType) {
+        self.u8(match preferred_type {
+            JsPreferredType::None => 0,
+            JsPreferredType::String => 1,
+            JsPreferredType::Number => 2
+        });
+    }
+    
+    fn span(&mut self, span: Span) {
+        self.i32(span.start_line);
+        self.i32(span.start_col);
+        self.i32(span.end_line);
+        self.i32(span.end_col);
+        self.name(span.file);
+    }
+    
+    fn slot(&mut self, slot: &Slot) {
+        self.name(slot.name);
+        self.bool(slot.arguments);
+        match slot.arg {
+            Some(arg) => {
+                self.bool(true);
+                self.u32(arg);
+            }
+            None => self.bool(false)
+        }
+        match slot.state {
+            SlotState::Local => self.u8(0),
+            SlotState::Scoped => self.u8(1),
+            SlotState::Lifted(index) => {
+                self.u8(2);
+                self.u32(index);
+            }
+        }
+    }
+    
+    fn scope_type(&mut self, scope_type: ScopeType) {
+        match scope_type {
+            ScopeType::None => self.u8(0),
+            ScopeType::Thin(size) => {
+                self.u8(1);
+                self.u32(size);
+            }
+            ScopeType::Thick => self.u8(2)
+        }
+    }
+    
+    fn range(&mut self, range: IrRange) {
+        self.u32(range.start().offset() as u32);
+        self.u32(range.end().offset() as u32);
+    }
+    
+    fn option_range(&mut self, range: Option<IrRange>) {
+        match range {
+            Some(range) => {
+                self.bool(true);
+                self.range(range);
+            }
+            None => self.bool(false)
+        }
+    }
+    
+    fn block(&mut self, block: &Block) {
+        self.u32(block.locals.len() as u32);
+        for &local in &block.locals {
+            self.option_name(local);
+        }
+        
+        self.u32(block.ir.len() as u32);
+        for &ir in &block.ir {
+            self.ir(ir);
+        }
+        
+        self.u32(block.try_catches.len() as u32);
+        for try_catch in &block.try_catches {
+            self.range(try_catch.try);
+            self.option_range(try_catch.catch);
+            self.option_range(try_catch.finally);
+        }
+        
+        self.u32(block.spans.len() as u32);
+        for &(offset, span) in &block.spans {
+            self.u32(offset.offset() as u32);
+            self.span(span);
+        }
+    }
+    
+    fn ir(&mut self, ir: Ir) {
+        match ir {
+            Ir::Add => self.u8(0),
+            Ir::BitAnd => self.u8(1),
+            Ir::BitNot => self.u8(2),
+            Ir::BitOr => self.u8(3),
+            Ir::BitXOr => self.u8(4),
+            Ir::Call(value) => {
+                self.u8(5);
+                self.u32(value);
+            }
+            Ir::CurrentIter(local) => {
+                self.u8(6);
+                self.local(local);
+            }
+            Ir::Debugger => self.u8(7),
+            Ir::DeleteIndex => self.u8(8),
+            Ir::DeleteName(name) => {
+                self.u8(9);
+                self.name(name);
+            }
+            Ir::DeleteEnvName(name) => {
+                self.u8(10);
+                self.name(name);
+            }
+            Ir::Divide => self.u8(11),
+            Ir::Dup => self.u8(12),
+            Ir::EndFinally => self.u8(13),
+            Ir::EndIter(local) => {
+                self.u8(14);
+                self.local(local);
+            }
+            Ir::EnterEnv => self.u8(15),
+            Ir::EnterWithEnv => self.u8(16),
+            Ir::Eq => self.u8(17),
+            Ir::FindEnvObjectFor(name) => {
+                self.u8(18);
+                self.name(name);
+            }
+            Ir::Ge => self.u8(19),
+            Ir::Gt => self.u8(20),
+            Ir::In => self.u8(21),
+            Ir::InitEnvName(name) => {
+                self.u8(22);
+                self.name(name);
+            }
+            Ir::InstanceOf => self.u8(23),
+            Ir::IntoIter(local) => {
+                self.u8(24);
+                self.local(local);
+            }
+            Ir::Jump(label) => {
+                self.u8(25);
+                self.label(label);
+            }
+            Ir::JumpStrictEq(label) => {
+                self.u8(26);
+                self.label(label);
+            }
+            Ir::JumpFalse(label) => {
+                self.u8(27);
+                self.label(label);
+            }
+            Ir::JumpTrue(label) => {
+                self.u8(28);
+                self.label(label);
+            }
+            Ir::Le => self.u8(29),
+            Ir::Leave(label) => {
+                self.u8(30);
+                self.label(label);
+            }
+            Ir::LeaveEnv => self.u8(31),
+            Ir::LoadException => self.u8(32),
+            Ir::LoadF64(value) => {
+                self.u8(33);
+                self.f64(value);
+            }
+            Ir::LoadFalse => self.u8(34),
+            Ir::LoadFunction(function) => {
+                self.u8(35);
+                self.function(function);
+            }
+            Ir::LoadI32(value) => {
+                self.u8(36);
+                self.i32(value);
+            }
+            Ir::LoadI64(value) => {
+                self.u8(37);
+                self.i64(value);
+            }
+            Ir::LoadIndex => self.u8(38),
+            Ir::LoadLifted(index, depth) => {
+                self.u8(39);
+                self.u32(index);
+                self.u32(depth);
+            }
+            Ir::LoadLocal(local) => {
+                self.u8(40);
+                self.local(local);
+            }
+            Ir::LoadName(name) => {
+                self.u8(41);
+                self.name(name);
+            }
+            Ir::LoadNull => self.u8(42),
+            Ir::LoadParam(value) => {
+                self.u8(43);
+                self.u32(value);
+            }
+            Ir::LoadRegex(pattern, flags) => {
+                self.u8(44);
+                self.name(pattern);
+                self.name(flags);
+            }
+            Ir::LoadEnv(name) => {
+                self.u8(45);
+                self.name(name);
+            }
+            Ir::LoadEnvObject => self.u8(46),
+            Ir::LoadEnvObjectFor(name) => {
+                self.u8(47);
+                self.name(name);
+            }
+            Ir::LoadGlobal(name) => {
+                self.u8(48);
+                self.name(name);
+            }
+            Ir::LoadEnvArguments(value) => {
+                self.u8(49);
+                self.u32(value);
+            }
+            Ir::LoadString(name) => {
+                self.u8(50);
+                self.name(name);
+            }
+            Ir::LoadThis => self.u8(51),
+            Ir::LoadTrue => self.u8(52),
+            Ir::LoadUndefined => self.u8(53),
+            Ir::Lsh => self.u8(54),
+            Ir::Lt => self.u8(55),
+            Ir::Modulus => self.u8(56),
+            Ir::Multiply => self.u8(57),
+            Ir::Ne => self.u8(58),
+            Ir::Negative => self.u8(59),
+            Ir::New(value) => {
+                self.u8(60);
+                self.u32(value);
+            }
+            Ir::NewArguments => self.u8(61),
+            Ir::NewArray => self.u8(62),
+            Ir::NewObject => self.u8(63),
+            Ir::NextIter(local, label) => {
+                self.u8(64);
+                self.local(local);
+                self.label(label);
+            }
+            Ir::Not => self.u8(65),
+            Ir::Pick(value) => {
+                self.u8(66);
+                self.u32(value);
+            }
+            Ir::Pop => self.u8(67),
+            Ir::Positive => self.u8(68),
+            Ir::Return => self.u8(69),
+            Ir::Rsh => self.u8(70),
+            Ir::RshZeroFill => self.u8(71),
+            Ir::CallEval(value) => {
+                self.u8(72);
+                self.u32(value);
+            }
+            Ir::StoreGlobal(name) => {
+                self.u8(73);
+                self.name(name);
+            }
+            Ir::StoreIndex => self.u8(74),
+            Ir::StoreIndexUnchecked => self.u8(75),
+            Ir::StoreLifted(index, depth) => {
+                self.u8(76);
+                self.u32(index);
+                self.u32(depth);
+            }
+            Ir::StoreLocal(local) => {
+                self.u8(77);
+                self.local(local);
+            }
+            Ir::StoreName(name) => {
+                self.u8(78);
+                self.name(name);
+            }
+            Ir::StoreNameUnchecked(name) => {
+                self.u8(79);
+                self.name(name);
+            }
+            Ir::StoreNameGetterUnchecked(name, function) => {
+                self.u8(80);
+                self.name(name);
+                self.function(function);
+            }
+            Ir::StoreNameSetterUnchecked(name, function) => {
+                self.u8(81);
+                self.name(name);
+                self.function(function);
+            }
+            Ir::StoreParam(value) => {
+                self.u8(82);
+                self.u32(value);
+            }
+            Ir::StoreEnv(name) => {
+                self.u8(83);
+                self.name(name);
+            }
+            Ir::StoreEnvArguments => self.u8(84),
+            Ir::StrictEq => self.u8(85),
+            Ir::StrictNe => self.u8(86),
+            Ir::Subtract => self.u8(87),
+            Ir::Swap => self.u8(88),
+            Ir::Throw => self.u8(89),
+            Ir::ToBoolean => self.u8(90),
+            Ir::ToNumber => self.u8(91),
+            Ir::ToPrimitive(preferred_type) => {
+                self.u8(92);
+                self.preferred_type(preferred_type);
+            }
+            Ir::ToInteger => self.u8(93),
+            Ir::ToInt32 => self.u8(94),
+            Ir::ToUInt32 => self.u8(95),
+            Ir::ToUInt16 => self.u8(96),
+            Ir::ToString => self.u8(97),
+            Ir::ToObject => self.u8(98),
+            Ir::ToPropertyKey => self.u8(99),
+            Ir::Typeof => self.u8(100),
+            Ir::TypeofIndex => self.u8(101),
+            Ir::TypeofName(name) => {
+                self.u8(102);
+                self.name(name);
+            }
+            Ir::ValidateMemberTarget => self.u8(103),
+        }
+    }
+}
+
+// Reads a cache. Every read fails when the cache is truncated, and
+// references to locals, labels, names and functions are checked, so a damaged
+// cache cannot produce IR that does not run.
+struct CacheReader<'a> {
+    buffer: &'a [u8],
+    position: usize,
+    names: Vec<Name>,
+    // Number of names in the interner once the names of the cache are interned.
+    interned: usize,
+    offset: usize,
+    // Number of functions in the cache.
+    functions: usize,
+    // Number of instructions and locals of the block being read.
+    ir: usize,
+    locals: usize
+}
+
+impl<'a> CacheReader<'a> {
+    fn read(&mut self, interner: &StrInterner, hash: u64) -> CacheResult<(FunctionRef, Vec<(Function, Block)>)> {
+        if try!(self.bytes(MAGIC.len())) != MAGIC || try!(self.u32()) != VERSION || try!(self.u64()) != hash {
+            return Err(());
+        }
+        
+        let names = try!(self.u32());
+        for _ in 0..names {
+            let name = try!(self.string());
+            self.names.push(interner.intern(&name));
+        }
+        
+        self.interned = interner.len();
+        
+        self.functions = try!(self.u32()) as usize;
+        let program_ref = try!(self.function());
+        
+        let mut functions = Vec::new();
+        
+        for _ in 0..self.functions {
+            let global = try!(self.bool());
+            let name = try!(self.option_name());
+            let args = try!(self.u32());
+            let strict = try!(self.bool());
+            
+            let mut arg_names = Vec::new();
+            for _ in 0..try!(self.u32()) {
+                arg_names.push(try!(self.name()));
+            }
+            
+            let mut slots = Vec::new();
+            for _ in 0..try!(self.u32()) {
+                slots.push(try!(self.slot()));
+            }
+            
+            let take_scope = try!(self.bool());
+            let build_scope = try!(self.scope_type());
+            let span = try!(self.span());
+            let block = try!(self.block());
+            
+            let function = Function {
+                global: global,
+                name: name,
+                args: args,
+                block: RootBlock {
+                    args: arg_names,
+                    block: AstBlock {
+                        stmts: Vec::new(),
+                        locals: HashMap::new()
+                    },
+                    state: RefCell::new(RootBlockState {
+                        slots: slots,
+                        take_scope: take_scope,
+                        build_scope: build_scope
+                    }),
+                    strict: strict
+                },
+                span: span
+            };
+            
+            functions.push((function, block));
+        }
+        
+        if self.position != self.buffer.len() {
+            return Err(());
+        }
+        
+        Ok((program_ref, functions))
+    }
+    
+    fn bytes(&mut self, len: usize) -> CacheResult<&'a [u8]> {
+        if self.buffer.len() - self.position < len {
+            return Err(());
+        }
+        
+        let result = &self.buffer[self.position..self.position + len];
+        self.position += len;
+        
+        Ok(result)
+    }
+    
+    fn u8(&mut self) -> CacheResult<u8> {
+        Ok(try!(self.bytes(1))[0])
+    }
+    
+    fn u32(&mut self) -> CacheResult<u32> {
+        let bytes = try!(self.bytes(4));
+        Ok((0..4).fold(0, |value, i| value | (bytes[i] as u32) << (i * 8)))
+    }
+    
+    fn u64(&mut self) -> CacheResult<u64> {
+        let bytes = try!(self.bytes(8));
+        Ok((0..8).fold(0, |value, i| value | (bytes[i] as u64) << (i * 8)))
+    }
+    
+    fn i32(&mut self) -> CacheResult<i32> {
+        Ok(try!(self.u32()) as i32)
+    }
+    
+    fn i64(&mut self) -> CacheResult<i64> {
+        Ok(try!(self.u64()) as i64)
+    }
+    
+    fn f64(&mut self) -> CacheResult<f64> {
+        Ok(unsafe { transmute(try!(self.u64())) })
+    }
+    
+    fn bool(&mut self) -> CacheResult<bool> {
+        match try!(self.u8()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(())
+        }
+    }
+    
+    fn string(&mut self) -> CacheResult<String> {
+        let len = try!(self.u32()) as usize;
+        let bytes = try!(self.bytes(len));
+        
+        String::from_utf8(bytes.to_vec()).map_err(|_| ())
+    }
+    
+    fn name(&mut self) -> CacheResult<Name> {
+        let value = try!(self.i64());
+        
+        let name = if value >= 0 {
+            if value >= ::std::u32::MAX as i64 {
+                return Err(());
+            }
+            Name::from_index(value as usize)
+        } else {
+            try!(self.names.get((-(value + 1)) as usize).cloned().ok_or(()))
+        };
+        
+        // Every name must resolve in the interner, which panics on names
+        // it doesn't know.
+        
+        match name.name() {
+            Some(index) if index >= self.interned => Err(()),
+            _ => Ok(name)
+        }
+    }
+    
+    fn option_name(&mut self) -> CacheResult<Option<Name>> {
+        if try!(self.bool()) {
+            Ok(Some(try!(self.name())))
+        } else {
+            Ok(None)
+        }
+    }
+    
+    fn function(&mut self) -> CacheResult<FunctionRef> {
+        let index = try!(self.u32()) as usize;
+        if index >= self.functions {
+            return Err(());
+        }
+        
+        Ok(FunctionRef((index + self.offset) as u32))
+    }
+    
+    fn local(&mut self) -> CacheResult<Local> {
+        let offset = try!(self.u32()) as usize;
+        if offset >= self.locals {
+            return Err(());
+        }
+        
+        Ok(Local::from_offset(offset))
+    }
+    
+    fn label(&mut self) -> CacheResult<Label> {
+        let offset = try!(self.u32()) as usize;
+        if offset > self.ir {
+            return Err(());
+        }
+        
+        Ok(Label::from_offset(offset))
+    }
+    
+    fn offset(&mut self) -> CacheResult<IrOffset> {
+        let offset = try!(self.u32()) as usize;
+        if offset > self.ir {
+            return Err(());
+        }
+        
+        Ok(IrOffset::from_offset(offset))
+    }
+    
+    fn preferred_type(&mut self) -> CacheResult<JsPreferredType> {
+        match try!(self.u8()) {
+            0 => Ok(JsPreferredType::None),
+            1 => Ok(JsPreferredType::String),
+            2 => Ok(JsPreferredType::Number),
+            _ => Err(())
+        }
+    }
+    
+    fn span(&mut self) -> CacheResult<Span> {
+        let start_line = try!(self.i32());
+        let start_col = try!(self.i32());
+        let end_line = try!(self.i32());
+        let end_col = try!(self.i32());
+        let file = try!(self.name());
+        
+        Ok(Span::new(start_line, start_col, end_line, end_col, file))
+    }
+    
+    fn slot(&mut self) -> CacheResult<Slot> {
+        let name = try!(self.name());
+        let arguments = try!(self.bool());
+        let arg = if try!(self.bool()) {
+            Some(try!(self.u32()))
+        } else {
+            None
+        };
+        let state = match try!(self.u8()) {
+            0 => SlotState::Local,
+            1 => SlotState::Scoped,
+            2 => SlotState::Lifted(try!(self.u32())),
+            _ => return Err(())
+        };
+        
+        Ok(Slot {
+            name: name,
+            arguments: arguments,
+            arg: arg,
+            state: state
+        })
+    }
+    
+    fn scope_type(&mut self) -> CacheResult<ScopeType> {
+        match try!(self.u8()) {
+            0 => Ok(ScopeType::None),
+            1 => Ok(ScopeType::Thin(try!(self.u32()))),
+            2 => Ok(ScopeType::Thick),
+            _ => Err(())
+        }
+    }
+    
+    fn range(&mut self) -> CacheResult<IrRange> {
+        let start = try!(self.offset());
+        let end = try!(self.offset());
+        
+        Ok(IrRange::new(start, end))
+    }
+    
+    fn option_range(&mut self) -> CacheResult<Option<IrRange>> {
+        if try!(self.bool()) {
+            Ok(Some(try!(self.range())))
+        } else {
+            Ok(None)
+        }
+    }
+    
+    fn block(&mut self) -> CacheResult<Block> {
+        let mut locals = Vec::new();
+        for _ in 0..try!(self.u32()) {
+            locals.push(try!(self.option_name()));
+        }
+        self.locals = locals.len();
+        
+        self.ir = try!(self.u32()) as usize;
+        let mut ir = Vec::new();
+        for _ in 0..self.ir {
+            ir.push(try!(self.ir()));
+        }
+        
+        let mut try_catches = Vec::new();
+        for _ in 0..try!(self.u32()) {
+            try_catches.push(TryCatch {
+                try: try!(self.range()),
+                catch: try!(self.option_range()),
+                finally: try!(self.option_range())
+            });
+        }
+        
+        let mut spans = Vec::new();
+        for _ in 0..try!(self.u32()) {
+            let offset = try!(self.offset());
+            let span = try!(self.span());
+            spans.push((offset, span));
+        }
+        
+        Ok(Block {
+            ir: ir,
+            locals: locals,
+            try_catches: try_catches,
+            spans: spans,
+            registers: Vec::new(),
+            caches: Vec::new()
+        })
+    }
+    
+    fn ir(&mut self) -> CacheResult<Ir> {
+        Ok(match try!(self.u8()) {
+            0 => Ir::Add,
+            1 => Ir::BitAnd,
+            2 => Ir::BitNot,
+            3 => Ir::BitOr,
+            4 => Ir::BitXOr,
+            5 => Ir::Call(try!(self.u32())),
+            6 => Ir::CurrentIter(try!(self.local())),
+            7 => Ir::Debugger,
+            8 => Ir::DeleteIndex,
+            9 => Ir::DeleteName(try!(self.name())),
+            10 => Ir::DeleteEnvName(try!(self.name())),
+            11 => Ir::Divide,
+            12 => Ir::Dup,
+            13 => Ir::EndFinally,
+            14 => Ir::EndIter(try!(self.local())),
+            15 => Ir::EnterEnv,
+            16 => Ir::EnterWithEnv,
+            17 => Ir::Eq,
+            18 => Ir::FindEnvObjectFor(try!(self.name())),
+            19 => Ir::Ge,
+            20 => Ir::Gt,
+            21 => Ir::In,
+            22 => Ir::InitEnvName(try!(self.name())),
+            23 => Ir::InstanceOf,
+            24 => Ir::IntoIter(try!(self.local())),
+            25 => Ir::Jump(try!(self.label())),
+            26 => Ir::JumpStrictEq(try!(self.label())),
+            27 => Ir::JumpFalse(try!(self.label())),
+            28 => Ir::JumpTrue(try!(self.label())),
+            29 => Ir::Le,
+            30 => Ir::Leave(try!(self.label())),
+            31 => Ir::LeaveEnv,
+            32 => Ir::LoadException,
+            33 => Ir::LoadF64(try!(self.f64())),
+            34 => Ir::LoadFalse,
+            35 => Ir::LoadFunction(try!(self.function())),
+            36 => Ir::LoadI32(try!(self.i32())),
+            37 => Ir::LoadI64(try!(self.i64())),
+            38 => Ir::LoadIndex,
+            39 => Ir::LoadLifted(try!(self.u32()), try!(self.u32())),
+            40 => Ir::LoadLocal(try!(self.local())),
+            41 => Ir::LoadName(try!(self.name())),
+            42 => Ir::LoadNull,
+            43 => Ir::LoadParam(try!(self.u32())),
+            44 => Ir::LoadRegex(try!(self.name()), try!(self.name())),
+            45 => Ir::LoadEnv(try!(self.name())),
+            46 => Ir::LoadEnvObject,
+            47 => Ir::LoadEnvObjectFor(try!(self.name())),
+            48 => Ir::LoadGlobal(try!(self.name())),
+            49 => Ir::LoadEnvArguments(try!(self.u32())),
+            50 => Ir::LoadString(try!(self.name())),
+            51 => Ir::LoadThis,
+            52 => Ir::LoadTrue,
+            53 => Ir::LoadUndefined,
+            54 => Ir::Lsh,
+            55 => Ir::Lt,
+            56 => Ir::Modulus,
+            57 => Ir::Multiply,
+            58 => Ir::Ne,
+            59 => Ir::Negative,
+            60 => Ir::New(try!(self.u32())),
+            61 => Ir::NewArguments,
+            62 => Ir::NewArray,
+            63 => Ir::NewObject,
+            64 => Ir::NextIter(try!(self.local()), try!(self.label())),
+            65 => Ir::Not,
+            66 => Ir::Pick(try!(self.u32())),
+            67 => Ir::Pop,
+            68 => Ir::Positive,
+            69 => Ir::Return,
+            70 => Ir::Rsh,
+            71 => Ir::RshZeroFill,
+            72 => Ir::CallEval(try!(self.u32())),
+            73 => Ir::StoreGlobal(try!(self.name())),
+            74 => Ir::StoreIndex,
+            75 => Ir::StoreIndexUnchecked,
+            76 => Ir::StoreLifted(try!(self.u32()), try!(self.u32())),
+            77 => Ir::StoreLocal(try!(self.local())),
+            78 => Ir::StoreName(try!(self.name())),
+            79 => Ir::StoreNameUnchecked(try!(self.name())),
+            80 => Ir::StoreNameGetterUnchecked(try!(self.name()), try!(self.function())),
+            81 => Ir::StoreNameSetterUnchecked(try!(self.name()), try!(self.function())),
+            82 => Ir::StoreParam(try!(self.u32())),
+            83 => Ir::StoreEnv(try!(self.name())),
+            84 => Ir::StoreEnvArguments,
+            85 => Ir::StrictEq,
+            86 => Ir::StrictNe,
+            87 => Ir::Subtract,
+            88 => Ir::Swap,
+            89 => Ir::Throw,
+            90 => Ir::ToBoolean,
+            91 => Ir::ToNumber,
+            92 => Ir::ToPrimitive(try!(self.preferred_type())),
+            93 => Ir::ToInteger,
+            94 => Ir::ToInt32,
+            95 => Ir::ToUInt32,
+            96 => Ir::ToUInt16,
+            97 => Ir::ToString,
+            98 => Ir::ToObject,
+            99 => Ir::ToPropertyKey,
+            100 => Ir::Typeof,
+            101 => Ir::TypeofIndex,
+            102 => Ir::TypeofName(try!(self.name())),
+            103 => Ir::ValidateMemberTarget,
+            _ => return Err(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ir::IrContext;
+    use rt::{JsEnv, JsItem};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+    
+    const SCRIPT : &'static str = "\
+function f(a, b) {
+    try {
+        return [a, b, 'x'].join('') + g();
+    } catch (e) {
+        return 'caught';
+    }
+}
+function g() {
+    return /y+/g.source;
+}
+f(1, 2);
+";
+    
+    fn write_script(name: &str, js: &str) -> (String, String) {
+        let file_name = env::temp_dir().join(format!("{}.js", name)).to_str().unwrap().to_string();
+        let cache_file_name = env::temp_dir().join(format!("{}.rjsc", name)).to_str().unwrap().to_string();
+        
+        File::create(&file_name).unwrap().write_all(js.as_bytes()).unwrap();
+        fs::remove_file(&cache_file_name).ok();
+        
+        (file_name, cache_file_name)
+    }
+    
+    fn run(file_name: &str, cache_file_name: &str) -> String {
+        let mut env = JsEnv::new().ok().unwrap();
+        let result = env.run_cached(file_name, cache_file_name).ok().unwrap();
+        let result = result.as_value(&env);
+        
+        result.to_string(&mut env).ok().unwrap().to_string()
+    }
+    
+    #[test]
+    fn load_cache() {
+        let (file_name, cache_file_name) = write_script("rjs-cache-load", SCRIPT);
+        
+        let mut ctx = IrContext::new();
+        let program_ref = ctx.parse_file_cached(&file_name, &cache_file_name, false, false).ok().unwrap();
+        let functions = ctx.function_count();
+        assert!(ctx.ast.functions[program_ref.usize()].block.block.stmts.len() > 0);
+        
+        // The second context is loaded from the cache and has no AST.
+        
+        let mut cached = IrContext::new();
+        let cached_ref = cached.parse_file_cached(&file_name, &cache_file_name, false, false).ok().unwrap();
+        assert_eq!(cached_ref.usize(), program_ref.usize());
+        assert_eq!(cached.function_count(), functions);
+        assert_eq!(cached.ast.functions[cached_ref.usize()].block.block.stmts.len(), 0);
+        
+        assert_eq!(run(&file_name, &cache_file_name), "12xy+");
+    }
+    
+    #[test]
+    fn source_changed() {
+        let (file_name, cache_file_name) = write_script("rjs-cache-changed", "1 + 1;");
+        assert_eq!(run(&file_name, &cache_file_name), "2");
+        
+        File::create(&file_name).unwrap().write_all(b"1 + 2;").unwrap();
+        assert_eq!(run(&file_name, &cache_file_name), "3");
+        assert_eq!(run(&file_name, &cache_file_name), "3");
+    }
+    
+    #[test]
+    fn damaged_cache() {
+        let (file_name, cache_file_name) = write_script("rjs-cache-damaged", SCRIPT);
+        assert_eq!(run(&file_name, &cache_file_name), "12xy+");
+        
+        let mut cache = Vec::new();
+        File::open(&cache_file_name).unwrap().read_to_end(&mut cache).unwrap();
+        let len = cache.len();
+        File::create(&cache_file_name).unwrap().write_all(&cache[..len - 3]).unwrap();
+        
+        assert_eq!(run(&file_name, &cache_file_name), "12xy+");
+    }
+}
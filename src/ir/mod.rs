@@ -1,4 +1,7 @@
 pub mod builder;
+pub mod register;
+mod cache;
+mod optimize;
 
 use std::fs::File;
 use syntax::{Name, Span};
@@ -15,7 +18,8 @@ use ::{JsResult, JsError};
 use std::rc::Rc;
 use std::fmt::Write;
 use std::io::Read;
-use rt::JsPreferredType;
+use rt::{JsPreferredType, PropertyCache};
+use std::cell::Cell;
 use std::i32;
 
 #[derive(Copy, Clone)]
@@ -35,6 +39,8 @@ pub struct IrContext {
     interner: StrInterner,
     ast: AstContext,
     functions: Vec<Option<Rc<builder::Block>>>,
+    optimize: bool,
+    registers: bool,
     // TODO #58: Remove
     last_printed: usize
 }
@@ -48,6 +54,19 @@ pub struct IrFunction {
     pub span: Span
 }
 
+fn read_file(file_name: &str) -> JsResult<String> {
+    let mut js = String::new();
+    let mut file = match File::open(file_name) {
+        Ok(ok) => ok,
+        Err(err) => return Err(JsError::Io(err))
+    };
+    if let Err(err) = file.read_to_string(&mut js) {
+        return Err(JsError::Io(err));
+    }
+    
+    Ok(js)
+}
+
 struct LhsRef<'a> {
     expr: &'a Expr
 }
@@ -58,6 +77,8 @@ impl IrContext {
             interner: name::new_interner(),
             functions: Vec::new(),
             ast: AstContext::new(),
+            optimize: true,
+            registers: true,
             last_printed: 0
         }
     }
@@ -66,15 +87,19 @@ impl IrContext {
         &self.interner
     }
     
+    /// Sets whether the IR of functions built from here on is optimized.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+    
+    /// Sets whether register instructions are compiled for the IR of
+    /// functions built from here on.
+    pub fn set_registers(&mut self, registers: bool) {
+        self.registers = registers;
+    }
+    
     pub fn parse_file(&mut self, file_name: &str, strict: bool, privileged: bool) -> JsResult<FunctionRef> {
-        let mut js = String::new();
-        let mut file = match File::open(file_name) {
-            Ok(ok) => ok,
-            Err(err) => return Err(JsError::Io(err))
-        };
-        if let Err(err) = file.read_to_string(&mut js) {
-            return Err(JsError::Io(err));
-        }
+        let js = try!(read_file(file_name));
         
         self.build_ir(&mut StringReader::new(file_name, &js), strict, ParseMode::Normal, privileged)
     }
@@ -123,10 +148,14 @@ impl IrContext {
 
         let function = &self.ast.functions[function_ref.usize()];
         
-        let block = {
+        let mut block = {
             let block_state = &function.block.state.borrow();
             let mut generator = IrGenerator::new(self, block_state, mode);
             
+            // The prolog and the implicit return are attributed to the function.
+            
+            generator.ir.set_span(function.span);
+            
             // Build the prolog to move the arguments into the scope and to
             // declare variables with undefined.
             
@@ -198,11 +227,32 @@ impl IrContext {
             generator.ir.build()
         };
         
+        if self.optimize {
+            optimize::optimize(&mut block);
+        }
+        
+        self.prepare_block(&mut block);
+        
         self.functions[function_ref.usize()] = Some(Rc::new(block));
         
         Ok(())
     }
     
+    // Creates the data the interpreter keeps next to the IR of a block.
+    fn prepare_block(&self, block: &mut builder::Block) {
+        if self.registers {
+            block.registers = register::compile(block);
+        }
+        
+        block.caches = vec![Cell::new(PropertyCache::new()); block.ir.len()];
+    }
+    
+    /// Gets the number of functions parsed so far. Function references
+    /// are indexes below this number.
+    pub fn function_count(&self) -> usize {
+        self.ast.functions.len()
+    }
+    
     pub fn get_function(&self, function_ref: FunctionRef) -> IrFunction {
         let function = &self.ast.functions[function_ref.usize()];
         
@@ -218,6 +268,32 @@ impl IrContext {
         }
     }
     
+    /// Gets the arguments of the function that stay on the stack, i.e. that
+    /// are not moved into a scope, with their index.
+    pub fn get_stack_args(&self, function_ref: FunctionRef) -> Vec<(Name, u32)> {
+        let function = &self.ast.functions[function_ref.usize()];
+        
+        let state = function.block.state.borrow();
+        
+        state.slots.iter()
+            .filter(|slot| slot.state == SlotState::Local)
+            .filter_map(|slot| slot.arg.map(|arg| (slot.name, arg)))
+            .collect()
+    }
+    
+    /// Gets the location of the instruction at the offset in the IR of the
+    /// function. When the IR of the function has not been built, the location
+    /// of the function itself is returned.
+    pub fn get_span(&self, function_ref: FunctionRef, offset: usize) -> Span {
+        if let Some(ref block) = self.functions[function_ref.usize()] {
+            if let Some(span) = block.span_at(offset) {
+                return span;
+            }
+        }
+        
+        self.ast.functions[function_ref.usize()].span
+    }
+    
     pub fn print_ir(&mut self, ir: &mut String) -> JsResult<()> {
         for i in self.last_printed..self.functions.len() {
             try!(self.build_function_ir(FunctionRef(i as u32), ParseMode::Normal));
@@ -387,7 +463,7 @@ impl<'a> IrGenerator<'a> {
         };
         
         for stmt in &block.stmts {
-            try!(self.emit_stmt(&stmt));
+            try!(self.emit_located_stmt(stmt));
         }
         
         if let Some(break_target) = break_target {
@@ -416,15 +492,32 @@ impl<'a> IrGenerator<'a> {
     
     fn emit_block(&mut self, block: &'a Block) -> JsResult<()> {
         for stmt in &block.stmts {
-            try!(self.emit_stmt(&stmt));
+            try!(self.emit_located_stmt(stmt));
+        }
+        
+        Ok(())
+    }
+    
+    fn emit_stmts(&mut self, stmts: &'a Vec<Stmt>) -> JsResult<()> {
+        for stmt in stmts {
+            try!(self.emit_located_stmt(stmt));
         }
         
         Ok(())
     }
     
-    fn emit_stmts(&mut self, stmts: &'a Vec<Item>) -> JsResult<()> {
-        for ref stmt in stmts {
-            try!(self.emit_stmt(stmt));
+    // Emits the statement with its location. The location of the enclosing
+    // statement is restored afterwards, so e.g. the update expression of a
+    // for loop is not attributed to the last statement of its body.
+    fn emit_located_stmt(&mut self, stmt: &'a Stmt) -> JsResult<()> {
+        let outer = self.ir.span();
+        
+        self.ir.set_span(stmt.span);
+        
+        try!(self.emit_stmt(&stmt.item));
+        
+        if let Some(outer) = outer {
+            self.ir.set_span(outer);
         }
         
         Ok(())
@@ -0,0 +1,515 @@
+use ir::builder::{Block, Ir, Label};
+use std::i32;
+
+// Every pass can enable further optimizations, e.g. folding constants can
+// leave a conditional jump on a constant. Passes are repeated until nothing
+// changes, up to this limit.
+const MAX_PASSES : usize = 8;
+
+// Limit on the number of jumps followed when threading jumps, to stop on
+// jumps that loop.
+const MAX_THREAD : usize = 8;
+
+/// Optimizes the IR of a block. Constants are folded, redundant conversions
+/// and stores to locals that are never loaded are removed, jumps to jumps
+/// are threaded and unreachable instructions are removed.
+pub fn optimize(block: &mut Block) {
+    for _ in 0..MAX_PASSES {
+        let removed = {
+            let mut optimizer = Optimizer::new(block);
+            
+            optimizer.thread_jumps();
+            optimizer.remove_dead_stores();
+            optimizer.peephole();
+            optimizer.remove_unreachable();
+            
+            if !optimizer.changed {
+                break;
+            }
+            
+            optimizer.removed
+        };
+        
+        block.remove(&removed);
+    }
+}
+
+struct Optimizer<'a> {
+    block: &'a mut Block,
+    // Instructions that can be reached other than from the previous
+    // instruction, i.e. jump targets and the boundaries of try/catch blocks.
+    // Instruction sequences are only rewritten when they do not contain a
+    // target, except for the first instruction.
+    targets: Vec<bool>,
+    removed: Vec<bool>,
+    changed: bool
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(block: &'a mut Block) -> Optimizer<'a> {
+        let mut targets = vec![false; block.ir.len() + 1];
+        
+        for ir in &block.ir {
+            if let Some(label) = ir.label() {
+                targets[label.offset()] = true;
+            }
+        }
+        
+        for try_catch in &block.try_catches {
+            let ranges = Some(try_catch.try).into_iter().chain(try_catch.catch).chain(try_catch.finally);
+            
+            for range in ranges {
+                targets[range.start().offset()] = true;
+                targets[range.end().offset()] = true;
+            }
+        }
+        
+        let removed = vec![false; block.ir.len()];
+        
+        Optimizer {
+            block: block,
+            targets: targets,
+            removed: removed,
+            changed: false
+        }
+    }
+    
+    // Gets the instruction that follows the instruction at the offset,
+    // when it can only be reached from that instruction.
+    fn next(&self, offset: usize) -> Option<usize> {
+        let mut next = offset + 1;
+        
+        while next < self.removed.len() && self.removed[next] {
+            next += 1;
+        }
+        
+        if next < self.removed.len() && !self.targets[next] {
+            Some(next)
+        } else {
+            None
+        }
+    }
+    
+    fn replace(&mut self, offset: usize, ir: Ir) {
+        self.block.ir[offset] = ir;
+        self.changed = true;
+    }
+    
+    fn remove(&mut self, offset: usize) {
+        self.removed[offset] = true;
+        self.changed = true;
+    }
+    
+    // Jumps to an unconditional jump are replaced by a jump to its target.
+    fn thread_jumps(&mut self) {
+        for offset in 0..self.block.ir.len() {
+            let ir = self.block.ir[offset];
+            
+            // Leave instructions are not threaded because their target
+            // decides which finally blocks run.
+            
+            let mut label = match ir {
+                Ir::Leave(..) => continue,
+                ir => match ir.label() {
+                    Some(label) => label,
+                    None => continue
+                }
+            };
+            
+            for _ in 0..MAX_THREAD {
+                match self.block.ir.get(label.offset()) {
+                    Some(&Ir::Jump(target)) if target.offset() != label.offset() => label = target,
+                    _ => break
+                }
+            }
+            
+            if label.offset() != ir.label().unwrap().offset() {
+                self.replace(offset, with_label(ir, label));
+            }
+        }
+    }
+    
+    // Stores to locals that are never loaded are replaced by a pop.
+    fn remove_dead_stores(&mut self) {
+        let mut loaded = vec![false; self.block.locals.len()];
+        
+        for ir in &self.block.ir {
+            match *ir {
+                Ir::LoadLocal(local) |
+                Ir::CurrentIter(local) |
+                Ir::EndIter(local) |
+                Ir::IntoIter(local) |
+                Ir::NextIter(local, _)
+                    => loaded[local.offset()] = true,
+                _ => {}
+            }
+        }
+        
+        for offset in 0..self.block.ir.len() {
+            if let Ir::StoreLocal(local) = self.block.ir[offset] {
+                if !loaded[local.offset()] {
+                    self.replace(offset, Ir::Pop);
+                }
+            }
+        }
+    }
+    
+    fn peephole(&mut self) {
+        for offset in 0..self.block.ir.len() {
+            if self.removed[offset] {
+                continue;
+            }
+            
+            let next = match self.next(offset) {
+                Some(next) => next,
+                None => continue
+            };
+            
+            let ir = self.block.ir[offset];
+            let next_ir = self.block.ir[next];
+            
+            // Fold binary operators on two constants.
+            
+            if let Some(last) = self.next(next) {
+                if let Some(folded) = fold_binary(ir, next_ir, self.block.ir[last]) {
+                    self.replace(offset, folded);
+                    self.remove(next);
+                    self.remove(last);
+                    continue;
+                }
+            }
+            
+            match (ir, next_ir) {
+                // Fold unary operators on a constant.
+                
+                (_, Ir::Negative) if number(ir).is_some() => {
+                    self.replace(offset, load_number(-number(ir).unwrap()));
+                    self.remove(next);
+                }
+                (Ir::LoadI32(value), Ir::BitNot) => {
+                    self.replace(offset, Ir::LoadI32(!value));
+                    self.remove(next);
+                }
+                (Ir::LoadTrue, Ir::Not) => {
+                    self.replace(offset, Ir::LoadFalse);
+                    self.remove(next);
+                }
+                (Ir::LoadFalse, Ir::Not) => {
+                    self.replace(offset, Ir::LoadTrue);
+                    self.remove(next);
+                }
+                (Ir::Not, Ir::Not) => {
+                    self.replace(offset, Ir::ToBoolean);
+                    self.remove(next);
+                }
+                
+                // Remove conversions of values that already have the
+                // target type.
+                
+                (_, Ir::ToNumber) |
+                (_, Ir::Positive) |
+                (_, Ir::ToPrimitive(..)) if is_number(ir) => self.remove(next),
+                (_, Ir::ToBoolean) if is_boolean(ir) => self.remove(next),
+                (_, Ir::ToString) if is_string(ir) => self.remove(next),
+                
+                // Conditional jumps convert the condition to a boolean
+                // themselves and a negated condition is a jump on the
+                // opposite condition.
+                
+                (Ir::ToBoolean, Ir::JumpTrue(..)) |
+                (Ir::ToBoolean, Ir::JumpFalse(..)) => self.remove(offset),
+                (Ir::Not, Ir::JumpTrue(label)) => {
+                    self.remove(offset);
+                    self.replace(next, Ir::JumpFalse(label));
+                }
+                (Ir::Not, Ir::JumpFalse(label)) => {
+                    self.remove(offset);
+                    self.replace(next, Ir::JumpTrue(label));
+                }
+                
+                // Jumps on a constant condition are either always or never
+                // taken.
+                
+                (Ir::LoadTrue, Ir::JumpTrue(label)) |
+                (Ir::LoadFalse, Ir::JumpFalse(label)) => {
+                    self.remove(offset);
+                    self.replace(next, Ir::Jump(label));
+                }
+                (Ir::LoadTrue, Ir::JumpFalse(..)) |
+                (Ir::LoadFalse, Ir::JumpTrue(..)) => {
+                    self.remove(offset);
+                    self.remove(next);
+                }
+                
+                // Values that are pushed and popped right away.
+                
+                (Ir::Dup, Ir::Pop) => {
+                    self.remove(offset);
+                    self.remove(next);
+                }
+                (_, Ir::Pop) if is_pure(ir) => {
+                    self.remove(offset);
+                    self.remove(next);
+                }
+                
+                _ => {}
+            }
+        }
+        
+        // Jumps to the next instruction.
+        
+        for offset in 0..self.block.ir.len() {
+            if self.removed[offset] {
+                continue;
+            }
+            
+            let mut next = offset + 1;
+            while next < self.removed.len() && self.removed[next] {
+                next += 1;
+            }
+            
+            match self.block.ir[offset] {
+                Ir::Jump(label) if label.offset() == next => self.remove(offset),
+                Ir::JumpTrue(label) | Ir::JumpFalse(label) if label.offset() == next => self.replace(offset, Ir::Pop),
+                _ => {}
+            }
+        }
+    }
+    
+    // Removes the instructions after an unconditional jump, return or throw
+    // up to the next target.
+    fn remove_unreachable(&mut self) {
+        let mut reachable = true;
+        
+        for offset in 0..self.block.ir.len() {
+            if self.targets[offset] {
+                reachable = true;
+            }
+            
+            if self.removed[offset] {
+                continue;
+            }
+            
+            if !reachable {
+                self.remove(offset);
+                continue;
+            }
+            
+            match self.block.ir[offset] {
+                Ir::Jump(..) | Ir::Leave(..) | Ir::Return | Ir::Throw => reachable = false,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn with_label(ir: Ir, label: Label) -> Ir {
+    match ir {
+        Ir::Jump(..) => Ir::Jump(label),
+        Ir::JumpStrictEq(..) => Ir::JumpStrictEq(label),
+        Ir::JumpFalse(..) => Ir::JumpFalse(label),
+        Ir::JumpTrue(..) => Ir::JumpTrue(label),
+        Ir::Leave(..) => Ir::Leave(label),
+        Ir::NextIter(local, _) => Ir::NextIter(local, label),
+        _ => panic!("expected a jump")
+    }
+}
+
+fn number(ir: Ir) -> Option<f64> {
+    match ir {
+        Ir::LoadI32(value) => Some(value as f64),
+        Ir::LoadI64(value) => Some(value as f64),
+        Ir::LoadF64(value) => Some(value),
+        _ => None
+    }
+}
+
+// Loads the number as an i32 when that does not lose information; -0 must
+// stay a float.
+fn load_number(value: f64) -> Ir {
+    if value.trunc() == value && value >= i32::MIN as f64 && value <= i32::MAX as f64 && !(value == 0.0 && value.is_sign_negative()) {
+        Ir::LoadI32(value as i32)
+    } else {
+        Ir::LoadF64(value)
+    }
+}
+
+fn load_bool(value: bool) -> Ir {
+    if value { Ir::LoadTrue } else { Ir::LoadFalse }
+}
+
+fn fold_binary(lhs: Ir, rhs: Ir, op: Ir) -> Option<Ir> {
+    // Bitwise operators are only folded on i32 operands, so no conversion
+    // is needed.
+    
+    if let (Ir::LoadI32(lhs), Ir::LoadI32(rhs)) = (lhs, rhs) {
+        let shift = rhs as u32 & 0x1f;
+        
+        match op {
+            Ir::BitAnd => return Some(Ir::LoadI32(lhs & rhs)),
+            Ir::BitOr => return Some(Ir::LoadI32(lhs | rhs)),
+            Ir::BitXOr => return Some(Ir::LoadI32(lhs ^ rhs)),
+            Ir::Lsh => return Some(Ir::LoadI32(lhs << shift)),
+            Ir::Rsh => return Some(Ir::LoadI32(lhs >> shift)),
+            Ir::RshZeroFill => return Some(load_number(((lhs as u32) >> shift) as f64)),
+            _ => {}
+        }
+    }
+    
+    let (lhs, rhs) = match (number(lhs), number(rhs)) {
+        (Some(lhs), Some(rhs)) => (lhs, rhs),
+        _ => return None
+    };
+    
+    let result = match op {
+        Ir::Add => load_number(lhs + rhs),
+        Ir::Subtract => load_number(lhs - rhs),
+        Ir::Multiply => load_number(lhs * rhs),
+        Ir::Divide => load_number(lhs / rhs),
+        Ir::Modulus => load_number(lhs % rhs),
+        Ir::Lt => load_bool(lhs < rhs),
+        Ir::Gt => load_bool(lhs > rhs),
+        Ir::Le => load_bool(lhs <= rhs),
+        Ir::Ge => load_bool(lhs >= rhs),
+        Ir::Eq | Ir::StrictEq => load_bool(lhs == rhs),
+        Ir::Ne | Ir::StrictNe => load_bool(lhs != rhs),
+        _ => return None
+    };
+    
+    Some(result)
+}
+
+fn is_number(ir: Ir) -> bool {
+    match ir {
+        Ir::LoadI32(..) | Ir::LoadI64(..) | Ir::LoadF64(..) |
+        Ir::Subtract | Ir::Multiply | Ir::Divide | Ir::Modulus |
+        Ir::Negative | Ir::Positive | Ir::ToNumber |
+        Ir::BitAnd | Ir::BitOr | Ir::BitXOr | Ir::BitNot |
+        Ir::Lsh | Ir::Rsh | Ir::RshZeroFill |
+        Ir::ToInteger | Ir::ToInt32 | Ir::ToUInt32 | Ir::ToUInt16
+            => true,
+        _ => false
+    }
+}
+
+fn is_boolean(ir: Ir) -> bool {
+    match ir {
+        Ir::LoadTrue | Ir::LoadFalse | Ir::Not | Ir::ToBoolean |
+        Ir::Eq | Ir::Ne | Ir::StrictEq | Ir::StrictNe |
+        Ir::Lt | Ir::Gt | Ir::Le | Ir::Ge |
+        Ir::In | Ir::InstanceOf
+            => true,
+        _ => false
+    }
+}
+
+fn is_string(ir: Ir) -> bool {
+    match ir {
+        Ir::LoadString(..) | Ir::ToString | Ir::Typeof | Ir::TypeofIndex | Ir::TypeofName(..) => true,
+        _ => false
+    }
+}
+
+// Instructions that only push a value and cannot throw.
+fn is_pure(ir: Ir) -> bool {
+    match ir {
+        Ir::LoadI32(..) | Ir::LoadI64(..) | Ir::LoadF64(..) |
+        Ir::LoadTrue | Ir::LoadFalse | Ir::LoadNull | Ir::LoadUndefined |
+        Ir::LoadString(..) | Ir::LoadLocal(..) | Ir::LoadParam(..) | Ir::LoadThis
+            => true,
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ir::IrContext;
+    use rt::{JsEnvBuilder, JsItem};
+    use syntax::parser::ParseMode;
+    
+    // Gets the instructions of function f without the offsets.
+    fn instructions(js: &str, optimize: bool) -> Vec<String> {
+        let mut ctx = IrContext::new();
+        ctx.set_optimize(optimize);
+        ctx.parse_string(js, false, ParseMode::Normal, false).ok().unwrap();
+        
+        let mut ir = String::new();
+        ctx.print_ir(&mut ir).ok().unwrap();
+        
+        ir.split("\n\n(global)").find(|function| function.contains("function 'f'")).unwrap()
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| line.starts_with("L") && line.contains(": "))
+            .map(|line| line[line.find(": ").unwrap() + 2..].to_string())
+            .collect()
+    }
+    
+    fn contains(ir: &[String], instruction: &str) -> bool {
+        ir.iter().any(|ir| ir == instruction)
+    }
+    
+    #[test]
+    fn fold_constants() {
+        let ir = instructions("function f() { return 1 + 2 * 3; }", true);
+        assert!(contains(&ir, "ld.i32 7"));
+        assert!(!contains(&ir, "add"));
+        assert!(!contains(&ir, "mul"));
+        
+        let ir = instructions("function f() { return 1 + 2 * 3; }", false);
+        assert!(contains(&ir, "add"));
+        assert!(contains(&ir, "mul"));
+        
+        let ir = instructions("function f() { return 0 * -1; }", true);
+        assert!(contains(&ir, "ld.f64 -0"));
+        
+        let ir = instructions("function f() { return 7 / 2; }", true);
+        assert!(contains(&ir, "ld.f64 3.5"));
+    }
+    
+    #[test]
+    fn remove_conversions() {
+        let ir = instructions("function f() { return +1; }", true);
+        assert_eq!(ir, vec!["ld.i32 1", "ret"]);
+        
+        let ir = instructions("function f(a) { return !!a; }", true);
+        assert!(contains(&ir, "cast.bool"));
+        assert!(!contains(&ir, "not"));
+    }
+    
+    #[test]
+    fn remove_dead_stores() {
+        let ir = instructions("function f() { var a = 1; return 2; }", true);
+        assert_eq!(ir, vec!["ld.i32 2", "ret"]);
+    }
+    
+    #[test]
+    fn thread_jumps() {
+        let ir = instructions("function f(a) { while (true) { if (a) { break; } } }", true);
+        assert!(!contains(&ir, "ld.true"));
+        
+        for instruction in &ir {
+            if instruction.starts_with("jmp") {
+                let target = instruction[instruction.find('L').unwrap() + 1..].parse::<usize>().unwrap();
+                assert!(!ir[target].starts_with("jmp "));
+            }
+        }
+    }
+    
+    #[test]
+    fn same_results() {
+        let mut optimized = JsEnvBuilder::new().build().ok().unwrap();
+        let mut unoptimized = JsEnvBuilder::new().optimize(false).build().ok().unwrap();
+        
+        let tests = [
+            "1 + 2 * 3", "1 / (0 * -1)", "7 % -3", "-5 >>> 1", "1 << 40", "!!0", "+'1' + 1",
+            "(function (a) { var b = a; while (true) { if (a-- < 0) { break; } } return b + a; })(3)"
+        ];
+        
+        for js in &tests {
+            let expected = unoptimized.eval(js).ok().unwrap().as_value(&unoptimized).to_string(&mut unoptimized).ok().unwrap().to_string();
+            let actual = optimized.eval(js).ok().unwrap().as_value(&optimized).to_string(&mut optimized).ok().unwrap().to_string();
+            
+            assert_eq!(actual, expected);
+        }
+    }
+}
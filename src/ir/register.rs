@@ -0,0 +1,335 @@
+// Register superinstructions for hot instruction sequences.
+//
+// This is not a register-based bytecode: the stack IR stays the format that
+// is compiled and interpreted. On top of it, short sequences of arithmetic,
+// comparisons, increments and moves between locals are fused into a single
+// superinstruction that addresses locals, parameters and constants directly,
+// instead of moving every operand through the value stack and creating a
+// stack frame and a local GC scope per instruction.
+//
+// A superinstruction is stored at the offset of the first instruction of the
+// sequence it replaces, together with the length of the sequence. Offsets of
+// the IR stay valid, so jump targets, try/catch blocks and the line table do
+// not change, and everything else, e.g. calls and property access, keeps
+// running from the stack IR.
+//
+// A sequence never contains a jump target, try/catch boundary or line table
+// entry other than at its start, so it can only be entered at its start.
+
+use ir::builder::{Block, Ir, Label, Local};
+
+/// Operand of a register instruction.
+#[derive(Copy, Clone)]
+pub enum Operand {
+    Local(Local),
+    Param(u32),
+    Number(f64),
+    // The value on top of the stack, which is popped.
+    Stack
+}
+
+/// Destination of the result of a register instruction.
+#[derive(Copy, Clone)]
+pub enum Target {
+    Local(Local),
+    Param(u32),
+    // The result is pushed onto the stack.
+    Stack
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulus,
+    BitAnd,
+    BitOr,
+    BitXOr,
+    Lsh,
+    Rsh,
+    RshZeroFill
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompareOp {
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    StrictEq,
+    StrictNe
+}
+
+#[derive(Copy, Clone)]
+pub enum RegisterOp {
+    Move(Target, Operand),
+    Binary(BinaryOp, Target, Operand, Operand),
+    // Stores the operand converted to a number plus the delta, i.e. ++ and --.
+    Increment(Target, Operand, f64),
+    Compare(CompareOp, Target, Operand, Operand),
+    // Jumps to the label when the result of the comparison matches.
+    CompareJump(CompareOp, Operand, Operand, bool, Label)
+}
+
+#[derive(Copy, Clone)]
+pub struct RegisterInstruction {
+    pub op: RegisterOp,
+    // Number of IR instructions the register instruction replaces.
+    pub len: usize
+}
+
+/// Compiles the register instructions of the block. The result has an entry
+/// for every IR instruction.
+pub fn compile(block: &Block) -> Vec<Option<RegisterInstruction>> {
+    let ir = &block.ir[..];
+    
+    let mut boundaries = vec![false; ir.len() + 1];
+    
+    for instruction in ir {
+        if let Some(label) = instruction.label() {
+            boundaries[label.offset()] = true;
+        }
+    }
+    
+    for try_catch in &block.try_catches {
+        let ranges = Some(try_catch.try).into_iter().chain(try_catch.catch).chain(try_catch.finally);
+        
+        for range in ranges {
+            boundaries[range.start().offset()] = true;
+            boundaries[range.end().offset()] = true;
+        }
+    }
+    
+    for &(offset, _) in &block.spans {
+        boundaries[offset.offset()] = true;
+    }
+    
+    let compiler = Compiler {
+        ir: ir,
+        boundaries: &boundaries
+    };
+    
+    let mut registers = vec![None; ir.len()];
+    let mut offset = 0;
+    
+    while offset < ir.len() {
+        match compiler.compile(offset) {
+            Some(instruction) => {
+                registers[offset] = Some(instruction);
+                offset += instruction.len;
+            }
+            None => offset += 1
+        }
+    }
+    
+    registers
+}
+
+struct Compiler<'a> {
+    ir: &'a [Ir],
+    boundaries: &'a [bool]
+}
+
+impl<'a> Compiler<'a> {
+    // Gets the instruction at the offset when it can be part of a sequence
+    // that starts before it.
+    fn at(&self, offset: usize) -> Option<Ir> {
+        if offset < self.ir.len() && !self.boundaries[offset] {
+            Some(self.ir[offset])
+        } else {
+            None
+        }
+    }
+    
+    // Gets the target of the store at the offset and the number of
+    // instructions it takes; without a store the result goes on the stack.
+    fn target(&self, offset: usize) -> (Target, usize) {
+        match self.at(offset) {
+            Some(Ir::StoreLocal(local)) => (Target::Local(local), 1),
+            Some(Ir::StoreParam(index)) => (Target::Param(index), 1),
+            _ => (Target::Stack, 0)
+        }
+    }
+    
+    fn compile(&self, offset: usize) -> Option<RegisterInstruction> {
+        let first = match operand(self.ir[offset]) {
+            Some(operand) => operand,
+            None => return None
+        };
+        
+        // load; ToNumber; ld.i32 1; add|sub; [store]
+        
+        if let (Some(Ir::ToNumber), Some(Ir::LoadI32(1))) = (self.at(offset + 1), self.at(offset + 2)) {
+            let delta = match self.at(offset + 3) {
+                Some(Ir::Add) => Some(1.0),
+                Some(Ir::Subtract) => Some(-1.0),
+                _ => None
+            };
+            
+            if let Some(delta) = delta {
+                let (target, len) = self.target(offset + 4);
+                
+                return Some(RegisterInstruction {
+                    op: RegisterOp::Increment(target, first, delta),
+                    len: 4 + len
+                });
+            }
+        }
+        
+        // load; store
+        
+        if let (target, 1) = self.target(offset + 1) {
+            return Some(RegisterInstruction {
+                op: RegisterOp::Move(target, first),
+                len: 2
+            });
+        }
+        
+        // [load]; load; op; [store|jump]. Without the first load, the left
+        // hand side is the result of the preceding instructions.
+        
+        let (lhs, rhs, op_offset) = match self.at(offset + 1).and_then(operand) {
+            Some(rhs) => (first, rhs, offset + 2),
+            None => (Operand::Stack, first, offset + 1)
+        };
+        
+        let op = match self.at(op_offset) {
+            Some(op) => op,
+            None => return None
+        };
+        
+        if let Some(op) = binary_op(op) {
+            let (target, len) = self.target(op_offset + 1);
+            
+            return Some(RegisterInstruction {
+                op: RegisterOp::Binary(op, target, lhs, rhs),
+                len: op_offset + 1 + len - offset
+            });
+        }
+        
+        if let Some(op) = compare_op(op) {
+            let jump = match self.at(op_offset + 1) {
+                Some(Ir::JumpTrue(label)) => Some((true, label)),
+                Some(Ir::JumpFalse(label)) => Some((false, label)),
+                _ => None
+            };
+            
+            if let Some((jump_if, label)) = jump {
+                return Some(RegisterInstruction {
+                    op: RegisterOp::CompareJump(op, lhs, rhs, jump_if, label),
+                    len: op_offset + 2 - offset
+                });
+            }
+            
+            let (target, len) = self.target(op_offset + 1);
+            
+            return Some(RegisterInstruction {
+                op: RegisterOp::Compare(op, target, lhs, rhs),
+                len: op_offset + 1 + len - offset
+            });
+        }
+        
+        None
+    }
+}
+
+fn operand(ir: Ir) -> Option<Operand> {
+    match ir {
+        Ir::LoadLocal(local) => Some(Operand::Local(local)),
+        Ir::LoadParam(index) => Some(Operand::Param(index)),
+        Ir::LoadI32(value) => Some(Operand::Number(value as f64)),
+        Ir::LoadI64(value) => Some(Operand::Number(value as f64)),
+        Ir::LoadF64(value) => Some(Operand::Number(value)),
+        _ => None
+    }
+}
+
+fn binary_op(ir: Ir) -> Option<BinaryOp> {
+    match ir {
+        Ir::Add => Some(BinaryOp::Add),
+        Ir::Subtract => Some(BinaryOp::Subtract),
+        Ir::Multiply => Some(BinaryOp::Multiply),
+        Ir::Divide => Some(BinaryOp::Divide),
+        Ir::Modulus => Some(BinaryOp::Modulus),
+        Ir::BitAnd => Some(BinaryOp::BitAnd),
+        Ir::BitOr => Some(BinaryOp::BitOr),
+        Ir::BitXOr => Some(BinaryOp::BitXOr),
+        Ir::Lsh => Some(BinaryOp::Lsh),
+        Ir::Rsh => Some(BinaryOp::Rsh),
+        Ir::RshZeroFill => Some(BinaryOp::RshZeroFill),
+        _ => None
+    }
+}
+
+fn compare_op(ir: Ir) -> Option<CompareOp> {
+    match ir {
+        Ir::Lt => Some(CompareOp::Lt),
+        Ir::Gt => Some(CompareOp::Gt),
+        Ir::Le => Some(CompareOp::Le),
+        Ir::Ge => Some(CompareOp::Ge),
+        Ir::Eq => Some(CompareOp::Eq),
+        Ir::Ne => Some(CompareOp::Ne),
+        Ir::StrictEq => Some(CompareOp::StrictEq),
+        Ir::StrictNe => Some(CompareOp::StrictNe),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ir::IrContext;
+    use rt::{JsEnvBuilder, JsItem};
+    use syntax::ast::FunctionRef;
+    use syntax::parser::ParseMode;
+    
+    // Gets the register instructions of function f.
+    fn registers(js: &str) -> Vec<RegisterOp> {
+        let mut ctx = IrContext::new();
+        ctx.parse_string(js, false, ParseMode::Normal, false).ok().unwrap();
+        
+        let function_ref = (0..ctx.function_count())
+            .map(|i| FunctionRef(i as u32))
+            .find(|&function_ref| ctx.get_function(function_ref).name.map_or(false, |name| &*ctx.interner().get(name) == "f"))
+            .unwrap();
+        
+        let block = ctx.get_function_ir(function_ref).ok().unwrap();
+        
+        block.registers.iter().filter_map(|register| register.map(|register| register.op)).collect()
+    }
+    
+    #[test]
+    fn compile_loop() {
+        let ops = registers("function f(n) { var j = 0; for (var i = 0; i < n; i++) { j = j + i * 2; } return j; }");
+        
+        assert!(ops.iter().any(|op| match *op { RegisterOp::CompareJump(CompareOp::Lt, Operand::Local(..), Operand::Param(0), ..) => true, _ => false }));
+        assert!(ops.iter().any(|op| match *op { RegisterOp::Increment(Target::Local(..), Operand::Local(..), delta) => delta == 1.0, _ => false }));
+        assert!(ops.iter().any(|op| match *op { RegisterOp::Binary(BinaryOp::Multiply, Target::Stack, Operand::Local(..), Operand::Number(value)) => value == 2.0, _ => false }));
+    }
+    
+    #[test]
+    fn same_results() {
+        let mut registers = JsEnvBuilder::new().build().ok().unwrap();
+        let mut stack = JsEnvBuilder::new().registers(false).build().ok().unwrap();
+        
+        let tests = [
+            "(function (n) { var j = 0; for (var i = 0; i < n; i++) { j = j + i * 2; } return j; })(10)",
+            "(function (a, b) { a = a + b; return a; })('1', 2)",
+            "(function (a) { var b = a; b--; return b < a; })('x')",
+            "(function (a, b) { if (a == b) { return a === b; } return a != b; })(1, '1')",
+            "(function (a) { var b = a % 3; var c = a >>> 1; return b + c | 0; })(-7)",
+            "(function (a) { var b = { valueOf: function () { return a; } }; return b - 1; })(5)"
+        ];
+        
+        for js in &tests {
+            let expected = stack.eval(js).ok().unwrap().as_value(&stack).to_string(&mut stack).ok().unwrap().to_string();
+            let actual = registers.eval(js).ok().unwrap().as_value(&registers).to_string(&mut registers).ok().unwrap().to_string();
+            
+            assert_eq!(actual, expected);
+        }
+    }
+}
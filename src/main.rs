@@ -1,5 +1,305 @@
 extern crate rjs;
 
+use rjs::rt::{JsEnv, JsEnvBuilder, JsItem, JsValue, JsString};
+use rjs::gc::{GcOpts, GcStrategy};
+use rjs::JsResult;
+use rjs::contrib::test262::Test262Runner;
+use rjs::contrib::repl::Repl;
+use rjs::contrib::dap::DapServer;
+use std::env;
+use std::fs::File;
+use std::process;
+use std::io::prelude::*;
+use std::io;
+
+const USAGE : &'static str = "\
+Usage: rjs [options] <file.js> [args...]
+       rjs [options] -e <code>
+       rjs [options] repl
+       rjs [options] debug <file.js>
+       rjs [options] test262
+
+Options:
+    --strict                  Run the script in strict mode
+    -e <code>                 Evaluate the code instead of running a file
+    --profile <file>          Profile the script and write the profile to the
+                              file; a .cpuprofile file gets the Chrome format,
+                              any other file gets collapsed stacks
+    --profile-interval <n>    Sample every n instructions (default 1000)
+    --coverage <file>         Collect coverage and write it to the file; a
+                              .json file gets a summary per file, any other
+                              file gets an LCOV tracefile
+    --cache <file>            Load the compiled script from the file, or
+                              write it when the script changed
+    --gc <strategy>           Use the copying (default), mark-sweep or
+                              generational garbage collector
+    -h, --help                Print this message
+";
+
+enum Command {
+    Run(String, Vec<String>),
+    Eval(String),
+    Repl,
+    Debug(String),
+    Test262,
+    Help
+}
+
+struct Options {
+    strict: bool,
+    profile: Option<String>,
+    profile_interval: u32,
+    coverage: Option<String>,
+    cache: Option<String>,
+    gc: GcStrategy,
+    command: Command
+}
+
 fn main() {
-    rjs::contrib::test262::Test262Runner::run();
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            writeln!(io::stderr(), "rjs: {}\n\n{}", message, USAGE).ok();
+            process::exit(2);
+        }
+    };
+    
+    match options.command {
+        Command::Test262 => Test262Runner::run(options.gc),
+        Command::Help => print!("{}", USAGE),
+        Command::Repl => Repl::new(create_env(&options), options.strict).run(),
+        Command::Debug(file) => {
+            let input = Box::new(io::BufReader::new(io::stdin()));
+            let output = Box::new(io::stdout());
+            
+            match DapServer::new(&file, options.strict, input, output) {
+                Ok(mut server) => server.run(),
+                Err(error) => {
+                    writeln!(io::stderr(), "rjs: cannot create environment: {:?}", error).ok();
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Run(file, script_args) => {
+            let mut env = create_env(&options);
+            
+            start_instrumentation(&mut env, &options);
+            
+            let result = match set_script_args(&mut env, &script_args) {
+                Ok(_) => match options.cache {
+                    Some(ref cache) => env.run_cached_strict(&file, cache, options.strict),
+                    None => env.run_strict(&file, options.strict)
+                },
+                Err(error) => Err(error)
+            };
+            
+            finish_instrumentation(&mut env, &options);
+            exit_on_error(&mut env, result);
+        }
+        Command::Eval(js) => {
+            let mut env = create_env(&options);
+            
+            start_instrumentation(&mut env, &options);
+            
+            let result = match set_script_args(&mut env, &[]) {
+                Ok(_) => env.eval_strict(&js, options.strict),
+                Err(error) => Err(error)
+            };
+            
+            finish_instrumentation(&mut env, &options);
+            exit_on_error(&mut env, result);
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        strict: false,
+        profile: None,
+        profile_interval: 1000,
+        coverage: None,
+        cache: None,
+        gc: GcStrategy::Copying,
+        command: Command::Help
+    };
+    
+    let mut offset = 0;
+    
+    while offset < args.len() {
+        match &*args[offset] {
+            "--strict" => options.strict = true,
+            "--profile" => {
+                if offset + 1 >= args.len() {
+                    return Err("missing file for --profile".to_string());
+                }
+                
+                options.profile = Some(args[offset + 1].clone());
+                offset += 1;
+            }
+            "--profile-interval" => {
+                options.profile_interval = match args.get(offset + 1).and_then(|arg| arg.parse::<u32>().ok()) {
+                    Some(interval) if interval > 0 => interval,
+                    _ => return Err("--profile-interval expects a positive number".to_string())
+                };
+                offset += 1;
+            }
+            "--coverage" => {
+                if offset + 1 >= args.len() {
+                    return Err("missing file for --coverage".to_string());
+                }
+                
+                options.coverage = Some(args[offset + 1].clone());
+                offset += 1;
+            }
+            "--cache" => {
+                if offset + 1 >= args.len() {
+                    return Err("missing file for --cache".to_string());
+                }
+                
+                options.cache = Some(args[offset + 1].clone());
+                offset += 1;
+            }
+            "--gc" => {
+                options.gc = match args.get(offset + 1).map(|arg| &**arg) {
+                    Some("copying") => GcStrategy::Copying,
+                    Some("mark-sweep") => GcStrategy::MarkSweep,
+                    Some("generational") => GcStrategy::Generational,
+                    _ => return Err("--gc expects copying, mark-sweep or generational".to_string())
+                };
+                offset += 1;
+            }
+            "-h" | "--help" => return Ok(options),
+            "-e" => {
+                if offset + 1 >= args.len() {
+                    return Err("missing code for -e".to_string());
+                }
+                if offset + 2 < args.len() {
+                    return Err(format!("unexpected argument '{}'", args[offset + 2]));
+                }
+                
+                options.command = Command::Eval(args[offset + 1].clone());
+                return Ok(options);
+            }
+            "repl" if offset + 1 == args.len() => {
+                options.command = Command::Repl;
+                return Ok(options);
+            }
+            "debug" if offset + 2 == args.len() => {
+                options.command = Command::Debug(args[offset + 1].clone());
+                return Ok(options);
+            }
+            "test262" if offset + 1 == args.len() => {
+                options.command = Command::Test262;
+                return Ok(options);
+            }
+            arg if arg.starts_with("-") => return Err(format!("unknown option '{}'", arg)),
+            file => {
+                let script_args = args[offset + 1..].to_vec();
+                
+                options.command = Command::Run(file.to_string(), script_args);
+                return Ok(options);
+            }
+        }
+        
+        offset += 1;
+    }
+    
+    Err("missing script file".to_string())
+}
+
+fn create_env(options: &Options) -> JsEnv {
+    let mut opts = GcOpts::default();
+    opts.strategy = options.gc;
+    
+    match JsEnvBuilder::new().gc_opts(opts).build() {
+        Ok(env) => env,
+        Err(error) => {
+            writeln!(io::stderr(), "rjs: cannot create environment: {:?}", error).ok();
+            process::exit(1);
+        }
+    }
+}
+
+// The arguments after the script file are exposed to the script through
+// the global scriptArgs array.
+fn set_script_args(env: &mut JsEnv, args: &[String]) -> JsResult<()> {
+    let _scope = env.new_local_scope();
+    
+    let mut array = env.create_array();
+    
+    for i in 0..args.len() {
+        let index = try!(env.intern_value(JsValue::new_number(i as f64)));
+        let value = JsString::from_str(env, &args[i]).as_value();
+        try!(array.put(env, index, value, true));
+    }
+    
+    env.global().set("scriptArgs", array.as_value())
+}
+
+fn start_instrumentation(env: &mut JsEnv, options: &Options) {
+    if options.profile.is_some() {
+        env.start_profiler(options.profile_interval);
+    }
+    if options.coverage.is_some() {
+        env.start_coverage();
+    }
+}
+
+// Writes the profile and coverage requested on the command line.
+fn finish_instrumentation(env: &mut JsEnv, options: &Options) {
+    if let (&Some(ref file), Some(profile)) = (&options.profile, env.stop_profiler()) {
+        let result = File::create(file).and_then(|mut writer| {
+            if file.ends_with(".cpuprofile") {
+                profile.write_cpuprofile(&mut writer)
+            } else {
+                profile.write_collapsed(&mut writer)
+            }
+        });
+        
+        if let Err(error) = result {
+            writeln!(io::stderr(), "rjs: cannot write profile to {}: {}", file, error).ok();
+        }
+    }
+    
+    if let (&Some(ref file), Some(coverage)) = (&options.coverage, env.stop_coverage()) {
+        let result = File::create(file).and_then(|mut writer| {
+            if file.ends_with(".json") {
+                coverage.write_json_summary(&mut writer)
+            } else {
+                coverage.write_lcov(&mut writer)
+            }
+        });
+        
+        if let Err(error) = result {
+            writeln!(io::stderr(), "rjs: cannot write coverage to {}: {}", file, error).ok();
+        }
+    }
+}
+
+fn exit_on_error<T>(env: &mut JsEnv, result: JsResult<T>) {
+    if let Err(error) = result {
+        let _scope = env.new_local_scope();
+        
+        let backtrace = error.backtrace().map(|backtrace| backtrace.to_string());
+        
+        let error = error.as_runtime(env).as_value(&*env);
+        
+        let message = if let Ok(error) = error.to_string(env) {
+            error.to_string()
+        } else {
+            "(cannot convert error to string)".to_string()
+        };
+        
+        writeln!(io::stderr(), "Uncaught {}", message).ok();
+        
+        if let Some(backtrace) = backtrace {
+            if backtrace.len() > 0 {
+                writeln!(io::stderr(), "{}", backtrace).ok();
+            }
+        }
+        
+        process::exit(1);
+    }
 }
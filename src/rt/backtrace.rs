@@ -0,0 +1,148 @@
+use rt::JsEnv;
+use rt::stack::StackFrame;
+use ir::builder::Block;
+use syntax::Name;
+use syntax::ast::FunctionRef;
+use std::cmp;
+use std::fmt;
+use std::rc::Rc;
+
+// Entry of the call-frame chain. Frames are pushed when a function is
+// entered and popped when it returns or throws. Native functions have no
+// function reference, no location and no state.
+pub struct CallFrame {
+    pub name: Option<Name>,
+    pub function: Option<FunctionRef>,
+    pub ip: usize,
+    // Offset of the last line table entry the debugger has seen in this
+    // frame.
+    pub entry: Option<usize>,
+    pub state: Option<FrameState>
+}
+
+impl CallFrame {
+    pub fn new(name: Option<Name>, function: Option<FunctionRef>) -> CallFrame {
+        CallFrame {
+            name: name,
+            function: function,
+            ip: 0,
+            entry: None,
+            state: None
+        }
+    }
+}
+
+// Stack frames of an interpreted function, used to inspect the function
+// while it is paused in the debugger. The scope is an offset into the locals.
+pub struct FrameState {
+    pub block: Rc<Block>,
+    pub args: StackFrame,
+    pub locals: StackFrame,
+    pub scope: usize,
+    pub strict: bool
+}
+
+#[derive(Clone, Debug)]
+pub struct JsLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32
+}
+
+#[derive(Clone, Debug)]
+pub struct JsStackFrame {
+    pub function: Option<String>,
+    pub location: Option<JsLocation>
+}
+
+/// Snapshot of the call-frame chain, innermost frame first.
+#[derive(Clone, Debug)]
+pub struct JsBacktrace {
+    frames: Vec<JsStackFrame>
+}
+
+impl JsBacktrace {
+    pub fn frames(&self) -> &[JsStackFrame] {
+        &self.frames
+    }
+}
+
+impl fmt::Display for JsLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl fmt::Display for JsStackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.function, &self.location) {
+            (&Some(ref function), &Some(ref location)) => write!(f, "{} ({})", function, location),
+            (&None, &Some(ref location)) => write!(f, "{}", location),
+            (&Some(ref function), &None) => write!(f, "{} (native)", function),
+            (&None, &None) => write!(f, "(native)")
+        }
+    }
+}
+
+// Formats the frames the way they appear in the stack property of errors,
+// one "    at ..." line per frame.
+impl fmt::Display for JsBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in 0..self.frames.len() {
+            if i > 0 {
+                try!(write!(f, "\n"));
+            }
+            try!(write!(f, "    at {}", self.frames[i]));
+        }
+        
+        Ok(())
+    }
+}
+
+impl JsEnv {
+    /// Captures the current call-frame chain.
+    pub fn backtrace(&self) -> JsBacktrace {
+        self.capture_backtrace(0)
+    }
+    
+    // Captures the call-frame chain without the innermost skip frames.
+    pub fn capture_backtrace(&self, skip: usize) -> JsBacktrace {
+        let count = self.frames.len().saturating_sub(skip);
+        
+        let frames = self.frames[..count].iter().rev().map(|frame| {
+            let function = frame.name.map(|name| self.ir.interner().get(name).to_string());
+            
+            let location = frame.function.map(|function_ref| {
+                let span = self.ir.get_span(function_ref, frame.ip);
+                
+                JsLocation {
+                    file: self.ir.interner().get(span.file).to_string(),
+                    line: cmp::max(span.start_line, 0) as u32,
+                    column: cmp::max(span.start_col, 0) as u32
+                }
+            });
+            
+            JsStackFrame {
+                function: function,
+                location: location
+            }
+        }).collect();
+        
+        JsBacktrace {
+            frames: frames
+        }
+    }
+    
+    #[inline(always)]
+    pub fn set_frame_ip(&mut self, ip: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.ip = ip;
+        }
+    }
+    
+    pub fn set_frame_state(&mut self, state: FrameState) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.state = Some(state);
+        }
+    }
+}
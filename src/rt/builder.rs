@@ -0,0 +1,235 @@
+use rt::{JsEnv, JsScope, GC_SCOPE};
+use rt::debugger::DebugState;
+use rt::object::Shapes;
+use rt::{env, stack, walker};
+use gc::{GcHeap, GcOpts, GcWalker};
+use ir::IrContext;
+use ::JsResult;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+/// The default size of the value stack in bytes.
+pub const DEFAULT_STACK_SIZE : usize = 1024 * 1024; // 1M
+
+/// The default amount of native stack in bytes that nested calls may use.
+/// This leaves room on the 2M stack Rust gives to spawned threads.
+pub const DEFAULT_NATIVE_STACK_SIZE : usize = 1024 * 1024; // 1M
+
+/// The default maximum depth of nested calls.
+pub const DEFAULT_MAX_CALL_DEPTH : usize = 10000;
+
+// Selects the optional built-in objects that are made available to scripts.
+// When RegExp is disabled, regular expression literals and the String methods
+// that take a pattern throw a TypeError.
+pub struct JsIntrinsics {
+    pub console: bool,
+    pub date: bool,
+    pub eval: bool,
+    pub json: bool,
+    pub math: bool,
+    pub regexp: bool
+}
+
+impl JsIntrinsics {
+    pub fn all() -> JsIntrinsics {
+        JsIntrinsics {
+            console: true,
+            date: true,
+            eval: true,
+            json: true,
+            math: true,
+            regexp: true
+        }
+    }
+    
+    pub fn none() -> JsIntrinsics {
+        JsIntrinsics {
+            console: false,
+            date: false,
+            eval: false,
+            json: false,
+            math: false,
+            regexp: false
+        }
+    }
+}
+
+// Host hooks used by the built-in objects. When a hook is not set, the
+// default implementation is used.
+pub struct JsHooks {
+    pub console: Option<Box<FnMut(&str)>>,
+    pub time: Option<Box<FnMut() -> f64>>,
+    pub random: Option<Box<FnMut() -> f64>>
+}
+
+pub struct JsEnvBuilder {
+    gc: GcOpts,
+    stack_size: usize,
+    native_stack_size: usize,
+    max_call_depth: usize,
+    strict: bool,
+    optimize: bool,
+    registers: bool,
+    inline_caches: bool,
+    intrinsics: JsIntrinsics,
+    hooks: JsHooks
+}
+
+impl JsEnvBuilder {
+    pub fn new() -> JsEnvBuilder {
+        JsEnvBuilder {
+            gc: GcOpts::default(),
+            stack_size: DEFAULT_STACK_SIZE,
+            native_stack_size: DEFAULT_NATIVE_STACK_SIZE,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            strict: false,
+            optimize: true,
+            registers: true,
+            inline_caches: true,
+            intrinsics: JsIntrinsics::all(),
+            hooks: JsHooks {
+                console: None,
+                time: None,
+                random: None
+            }
+        }
+    }
+    
+    pub fn gc_opts(mut self, opts: GcOpts) -> JsEnvBuilder {
+        self.gc = opts;
+        self
+    }
+    
+    /// Sets the size of the value stack in bytes.
+    pub fn stack_size(mut self, stack_size: usize) -> JsEnvBuilder {
+        self.stack_size = stack_size;
+        self
+    }
+    
+    /// Sets how much of the native stack in bytes nested calls may use before
+    /// a RangeError is thrown. Every call recurses on the native stack, so
+    /// this must be smaller than the stack of the thread running the
+    /// environment, leaving room for the native functions that are called.
+    pub fn native_stack_size(mut self, native_stack_size: usize) -> JsEnvBuilder {
+        self.native_stack_size = native_stack_size;
+        self
+    }
+    
+    /// Sets the maximum depth of nested calls.
+    pub fn max_call_depth(mut self, max_call_depth: usize) -> JsEnvBuilder {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+    
+    /// Sets whether JsEnv::run and JsEnv::eval run code in strict mode.
+    pub fn strict(mut self, strict: bool) -> JsEnvBuilder {
+        self.strict = strict;
+        self
+    }
+    
+    /// Sets whether the IR is optimized before it runs. Optimization moves
+    /// and removes instructions, so breakpoints and steps of a debugger are
+    /// more precise without it.
+    pub fn optimize(mut self, optimize: bool) -> JsEnvBuilder {
+        self.optimize = optimize;
+        self
+    }
+    
+    /// Sets whether hot instruction sequences of arithmetic, comparisons and
+    /// moves between locals run as register superinstructions instead of on
+    /// the value stack.
+    pub fn registers(mut self, registers: bool) -> JsEnvBuilder {
+        self.registers = registers;
+        self
+    }
+    
+    /// Sets whether instructions that access named properties cache where
+    /// they found the property.
+    pub fn inline_caches(mut self, inline_caches: bool) -> JsEnvBuilder {
+        self.inline_caches = inline_caches;
+        self
+    }
+    
+    pub fn intrinsics(mut self, intrinsics: JsIntrinsics) -> JsEnvBuilder {
+        self.intrinsics = intrinsics;
+        self
+    }
+    
+    /// Sets the hook that receives the output of console.log.
+    pub fn console(mut self, console: Box<FnMut(&str)>) -> JsEnvBuilder {
+        self.hooks.console = Some(console);
+        self
+    }
+    
+    /// Sets the hook that provides the current time in milliseconds since
+    /// the epoch, e.g. for Date.now().
+    pub fn time(mut self, time: Box<FnMut() -> f64>) -> JsEnvBuilder {
+        self.hooks.time = Some(time);
+        self
+    }
+    
+    /// Sets the hook that provides random numbers between 0 (inclusive) and
+    /// 1 (exclusive) for Math.random().
+    pub fn random(mut self, random: Box<FnMut() -> f64>) -> JsEnvBuilder {
+        self.hooks.random = Some(random);
+        self
+    }
+    
+    pub fn build(self) -> JsResult<JsEnv> {
+        let stack = Rc::new(stack::Stack::new(self.stack_size));
+        
+        let walker = Box::new(walker::Walker::new(stack.clone()));
+        walker.validate();
+        
+        let heap = GcHeap::new(walker, self.gc);
+        
+        let global_scope = heap.alloc_root::<JsScope>(GC_SCOPE);
+        
+        let mut env = JsEnv {
+            heap: heap,
+            global_scope: global_scope,
+            ir: IrContext::new(),
+            stack: stack,
+            privileged: true,
+            strict: self.strict,
+            handles: Vec::new(),
+            call_depth: 0,
+            max_call_depth: self.max_call_depth,
+            native_stack_base: 0,
+            native_stack_size: self.native_stack_size,
+            stack_overflow: false,
+            budget: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            hooks: self.hooks,
+            frames: Vec::new(),
+            debug: DebugState::new(),
+            profiler: None,
+            coverage: None,
+            shapes: Shapes::new(),
+            inline_caches: self.inline_caches
+        };
+        
+        env.ir.set_optimize(self.optimize);
+        env.ir.set_registers(self.registers);
+        
+        if let Err(error) = env::setup(&mut env, &self.intrinsics) {
+            let _scope = env.new_local_scope();
+            
+            let error = error.as_runtime(&mut env).as_value(&env);
+            
+            let error = if let Ok(error) = error.to_string(&mut env) {
+                error.to_string()
+            } else {
+                "(cannot convert error to string)".to_string()
+            };
+            
+            panic!("(setup): uncaught {}", error);
+        }
+        
+        // Turn of privileged mode for normal code (i.e. not setup code).
+        env.privileged = false;
+        
+        Ok(env)
+    }
+}
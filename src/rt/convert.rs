@@ -0,0 +1,306 @@
+use rt::{JsEnv, JsValue, JsArgs, JsString, JsItem, JsFnMode, JsClosure};
+use rt::object::JsStoreKey;
+use syntax::Name;
+use syntax::token::name;
+use ::JsResult;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::cell::RefCell;
+
+/// Conversion of a JavaScript value into a Rust value. The conversions follow
+/// the type conversions of the specification, e.g. f64 uses ToNumber and
+/// String uses ToString.
+pub trait FromJs : Sized {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<Self>;
+}
+
+/// Conversion of a Rust value into a JavaScript value.
+pub trait IntoJs {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue>;
+}
+
+impl FromJs for JsValue {
+    fn from_js(_: &mut JsEnv, value: JsValue) -> JsResult<JsValue> {
+        Ok(value)
+    }
+}
+
+impl IntoJs for JsValue {
+    fn into_js(self, _: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(self)
+    }
+}
+
+// 9.3 ToNumber
+impl FromJs for f64 {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<f64> {
+        value.to_number(env)
+    }
+}
+
+impl IntoJs for f64 {
+    fn into_js(self, _: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(JsValue::new_number(self))
+    }
+}
+
+// 9.5 ToInt32
+impl FromJs for i32 {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<i32> {
+        value.to_int32(env)
+    }
+}
+
+impl IntoJs for i32 {
+    fn into_js(self, _: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(JsValue::new_number(self as f64))
+    }
+}
+
+// 9.6 ToUint32
+impl FromJs for u32 {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<u32> {
+        value.to_uint32(env)
+    }
+}
+
+impl IntoJs for u32 {
+    fn into_js(self, _: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(JsValue::new_number(self as f64))
+    }
+}
+
+// 9.2 ToBoolean
+impl FromJs for bool {
+    fn from_js(_: &mut JsEnv, value: JsValue) -> JsResult<bool> {
+        Ok(value.to_boolean())
+    }
+}
+
+impl IntoJs for bool {
+    fn into_js(self, _: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(JsValue::new_bool(self))
+    }
+}
+
+// 9.8 ToString
+impl FromJs for String {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<String> {
+        Ok(try!(value.to_string(env)).to_string())
+    }
+}
+
+impl IntoJs for String {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(try!(JsString::try_from_str(env, &self)).as_value())
+    }
+}
+
+impl<'a> IntoJs for &'a str {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(try!(JsString::try_from_str(env, self)).as_value())
+    }
+}
+
+impl FromJs for () {
+    fn from_js(_: &mut JsEnv, _: JsValue) -> JsResult<()> {
+        Ok(())
+    }
+}
+
+impl IntoJs for () {
+    fn into_js(self, _: &mut JsEnv) -> JsResult<JsValue> {
+        Ok(JsValue::new_undefined())
+    }
+}
+
+// Both null and undefined map to None. None is converted into null.
+impl<T: FromJs> FromJs for Option<T> {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<Option<T>> {
+        if value.is_null_or_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(try!(T::from_js(env, value))))
+        }
+    }
+}
+
+impl<T: IntoJs> IntoJs for Option<T> {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue> {
+        match self {
+            Some(value) => value.into_js(env),
+            None => Ok(JsValue::new_null())
+        }
+    }
+}
+
+// Errors are propagated, so a function can return a JsResult.
+impl<T: IntoJs> IntoJs for JsResult<T> {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue> {
+        match self {
+            Ok(value) => value.into_js(env),
+            Err(error) => Err(error)
+        }
+    }
+}
+
+// Any array like object can be converted into a Vec. The value is coerced
+// to an object and the items are read using the length property.
+impl<T: FromJs> FromJs for Vec<T> {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<Vec<T>> {
+        let object = try!(value.to_object(env));
+        let length = try!(object.get(env, name::LENGTH));
+        let length = try!(length.to_uint32(env)) as usize;
+        
+        // The length comes from script, so it is not used to reserve
+        // memory up front.
+        
+        let mut result = Vec::new();
+        
+        for i in 0..length {
+            let item = try!(object.get(env, Name::from_index(i)));
+            result.push(try!(T::from_js(env, item)));
+        }
+        
+        Ok(result)
+    }
+}
+
+impl<T: IntoJs> IntoJs for Vec<T> {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue> {
+        let mut array = env.create_array();
+        
+        for (i, item) in self.into_iter().enumerate() {
+            let item = try!(item.into_js(env));
+            try!(array.put(env, Name::from_index(i), item, true));
+        }
+        
+        Ok(array.as_value())
+    }
+}
+
+// Objects are converted into a HashMap using their enumerable own properties.
+// The keys are collected before the properties are read, because getters can
+// change the store of the object.
+impl<T: FromJs> FromJs for HashMap<String, T> {
+    fn from_js(env: &mut JsEnv, value: JsValue) -> JsResult<HashMap<String, T>> {
+        let object = try!(value.to_object(env)).unwrap_object();
+        
+        let mut names = Vec::new();
+        
+        for offset in 0.. {
+            match object.get_key(env, offset) {
+                JsStoreKey::Key(name, enumerable) => {
+                    if enumerable {
+                        names.push(name);
+                    }
+                }
+                JsStoreKey::Missing => {}
+                JsStoreKey::End => break
+            }
+        }
+        
+        let mut result = HashMap::new();
+        
+        for name in names {
+            // Properties deleted by an earlier getter are skipped.
+            
+            if object.get_own_property(env, name).is_none() {
+                continue;
+            }
+            
+            let key = env.ir.interner().get(name).to_string();
+            let item = try!(object.get(env, name));
+            result.insert(key, try!(T::from_js(env, item)));
+        }
+        
+        Ok(result)
+    }
+}
+
+impl<K: AsRef<str> + Eq + Hash, T: IntoJs> IntoJs for HashMap<K, T> {
+    fn into_js(self, env: &mut JsEnv) -> JsResult<JsValue> {
+        let mut object = env.create_object();
+        
+        for (key, item) in self {
+            let key = env.intern(key.as_ref());
+            let item = try!(item.into_js(env));
+            try!(object.put(env, key, item, true));
+        }
+        
+        Ok(object.as_value())
+    }
+}
+
+/// Adapts a Rust function or closure with typed arguments and result into
+/// a native function. Arguments are converted using FromJs, missing arguments
+/// are undefined, and the result is converted using IntoJs.
+pub trait JsTypedFn<Args, R> {
+    fn args() -> u32;
+    
+    fn convert_args(env: &mut JsEnv, args: &JsArgs) -> JsResult<Args>;
+    
+    fn call(&mut self, args: Args) -> R;
+}
+
+macro_rules! typed_fn {
+    ( $count:expr ; $( $ty:ident $arg:ident $index:expr ),* ) => {
+        impl<F, R $( , $ty )*> JsTypedFn<( $( $ty, )* ), R> for F
+            where F: FnMut( $( $ty ),* ) -> R, R: IntoJs $( , $ty: FromJs )*
+        {
+            fn args() -> u32 {
+                $count
+            }
+            
+            #[allow(unused_variables)]
+            fn convert_args(env: &mut JsEnv, args: &JsArgs) -> JsResult<( $( $ty, )* )> {
+                $(
+                    let $arg = args.arg(env, $index);
+                    let $arg = try!($ty::from_js(env, $arg));
+                )*
+                
+                Ok(( $( $arg, )* ))
+            }
+            
+            fn call(&mut self, args: ( $( $ty, )* )) -> R {
+                let ( $( $arg, )* ) = args;
+                
+                (*self)( $( $arg ),* )
+            }
+        }
+    }
+}
+
+typed_fn!(0; );
+typed_fn!(1; A a 0);
+typed_fn!(2; A a 0, B b 1);
+typed_fn!(3; A a 0, B b 1, C c 2);
+typed_fn!(4; A a 0, B b 1, C c 2, D d 3);
+typed_fn!(5; A a 0, B b 1, C c 2, D d 3, E e 4);
+typed_fn!(6; A a 0, B b 1, C c 2, D d 3, E e 4, G g 5);
+
+impl JsEnv {
+    /// Creates a native function from a Rust function with typed arguments,
+    /// e.g. `env.new_typed_function("add", |x: f64, y: f64| x + y)`.
+    pub fn new_typed_function<Args, R, F>(&mut self, name: &str, function: F) -> JsValue
+        where F: JsTypedFn<Args, R> + 'static, Args: 'static, R: IntoJs + 'static
+    {
+        let args = F::args();
+        
+        // Converting the arguments and the result can call back into script,
+        // e.g. through valueOf, which can call this function again. Typed
+        // functions don't get the environment, so the function is only
+        // borrowed while it runs.
+        
+        let function = RefCell::new(function);
+        
+        let closure : Box<JsClosure> = Box::new(move |env: &mut JsEnv, _mode: JsFnMode, args: JsArgs| {
+            let args = try!(F::convert_args(env, &args));
+            let result = function.borrow_mut().call(args);
+            
+            result.into_js(env)
+        });
+        
+        self.new_native_closure(name, args, closure)
+    }
+}
@@ -0,0 +1,330 @@
+extern crate rustc_serialize;
+
+use rt::JsEnv;
+use ir::builder::{Block, Ir};
+use syntax::ast::FunctionRef;
+use self::rustc_serialize::json::{Json, ToJson};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::cmp;
+use std::io::prelude::*;
+use std::io;
+
+// Coverage state of an environment. Functions are indexed by their function
+// reference. Statements are counted by the offset of their line table entry
+// and branches by the offset of the conditional jump.
+pub struct Coverage {
+    functions: Vec<Option<FunctionCoverage>>
+}
+
+struct FunctionCoverage {
+    calls: u64,
+    statements: HashMap<usize, u64>,
+    branches: HashMap<usize, (u64, u64)>
+}
+
+/// Coverage collected by JsEnv::start_coverage, per file.
+pub struct JsCoverage {
+    files: BTreeMap<String, JsFileCoverage>
+}
+
+pub struct JsFileCoverage {
+    pub functions: Vec<JsFunctionCoverage>,
+    pub statements: Vec<JsStatementCoverage>,
+    pub branches: Vec<JsBranchCoverage>
+}
+
+pub struct JsFunctionCoverage {
+    pub name: String,
+    pub line: u32,
+    pub calls: u64
+}
+
+pub struct JsStatementCoverage {
+    pub line: u32,
+    pub column: u32,
+    pub hits: u64
+}
+
+// A branch is a conditional jump; it is taken when the jump is made.
+pub struct JsBranchCoverage {
+    pub line: u32,
+    pub taken: u64,
+    pub not_taken: u64
+}
+
+impl Coverage {
+    fn new() -> Coverage {
+        Coverage {
+            functions: Vec::new()
+        }
+    }
+    
+    fn function(&mut self, function_ref: FunctionRef) -> &mut FunctionCoverage {
+        let index = function_ref.usize();
+        
+        while self.functions.len() <= index {
+            self.functions.push(None);
+        }
+        
+        if self.functions[index].is_none() {
+            self.functions[index] = Some(FunctionCoverage {
+                calls: 0,
+                statements: HashMap::new(),
+                branches: HashMap::new()
+            });
+        }
+        
+        self.functions[index].as_mut().unwrap()
+    }
+}
+
+impl JsEnv {
+    /// Starts collecting coverage of the code run in this environment.
+    /// Coverage collected before is discarded.
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Box::new(Coverage::new()));
+    }
+    
+    /// Stops collecting coverage and returns the coverage of all files that
+    /// had code executed. Functions of these files that did not run are
+    /// reported as uncovered.
+    pub fn stop_coverage(&mut self) -> Option<JsCoverage> {
+        let coverage = match self.coverage.take() {
+            Some(coverage) => coverage,
+            None => return None
+        };
+        
+        let mut files = HashSet::new();
+        
+        for i in 0..coverage.functions.len() {
+            if coverage.functions[i].is_some() {
+                files.insert(self.ir.get_function(FunctionRef(i as u32)).span.file);
+            }
+        }
+        
+        let mut result = JsCoverage {
+            files: BTreeMap::new()
+        };
+        
+        for i in 0..self.ir.function_count() {
+            let function_ref = FunctionRef(i as u32);
+            let function = self.ir.get_function(function_ref);
+            
+            if !files.contains(&function.span.file) {
+                continue;
+            }
+            
+            // The IR of functions that never ran is built here to find their
+            // statements and branches.
+            
+            let block = match self.ir.get_function_ir(function_ref) {
+                Ok(block) => block,
+                Err(..) => continue
+            };
+            
+            let file = self.ir.interner().get(function.span.file).to_string();
+            let line = cmp::max(function.span.start_line, 0) as u32;
+            
+            let name = match function.name {
+                Some(name) => self.ir.interner().get(name).to_string(),
+                None => format!("(anonymous:{})", line)
+            };
+            
+            let file_coverage = result.files.entry(file).or_insert_with(|| {
+                JsFileCoverage {
+                    functions: Vec::new(),
+                    statements: Vec::new(),
+                    branches: Vec::new()
+                }
+            });
+            
+            let function_coverage = coverage.functions.get(i).and_then(|function| function.as_ref());
+            
+            file_coverage.functions.push(JsFunctionCoverage {
+                name: name,
+                line: line,
+                calls: function_coverage.map_or(0, |function| function.calls)
+            });
+            
+            // A statement can have multiple entries in the line table when
+            // nested statements interrupt it.
+            
+            let mut statements = Vec::<JsStatementCoverage>::new();
+            
+            for &(offset, span) in &block.spans {
+                let hits = function_coverage.and_then(|function| function.statements.get(&offset.offset())).map_or(0, |&hits| hits);
+                let line = cmp::max(span.start_line, 0) as u32;
+                let column = cmp::max(span.start_col, 0) as u32;
+                
+                if let Some(statement) = statements.iter_mut().find(|statement| statement.line == line && statement.column == column) {
+                    statement.hits = cmp::max(statement.hits, hits);
+                    continue;
+                }
+                
+                statements.push(JsStatementCoverage {
+                    line: line,
+                    column: column,
+                    hits: hits
+                });
+            }
+            
+            file_coverage.statements.extend(statements);
+            
+            for ip in 0..block.ir.len() {
+                match block.ir[ip] {
+                    Ir::JumpTrue(..) | Ir::JumpFalse(..) | Ir::JumpStrictEq(..) => {
+                        let (taken, not_taken) = function_coverage.and_then(|function| function.branches.get(&ip)).map_or((0, 0), |&branch| branch);
+                        
+                        file_coverage.branches.push(JsBranchCoverage {
+                            line: block.span_at(ip).map_or(line, |span| cmp::max(span.start_line, 0) as u32),
+                            taken: taken,
+                            not_taken: not_taken
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        Some(result)
+    }
+    
+    // Called by the interpreter when a function is entered while collecting
+    // coverage.
+    pub fn cover_call(&mut self) {
+        if let Some(function_ref) = self.frames.last().and_then(|frame| frame.function) {
+            if let Some(ref mut coverage) = self.coverage {
+                coverage.function(function_ref).calls += 1;
+            }
+        }
+    }
+    
+    // Called by the interpreter before every instruction while collecting
+    // coverage. Counts the instruction when it starts a line table entry.
+    pub fn cover_instruction(&mut self, block: &Block, ip: usize) {
+        if block.spans.binary_search_by(|&(start, _)| start.offset().cmp(&ip)).is_err() {
+            return;
+        }
+        
+        if let Some(function_ref) = self.frames.last().and_then(|frame| frame.function) {
+            if let Some(ref mut coverage) = self.coverage {
+                *coverage.function(function_ref).statements.entry(ip).or_insert(0) += 1;
+            }
+        }
+    }
+    
+    // Called by the interpreter for every conditional jump while collecting
+    // coverage.
+    pub fn cover_branch(&mut self, ip: usize, taken: bool) {
+        if let Some(function_ref) = self.frames.last().and_then(|frame| frame.function) {
+            if let Some(ref mut coverage) = self.coverage {
+                let branch = coverage.function(function_ref).branches.entry(ip).or_insert((0, 0));
+                
+                if taken {
+                    branch.0 += 1;
+                } else {
+                    branch.1 += 1;
+                }
+            }
+        }
+    }
+}
+
+impl JsFileCoverage {
+    /// Gets the hits per line. The hits of a line are the hits of the
+    /// statement on that line that ran most often.
+    pub fn lines(&self) -> BTreeMap<u32, u64> {
+        let mut lines = BTreeMap::new();
+        
+        for statement in &self.statements {
+            let hits = lines.entry(statement.line).or_insert(0);
+            *hits = cmp::max(*hits, statement.hits);
+        }
+        
+        lines
+    }
+    
+    fn branches_hit(&self) -> usize {
+        self.branches.iter().fold(0, |hit, branch| hit + (branch.taken > 0) as usize + (branch.not_taken > 0) as usize)
+    }
+}
+
+impl JsCoverage {
+    pub fn files(&self) -> &BTreeMap<String, JsFileCoverage> {
+        &self.files
+    }
+    
+    /// Writes the coverage in the LCOV tracefile format.
+    pub fn write_lcov(&self, writer: &mut Write) -> io::Result<()> {
+        for (file, coverage) in &self.files {
+            try!(writeln!(writer, "TN:"));
+            try!(writeln!(writer, "SF:{}", file));
+            
+            for function in &coverage.functions {
+                try!(writeln!(writer, "FN:{},{}", function.line, function.name));
+            }
+            for function in &coverage.functions {
+                try!(writeln!(writer, "FNDA:{},{}", function.calls, function.name));
+            }
+            try!(writeln!(writer, "FNF:{}", coverage.functions.len()));
+            try!(writeln!(writer, "FNH:{}", coverage.functions.iter().filter(|function| function.calls > 0).count()));
+            
+            // Every conditional jump is a block with a taken and a not taken
+            // branch. Branches of jumps that never ran are reported as "-".
+            
+            for i in 0..coverage.branches.len() {
+                let branch = &coverage.branches[i];
+                
+                if branch.taken + branch.not_taken == 0 {
+                    try!(writeln!(writer, "BRDA:{},{},0,-", branch.line, i));
+                    try!(writeln!(writer, "BRDA:{},{},1,-", branch.line, i));
+                } else {
+                    try!(writeln!(writer, "BRDA:{},{},0,{}", branch.line, i, branch.taken));
+                    try!(writeln!(writer, "BRDA:{},{},1,{}", branch.line, i, branch.not_taken));
+                }
+            }
+            try!(writeln!(writer, "BRF:{}", coverage.branches.len() * 2));
+            try!(writeln!(writer, "BRH:{}", coverage.branches_hit()));
+            
+            let lines = coverage.lines();
+            
+            for (line, hits) in &lines {
+                try!(writeln!(writer, "DA:{},{}", line, hits));
+            }
+            try!(writeln!(writer, "LF:{}", lines.len()));
+            try!(writeln!(writer, "LH:{}", lines.values().filter(|&&hits| hits > 0).count()));
+            
+            try!(writeln!(writer, "end_of_record"));
+        }
+        
+        Ok(())
+    }
+    
+    /// Writes a JSON summary with the total and covered lines, statements,
+    /// functions and branches of every file.
+    pub fn write_json_summary(&self, writer: &mut Write) -> io::Result<()> {
+        fn summary(total: usize, covered: usize) -> Json {
+            let mut object = BTreeMap::new();
+            object.insert("total".to_string(), (total as u64).to_json());
+            object.insert("covered".to_string(), (covered as u64).to_json());
+            object.insert("pct".to_string(), (if total == 0 { 100f64 } else { covered as f64 * 100f64 / total as f64 }).to_json());
+            Json::Object(object)
+        }
+        
+        let mut files = BTreeMap::new();
+        
+        for (file, coverage) in &self.files {
+            let lines = coverage.lines();
+            
+            let mut object = BTreeMap::new();
+            object.insert("lines".to_string(), summary(lines.len(), lines.values().filter(|&&hits| hits > 0).count()));
+            object.insert("statements".to_string(), summary(coverage.statements.len(), coverage.statements.iter().filter(|statement| statement.hits > 0).count()));
+            object.insert("functions".to_string(), summary(coverage.functions.len(), coverage.functions.iter().filter(|function| function.calls > 0).count()));
+            object.insert("branches".to_string(), summary(coverage.branches.len() * 2, coverage.branches_hit()));
+            
+            files.insert(file.clone(), Json::Object(object));
+        }
+        
+        write!(writer, "{}", Json::Object(files))
+    }
+}
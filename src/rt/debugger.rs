@@ -0,0 +1,263 @@
+use rt::{JsEnv, JsRawValue, JsScope, JsItem};
+use rt::backtrace::FrameState;
+use ir::builder::Block;
+use gc::{Local, Root};
+use syntax::Name;
+use syntax::parser::ParseMode;
+use ::JsResult;
+
+/// Debugger installed on an environment using JsEnv::set_debugger.
+pub trait Debugger {
+    /// Called when execution pauses. While paused, the frames of the call
+    /// chain can be inspected using the debug_* methods of the environment.
+    /// Frame 0 is the innermost frame and the frames match those of
+    /// JsEnv::backtrace. The result determines how execution resumes.
+    fn paused(&mut self, env: &mut JsEnv, reason: JsPauseReason) -> JsResume;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JsBreakpoint(u32);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JsPauseReason {
+    DebuggerStatement,
+    Breakpoint(JsBreakpoint),
+    Step
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JsResume {
+    Continue,
+    // Pause at the next line of the current function or of a caller.
+    StepOver,
+    // Pause at the next line, including lines of called functions.
+    StepInto,
+    // Pause at the next line of a caller.
+    StepOut
+}
+
+// Debugger state of an environment.
+pub struct DebugState {
+    debugger: Option<Box<Debugger>>,
+    breakpoints: Vec<(JsBreakpoint, Name, i32)>,
+    next_breakpoint: u32,
+    // Pending step with the depth of the call chain at which it was requested.
+    step: Option<(JsResume, usize)>
+}
+
+impl DebugState {
+    pub fn new() -> DebugState {
+        DebugState {
+            debugger: None,
+            breakpoints: Vec::new(),
+            next_breakpoint: 0,
+            step: None
+        }
+    }
+    
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        self.debugger.is_some()
+    }
+}
+
+impl JsEnv {
+    /// Installs or removes the debugger. Breakpoints and steps are only
+    /// processed while a debugger is installed.
+    pub fn set_debugger(&mut self, debugger: Option<Box<Debugger>>) {
+        self.debug.debugger = debugger;
+        self.debug.step = None;
+    }
+    
+    /// Sets a breakpoint on the statements that start at the line of the file.
+    /// The file must match the file name the script was run with.
+    pub fn set_breakpoint(&mut self, file: &str, line: u32) -> JsBreakpoint {
+        let breakpoint = JsBreakpoint(self.debug.next_breakpoint);
+        self.debug.next_breakpoint += 1;
+        
+        let file = self.intern(file);
+        self.debug.breakpoints.push((breakpoint, file, line as i32));
+        
+        // The statements the frames are at can pause on the new breakpoint
+        // when they are entered again.
+        
+        for frame in &mut self.frames {
+            frame.entry = None;
+        }
+        
+        breakpoint
+    }
+    
+    pub fn remove_breakpoint(&mut self, breakpoint: JsBreakpoint) -> bool {
+        let len = self.debug.breakpoints.len();
+        
+        self.debug.breakpoints.retain(|&(id, _, _)| id != breakpoint);
+        
+        self.debug.breakpoints.len() != len
+    }
+    
+    pub fn clear_breakpoints(&mut self) {
+        self.debug.breakpoints.clear();
+    }
+    
+    /// Gets the value of this of the frame, or None for a native frame.
+    pub fn debug_this(&self, frame: usize) -> Option<Root<JsRawValue>> {
+        self.frame_state(frame).map(|state| self.new_root(state.args.get(self, 0)))
+    }
+    
+    /// Gets the current scope of the frame. The scope chain can be walked
+    /// using JsScope::parent.
+    pub fn debug_scope(&self, frame: usize) -> Option<Local<JsScope>> {
+        self.frame_state(frame).and_then(|state| {
+            let scope = state.locals.get(self, state.scope);
+            
+            if scope.is_undefined() {
+                None
+            } else {
+                Some(scope.unwrap_scope())
+            }
+        })
+    }
+    
+    /// Gets the named locals and arguments of the frame that are kept on the
+    /// stack. Variables that live in a scope are found through debug_scope.
+    pub fn debug_locals(&self, frame: usize) -> Option<Vec<(String, Root<JsRawValue>)>> {
+        let function = match self.frames.iter().rev().nth(frame) {
+            Some(frame) => frame.function,
+            None => return None
+        };
+        
+        self.frame_state(frame).map(|state| {
+            let mut locals = Vec::new();
+            
+            if let Some(function) = function {
+                for (name, index) in self.ir.get_stack_args(function) {
+                    let value = state.args.get(self, index as usize + 2);
+                    locals.push((self.ir.interner().get(name).to_string(), self.new_root(value)));
+                }
+            }
+            
+            for i in 0..state.block.locals.len() {
+                if let Some(name) = state.block.locals[i] {
+                    let value = state.locals.get(self, i);
+                    locals.push((self.ir.interner().get(name).to_string(), self.new_root(value)));
+                }
+            }
+            
+            locals
+        })
+    }
+    
+    /// Evaluates the expression in the frame. The locals of the frame are
+    /// visible to the expression, but assignments to them are not written
+    /// back into the frame.
+    pub fn debug_eval(&mut self, frame: usize, js: &str) -> JsResult<Root<JsRawValue>> {
+        let _scope = self.new_local_scope();
+        
+        let (this, scope, strict) = match self.frame_state(frame) {
+            Some(state) => (state.args.get(self, 0), state.locals.get(self, state.scope), state.strict),
+            None => return self.eval(js)
+        };
+        
+        let scope = if scope.is_undefined() {
+            self.global_scope.as_local(self)
+        } else {
+            scope.unwrap_scope()
+        };
+        
+        // The locals are made visible by putting a scope with the locals in
+        // front of the scope of the frame.
+        
+        let mut object = self.create_object();
+        object.set_prototype(self, None);
+        
+        for (name, value) in self.debug_locals(frame).unwrap_or_else(Vec::new) {
+            let name = self.intern(&name);
+            let value = value.as_value(self);
+            try!(object.put(self, name, value, true));
+        }
+        
+        let scope = JsScope::new_local_thick(self, object, Some(scope), false);
+        
+        self.eval_scoped(js, strict, this, scope, ParseMode::DirectEval)
+    }
+    
+    fn frame_state(&self, frame: usize) -> Option<&FrameState> {
+        self.frames.iter().rev().nth(frame).and_then(|frame| frame.state.as_ref())
+    }
+    
+    // Called by the interpreter before every instruction while a debugger is
+    // installed. Pauses when the instruction starts a statement that has a
+    // breakpoint on its line or completes a pending step. Every execution of
+    // a statement can pause, also when it is on the same line as the last
+    // one, e.g. in a loop on a single line.
+    pub fn debug_instruction(&mut self, block: &Block, ip: usize) {
+        let index = match block.spans.binary_search_by(|&(start, _)| start.offset().cmp(&ip)) {
+            Ok(index) => index,
+            Err(..) => return
+        };
+        
+        let span = block.spans[index].1;
+        
+        let depth = self.frames.len();
+        
+        match self.frames.last_mut() {
+            Some(frame) => {
+                if frame.entry == Some(ip) {
+                    return;
+                }
+                frame.entry = Some(ip);
+            }
+            None => return
+        }
+        
+        // A statement has multiple entries in the line table when nested
+        // statements interrupt it. The later entries continue the statement,
+        // e.g. with the update expression of a for loop, and don't start it.
+        
+        let continued = block.spans[..index].iter().any(|&(_, earlier)| {
+            earlier.start_line == span.start_line && earlier.start_col == span.start_col
+        });
+        
+        if continued {
+            return;
+        }
+        
+        let breakpoint = self.debug.breakpoints.iter()
+            .find(|&&(_, file, line)| file == span.file && line == span.start_line)
+            .map(|&(breakpoint, _, _)| breakpoint);
+        
+        let reason = if let Some(breakpoint) = breakpoint {
+            JsPauseReason::Breakpoint(breakpoint)
+        } else {
+            match self.debug.step {
+                Some((JsResume::StepInto, _)) => JsPauseReason::Step,
+                Some((JsResume::StepOver, step_depth)) if depth <= step_depth => JsPauseReason::Step,
+                Some((JsResume::StepOut, step_depth)) if depth < step_depth => JsPauseReason::Step,
+                _ => return
+            }
+        };
+        
+        self.debug_pause(reason);
+    }
+    
+    pub fn debug_pause(&mut self, reason: JsPauseReason) {
+        // The debugger is taken out of the environment while it runs, so
+        // code evaluated by the debugger does not pause.
+        
+        if let Some(mut debugger) = self.debug.debugger.take() {
+            let resume = debugger.paused(self, reason);
+            
+            // The debugger may have been replaced while paused.
+            
+            if self.debug.debugger.is_none() {
+                self.debug.debugger = Some(debugger);
+            }
+            
+            self.debug.step = match resume {
+                JsResume::Continue => None,
+                resume @ _ => Some((resume, self.frames.len()))
+            };
+        }
+    }
+}
@@ -100,7 +100,7 @@ pub fn Array_toLocaleString(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> J
         result
     };
     
-    Ok(JsString::from_str(env, &result).as_value())
+    Ok(try!(JsString::try_from_str(env, &result)).as_value())
 }
 
 // 15.4.4.4 Array.prototype.concat ( [ item1 [ , item2 [ , … ] ] ] )
@@ -214,7 +214,7 @@ pub fn Array_join(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<Js
         }
     }
     
-    Ok(JsString::from_str(env, &result).as_value())
+    Ok(try!(JsString::try_from_str(env, &result)).as_value())
 }
 
 // 15.4.4.6 Array.prototype.pop ( )
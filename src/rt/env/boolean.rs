@@ -18,7 +18,7 @@ pub fn Boolean_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsR
         let mut this = this_arg.unwrap_object();
         
         this.set_class(Some(name::BOOLEAN_CLASS));
-        this.set_value(arg);
+        this.set_value(env, arg);
         
         Ok(this_arg)
     } else {
@@ -5,7 +5,11 @@ use rt::{JsEnv, JsArgs, JsValue, JsFnMode};
 pub fn console_log(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<JsValue> {
     let string = try!(args.arg(env, 0).to_string(env)).to_string();
     
-    println!("{}", string);
+    if let Some(ref mut console) = env.hooks.console {
+        (&mut **console)(&string);
+    } else {
+        println!("{}", string);
+    }
     
     Ok(JsValue::new_undefined())
 }
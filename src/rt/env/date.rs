@@ -424,7 +424,7 @@ pub fn Date_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsResu
     }
     
     let time = if args.argc == 0 {
-        get_utc_now()
+        get_utc_now(env)
     } else if args.argc == 1 {
         let arg = try!(args.arg(env, 0).to_primitive(env, JsPreferredType::None));
         
@@ -441,7 +441,7 @@ pub fn Date_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsResu
     let mut this_obj = this.unwrap_object();
     
     this_obj.set_class(Some(name::DATE_CLASS));
-    this_obj.set_value(JsValue::new_number(time_clip(time)));
+    this_obj.set_value(env, JsValue::new_number(time_clip(time)));
     
     Ok(this)
 }
@@ -461,8 +461,12 @@ fn time_from_chrono(date_time: DateTime<UTC>) -> f64 {
     timestamp + (date_time.nanosecond() as f64 / 1_000_000.0).trunc()
 }
 
-fn get_utc_now() -> f64 {
-    time_from_chrono(UTC::now())
+fn get_utc_now(env: &mut JsEnv) -> f64 {
+    if let Some(ref mut hook) = env.hooks.time {
+        (&mut **hook)()
+    } else {
+        time_from_chrono(UTC::now())
+    }
 }
 
 fn make_date_from_args(env: &mut JsEnv, args: &JsArgs) -> JsResult<f64> {
@@ -527,8 +531,8 @@ pub fn Date_UTC(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<JsVa
 }
 
 // 15.9.4.4 Date.now ( )
-pub fn Date_now(_env: &mut JsEnv, _mode: JsFnMode, _args: JsArgs) -> JsResult<JsValue> {
-    Ok(JsValue::new_number(get_utc_now()))
+pub fn Date_now(env: &mut JsEnv, _mode: JsFnMode, _args: JsArgs) -> JsResult<JsValue> {
+    Ok(JsValue::new_number(get_utc_now(env)))
 }
 
 // 15.9.5.2 Date.prototype.toString ( )
@@ -708,7 +712,7 @@ pub fn Date_setTime(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<
                 let time = time_clip(try!(args.arg(env, 0).to_number(env)));
                 let time = JsValue::new_number(time);
                 
-                this.set_value(time);
+                this.set_value(env, time);
                 
                 return Ok(time);
             }
@@ -733,7 +737,7 @@ pub fn Date_setMilliseconds(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> J
     let time = time_clip(get_utc(make_date(get_day(time), time)));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -752,7 +756,7 @@ pub fn Date_setUTCMilliseconds(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -
     let time = time_clip(make_date(get_day(time), time));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -775,7 +779,7 @@ pub fn Date_setSeconds(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResu
     let time = time_clip(get_utc(make_date(get_day(time), time)));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -797,7 +801,7 @@ pub fn Date_setUTCSeconds(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsR
     let time = time_clip(make_date(get_day(time), time));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -821,7 +825,7 @@ pub fn Date_setMinutes(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResu
     let time = time_clip(get_utc(make_date(get_day(time), time)));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -844,7 +848,7 @@ pub fn Date_setUTCMinutes(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsR
     let time = time_clip(make_date(get_day(time), time));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -869,7 +873,7 @@ pub fn Date_setHours(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
     let time = time_clip(get_utc(make_date(get_day(time), time)));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -893,7 +897,7 @@ pub fn Date_setUTCHours(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsRes
     let time = time_clip(make_date(get_day(time), time));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -913,7 +917,7 @@ pub fn Date_setDate(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<
     let time = time_clip(get_utc(new_date));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -932,7 +936,7 @@ pub fn Date_setUTCDate(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResu
     let time = time_clip(new_date);
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -953,7 +957,7 @@ pub fn Date_setMonth(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
     let time = time_clip(get_utc(new_date));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -973,7 +977,7 @@ pub fn Date_setUTCMonth(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsRes
     let time = time_clip(new_date);
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -995,7 +999,7 @@ pub fn Date_setFullYear(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsRes
     let time = time_clip(get_utc(new_date));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -1016,7 +1020,7 @@ pub fn Date_setUTCFullYear(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> Js
     let time = time_clip(new_date);
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
@@ -1088,7 +1092,7 @@ pub fn Date_setYear(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<
     let time = time_clip(get_utc(time));
     let time = JsValue::new_number(time);
     
-    args.this(env).unwrap_object().set_value(time);
+    args.this(env).unwrap_object().set_value(env, time);
     
     Ok(time)
 }
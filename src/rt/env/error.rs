@@ -1,5 +1,7 @@
 use ::{JsResult, JsError};
-use rt::{JsEnv, JsArgs, JsValue, JsFnMode, JsItem, JsType, JsString};
+use rt::{JsEnv, JsArgs, JsValue, JsFnMode, JsItem, JsType, JsString, JsDescriptor, JsObject};
+use gc::Local;
+use syntax::Name;
 use syntax::token::name;
 
 pub fn Error_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsResult<JsValue> {
@@ -18,6 +20,20 @@ pub fn Error_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsRes
         try!(this_obj.put(env, name::MESSAGE, message, true));
     }
     
+    // The stack starts with the string representation of the error, followed
+    // by the call-frame chain without the frame of this constructor. Creating
+    // an error must not run script, so getters and toString are not called.
+    
+    let mut stack = error_to_raw_string(env, this_obj);
+    
+    let backtrace = env.capture_backtrace(1);
+    if backtrace.frames().len() > 0 {
+        stack.push_str(&format!("\n{}", backtrace));
+    }
+    
+    let stack = JsString::from_str(env, &stack).as_value();
+    try!(this_obj.define_own_property(env, name::STACK, JsDescriptor::new_value(stack, true, false, true), true));
+    
     Ok(this)
 }
 
@@ -26,29 +42,61 @@ pub fn Error_toString(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResul
     if this.ty() != JsType::Object {
         Err(JsError::new_type(env, ::errors::TYPE_INVALID))
     } else {
-        let name = try!(this.get(env, name::NAME));
-        let name = if name.is_undefined() {
-            JsString::from_str(env, "Error")
-        } else {
-            try!(name.to_string(env))
-        };
-        
-        let message = try!(this.get(env, name::MESSAGE));
-        let message = if message.is_undefined() {
-            JsString::from_str(env, "")
-        } else {
-            try!(message.to_string(env))
-        };
-        
-        let result = if name.chars().len() == 0 {
-            message
-        } else if message.chars().len() == 0 {
-            name
-        } else {
-            let result = format!("{}: {}", name.to_string(), message.to_string());
-            JsString::from_str(env, &result)
-        };
-        
-        Ok(result.as_value())
+        Ok(try!(error_to_string(env, this)).as_value())
     }
 }
+
+// Like error_to_string, but the name and message are only used when they are
+// strings stored in data properties.
+fn error_to_raw_string(env: &JsEnv, this: Local<JsObject>) -> String {
+    let name = raw_string(env, this, name::NAME).unwrap_or_else(|| "Error".to_string());
+    let message = raw_string(env, this, name::MESSAGE).unwrap_or_else(|| String::new());
+    
+    if name.len() == 0 {
+        message
+    } else if message.len() == 0 {
+        name
+    } else {
+        format!("{}: {}", name, message)
+    }
+}
+
+fn raw_string(env: &JsEnv, this: Local<JsObject>, property: Name) -> Option<String> {
+    match this.get_property(env, property) {
+        Some(descriptor) => {
+            if descriptor.is_data() && descriptor.value().ty() == JsType::String {
+                Some(descriptor.value().unwrap_string().to_string())
+            } else {
+                None
+            }
+        }
+        None => None
+    }
+}
+
+fn error_to_string(env: &mut JsEnv, this: JsValue) -> JsResult<Local<JsString>> {
+    let name = try!(this.get(env, name::NAME));
+    let name = if name.is_undefined() {
+        JsString::from_str(env, "Error")
+    } else {
+        try!(name.to_string(env))
+    };
+    
+    let message = try!(this.get(env, name::MESSAGE));
+    let message = if message.is_undefined() {
+        JsString::from_str(env, "")
+    } else {
+        try!(message.to_string(env))
+    };
+    
+    let result = if name.chars().len() == 0 {
+        message
+    } else if message.chars().len() == 0 {
+        name
+    } else {
+        let result = format!("{}: {}", name.to_string(), message.to_string());
+        JsString::from_str(env, &result)
+    };
+    
+    Ok(result)
+}
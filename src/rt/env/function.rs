@@ -121,7 +121,7 @@ pub fn Function_toString(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsRe
                         let description = env.ir.get_function(function_ref);
                         Ok((description.name, None))
                     }
-                    JsFunction::Native(name, args, _, _) => {
+                    JsFunction::Native(name, args, _, _) | JsFunction::Closure(name, args) => {
                         Ok((name, Some(args)))
                     }
                     JsFunction::Bound => {
@@ -181,12 +181,12 @@ pub fn Function_bind(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
     } else {
         let mut scope = JsScope::new_local_thin(env, max(args.argc + 1, 2), None);
         
-        scope.set(0, this_arg);
-        scope.set(1, args.arg(env, 0));
+        scope.set(env, 0, this_arg);
+        scope.set(env, 1, args.arg(env, 0));
         
         if args.argc > 1 {
             for i in 0..args.argc - 1 {
-                scope.set(i + 2, args.arg(env, i + 1));
+                scope.set(env, i + 2, args.arg(env, i + 1));
             }
         }
         
@@ -203,7 +203,7 @@ pub fn Function_bind(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
         let length = JsValue::new_number(length as f64);
         result.define_own_property(env, name::LENGTH, JsDescriptor::new_value(length, false, false, true), false).ok();
         
-        result.set_scope(Some(scope));
+        result.set_scope(env, Some(scope));
         
         Ok(result.as_value())
     }
@@ -79,7 +79,7 @@ impl<'a> Parser<'a> {
                 match lit {
                     Lit::Null => Ok(JsValue::new_null()),
                     Lit::Boolean(value) => Ok(JsValue::new_bool(value)),
-                    Lit::String(value) => Ok(JsString::from_str(self.env, &value).as_value()),
+                    Lit::String(value) => Ok(try!(JsString::try_from_str(self.env, &value)).as_value()),
                     Lit::Number(value) => Ok(JsValue::new_number(value))
                 }
             }
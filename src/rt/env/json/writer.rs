@@ -78,7 +78,7 @@ impl<'a> JsonWriter<'a> {
             
             try!(self.write_value(&mut json, value));
             
-            Ok(JsString::from_str(self.env, &json).as_value())
+            Ok(try!(JsString::try_from_str(self.env, &json)).as_value())
         }
     }
     
@@ -148,8 +148,14 @@ pub fn Math_pow(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<JsVa
 }
 
 // 15.8.2.14 random ( )
-pub fn Math_random(_env: &mut JsEnv, _mode: JsFnMode, _args: JsArgs) -> JsResult<JsValue> {
-    Ok(JsValue::new_number(random::<f64>()))
+pub fn Math_random(env: &mut JsEnv, _mode: JsFnMode, _args: JsArgs) -> JsResult<JsValue> {
+    let result = if let Some(ref mut hook) = env.hooks.random {
+        (&mut **hook)()
+    } else {
+        random::<f64>()
+    };
+    
+    Ok(JsValue::new_number(result))
 }
 
 // 15.8.2.15 round (x)
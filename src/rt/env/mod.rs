@@ -1,7 +1,7 @@
 #![allow(non_snake_case)]
 
 use rt::{JsEnv, JsObject, JsFunction, JsFn, JsValue, JsDescriptor, JsItem};
-use rt::{JsStoreType, JsScope, JsString, JsHandle};
+use rt::{JsStoreType, JsScope, JsString, JsHandle, JsIntrinsics};
 use ::JsResult;
 use syntax::Name;
 use syntax::token::name;
@@ -70,13 +70,13 @@ macro_rules! value {
     }
 }
 
-pub fn setup(env: &mut JsEnv) -> JsResult<()> {
-    setup_global(env);
+pub fn setup(env: &mut JsEnv, intrinsics: &JsIntrinsics) -> JsResult<()> {
+    setup_global(env, intrinsics);
     
     Ok(())
 }
 
-fn setup_global(env: &mut JsEnv) {
+fn setup_global(env: &mut JsEnv, intrinsics: &JsIntrinsics) {
     let _scope = env.new_local_scope();
     
     let global = JsObject::new_local(&env, JsStoreType::Hash);
@@ -93,7 +93,7 @@ fn setup_global(env: &mut JsEnv) {
     let mut object_prototype = JsObject::new_local(&env, JsStoreType::Hash);
     env.add_handle(JsHandle::Object, object_prototype);
     
-    global.set_prototype(Some(object_prototype.as_value()));
+    global.set_prototype(env, Some(object_prototype.as_value()));
     global.set_class(Some(name::OBJECT_CLASS));
     
     // Constructor setup.
@@ -102,13 +102,27 @@ fn setup_global(env: &mut JsEnv) {
     setup_object(env, global, &mut object_prototype);
     setup_array(env, global);
     setup_string(env, global);
-    setup_date(env, global);
+    if intrinsics.date {
+        setup_date(env, global);
+    } else {
+        setup_disabled(env, &[JsHandle::Date]);
+    }
     setup_number(env, global);
     setup_boolean(env, global);
-    setup_math(env, global);
-    setup_regexp(env, global);
-    setup_json(env, global);
-    setup_console(env, global);
+    if intrinsics.math {
+        setup_math(env, global);
+    }
+    if intrinsics.regexp {
+        setup_regexp(env, global);
+    } else {
+        setup_disabled(env, &[JsHandle::RegExpClass, JsHandle::RegExp]);
+    }
+    if intrinsics.json {
+        setup_json(env, global);
+    }
+    if intrinsics.console {
+        setup_console(env, global);
+    }
     setup_error(env, global);
     
     // Build global functions
@@ -117,7 +131,9 @@ fn setup_global(env: &mut JsEnv) {
     function!(global, name::PARSE_FLOAT, Global_parseFloat, 1, env);
     function!(global, name::IS_NAN, Global_isNaN, 1, env);
     function!(global, name::IS_FINITE, Global_isFinite, 1, env);
-    function!(global, name::EVAL, Global_eval, 1, env);
+    if intrinsics.eval {
+        function!(global, name::EVAL, Global_eval, 1, env);
+    }
     function!(global, name::DECODE_URI, Global_decodeURI, 1, env);
     function!(global, name::DECODE_URI_COMPONENT, Global_decodeURIComponent, 1, env);
     function!(global, name::ENCODE_URI, Global_encodeURI, 1, env);
@@ -217,7 +233,7 @@ fn setup_array<'a>(env: &mut JsEnv, mut global: JsValue) {
     
     let array_prototype = prototype.as_value();
     // Set the [[Prototype]] value as usual.
-    prototype.set_prototype(Some(env.handle(JsHandle::Object).as_value()));
+    prototype.set_prototype(env, Some(env.handle(JsHandle::Object).as_value()));
     prototype.set_class(Some(name::ARRAY_CLASS));
     
     // Create the class as usual.
@@ -432,6 +448,16 @@ fn setup_math<'a>(env: &mut JsEnv, mut global: JsValue) {
     function!(class, name::TAN, Math_tan, 1, env);
 }
 
+// Handles are added in order, so the handles of a disabled intrinsic are
+// taken by plain objects. They cannot be constructed, which is checked where
+// the handles are used.
+fn setup_disabled(env: &mut JsEnv, handles: &[JsHandle]) {
+    for &handle in handles {
+        let object = env.create_object();
+        env.add_handle(handle, object);
+    }
+}
+
 fn setup_regexp<'a>(env: &mut JsEnv, mut global: JsValue) {
     let class = env.new_native_function(Some(name::REGEXP_CLASS), 2, RegExp_constructor);
     
@@ -482,10 +508,10 @@ fn setup_error<'a>(env: &mut JsEnv, global: JsValue) {
         let prototype = class.get(env, name::PROTOTYPE).ok().unwrap();
         let mut prototype_obj = prototype.unwrap_object();
         if error_prototype.is_some() {
-            prototype_obj.set_prototype(error_prototype);
+            prototype_obj.set_prototype(env, error_prototype);
         }
         if error_class.is_some() {
-            class_obj.set_prototype(error_class);
+            class_obj.set_prototype(env, error_class);
         }
         
         let value = JsString::from_str(env, "").as_value();
@@ -19,7 +19,7 @@ pub fn Number_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsRe
         let mut this = this_arg.unwrap_object();
         
         this.set_class(Some(name::NUMBER_CLASS));
-        this.set_value(arg);
+        this.set_value(env, arg);
         
         Ok(this_arg)
     } else {
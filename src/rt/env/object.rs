@@ -39,9 +39,9 @@ pub fn Object_create(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
             let mut result = JsObject::new_local(env, JsStoreType::Hash);
             
             if ty == JsType::Null {
-                result.set_prototype(None);
+                result.set_prototype(env, None);
             } else {
-                result.set_prototype(Some(args.arg(env, 0)));
+                result.set_prototype(env, Some(args.arg(env, 0)));
             }
             
             let properties = args.arg(env, 1);
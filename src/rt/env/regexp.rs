@@ -1,9 +1,24 @@
 use ::{JsResult, JsError};
-use rt::{JsEnv, JsArgs, JsValue, JsFnMode, JsRegExp, JsString, JsItem, JsDescriptor};
+use rt::{JsEnv, JsArgs, JsValue, JsFnMode, JsRegExp, JsString, JsItem, JsDescriptor, JsHandle};
 use gc::*;
 use syntax::token::name;
 use syntax::Name;
 
+impl JsEnv {
+    /// Creates a RegExp object for regular expression literals and for the
+    /// String methods that take a pattern. Fails when the RegExp intrinsic
+    /// is disabled.
+    pub fn new_regexp_object(&mut self, args: Vec<JsValue>) -> JsResult<JsValue> {
+        let class = self.handle(JsHandle::RegExpClass);
+        
+        if !class.can_construct() {
+            return Err(JsError::new_type(self, ::errors::TYPE_REGEXP_DISABLED));
+        }
+        
+        class.construct(self, args)
+    }
+}
+
 // 15.10.3 The RegExp Constructor Called as a Function
 // 15.10.4 The RegExp Constructor
 pub fn RegExp_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsResult<JsValue> {
@@ -35,9 +35,9 @@ pub fn String_constructor(env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsRe
     let this_arg = args.this(env);
     let mut object = this_arg.unwrap_object();
     
-    object.set_prototype(Some(env.handle(JsHandle::String).as_value()));
+    object.set_prototype(env, Some(env.handle(JsHandle::String).as_value()));
     object.set_class(Some(name::STRING_CLASS));
-    object.set_value(arg);
+    object.set_value(env, arg);
     
     let value = JsValue::new_number(arg.unwrap_string().chars().len() as f64);
     try!(object.define_own_property(env, name::LENGTH, JsDescriptor::new_value(value, false, false, false), false));
@@ -275,7 +275,7 @@ pub fn String_concat(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
         strings.push(arg);
     }
     
-    Ok(JsString::concat(env, &strings).as_value())
+    Ok(try!(JsString::concat(env, &strings)).as_value())
 }
 
 
@@ -321,7 +321,7 @@ pub fn String_match(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult<
     let mut rx = if regex.class() == Some(name::REGEXP_CLASS) {
         regex
     } else {
-        try!(env.handle(JsHandle::RegExpClass).construct(env, vec![regex]))
+        try!(env.new_regexp_object(vec![regex]))
     };
     
     let global = rx.unwrap_object().value(env).unwrap_regexp().global();
@@ -388,7 +388,7 @@ pub fn String_search(env: &mut JsEnv, _mode: JsFnMode, args: JsArgs) -> JsResult
     let rx = if regex.class() == Some(name::REGEXP_CLASS) {
         regex
     } else {
-        try!(env.handle(JsHandle::RegExpClass).construct(env, vec![regex]))
+        try!(env.new_regexp_object(vec![regex]))
     };
     
     let regexp = rx.unwrap_object().value(env).unwrap_regexp();
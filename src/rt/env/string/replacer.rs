@@ -68,7 +68,7 @@ impl<'a> Replacer<'a> {
             try!(replacer.replace_string(string, search_value));
         }
         
-        Ok(JsString::from_str(replacer.env, &replacer.result).as_value())
+        Ok(try!(JsString::try_from_str(replacer.env, &replacer.result)).as_value())
     }
     
     fn replace_regexp(&mut self, string: JsValue, mut search_value: JsValue) -> JsResult<()> {
@@ -0,0 +1,74 @@
+use rt::{JsEnv, JsArgs, JsObject, JsStoreType, JsItem, JsType};
+use gc::{Local, Root};
+use syntax::Name;
+use ::{JsResult, JsError};
+use std::any::Any;
+
+// A host class describes objects that wrap a Rust value. All instances
+// share the prototype of the class, on which the methods of the class
+// can be defined.
+#[derive(Clone)]
+pub struct JsHostClass {
+    name: Name,
+    prototype: Root<JsObject>
+}
+
+impl JsHostClass {
+    pub fn name(&self) -> Name {
+        self.name
+    }
+    
+    pub fn prototype(&self, env: &JsEnv) -> Local<JsObject> {
+        self.prototype.as_local(env)
+    }
+}
+
+impl JsEnv {
+    /// Registers a host class with the provided class name. The prototype of
+    /// the class inherits from Object.prototype.
+    pub fn register_host_class(&mut self, name: &str) -> JsHostClass {
+        let _scope = self.new_local_scope();
+        
+        let name = self.intern(name);
+        
+        let mut prototype = self.create_object();
+        prototype.set_class(Some(name));
+        
+        JsHostClass {
+            name: name,
+            prototype: prototype.as_root(self)
+        }
+    }
+    
+    /// Creates an instance of the host class. The payload is owned by the
+    /// object and is dropped when the object is collected.
+    pub fn new_host_object(&mut self, class: &JsHostClass, payload: Box<Any>) -> JsResult<Local<JsObject>> {
+        let mut object = JsObject::new_local(self, JsStoreType::Hash);
+        
+        object.set_class(Some(class.name));
+        object.set_prototype(self, Some(class.prototype(self).as_value()));
+        try!(object.set_host_payload(self, payload));
+        
+        Ok(object)
+    }
+}
+
+impl JsArgs {
+    /// Gets the payload of the this argument, if it is a host object with
+    /// a payload of type T. Otherwise a TypeError is returned. The payload
+    /// borrows the environment, so copy out what is needed before using the
+    /// environment again.
+    pub fn this_host<'a, T: Any>(&self, env: &'a mut JsEnv) -> JsResult<&'a mut T> {
+        let this = self.this(env);
+        
+        if this.ty() == JsType::Object {
+            let mut object = this.unwrap_object();
+            
+            if object.host_payload(env).map_or(false, |payload| payload.is::<T>()) {
+                return Ok(object.host_payload(env).unwrap().downcast_mut::<T>().unwrap());
+            }
+        }
+        
+        Err(JsError::new_type(env, ::errors::TYPE_NOT_HOST_OBJECT))
+    }
+}
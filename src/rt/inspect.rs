@@ -0,0 +1,229 @@
+use rt::{JsEnv, JsValue, JsItem, JsType, JsDescriptor};
+use rt::object::JsStoreKey;
+use ::JsResult;
+use syntax::Name;
+use syntax::token::name;
+
+const MAX_DEPTH : usize = 2;
+const MAX_ITEMS : usize = 100;
+
+impl JsEnv {
+    /// Formats a value for display, similar to how it would be written as a
+    /// literal. Objects are expanded up to a fixed depth. Accessors are not
+    /// invoked; they are shown as [Getter], [Setter] or [Getter/Setter].
+    pub fn inspect(&mut self, value: JsValue) -> JsResult<String> {
+        let _scope = self.new_local_scope();
+        
+        let mut result = String::new();
+        let mut seen = Vec::new();
+        
+        try!(self.inspect_value(&mut result, value, 0, &mut seen));
+        
+        Ok(result)
+    }
+    
+    fn inspect_value(&mut self, result: &mut String, value: JsValue, depth: usize, seen: &mut Vec<JsValue>) -> JsResult<()> {
+        match value.ty() {
+            JsType::Undefined => result.push_str("undefined"),
+            JsType::Null => result.push_str("null"),
+            JsType::Boolean => result.push_str(if value.unwrap_bool() { "true" } else { "false" }),
+            JsType::Number => {
+                let number = value.unwrap_number();
+                
+                if number == 0.0 && number.is_sign_negative() {
+                    result.push_str("-0");
+                } else {
+                    result.push_str(&try!(value.to_string(self)).to_string());
+                }
+            }
+            JsType::String => push_quoted(result, &value.unwrap_string().to_string()),
+            JsType::Object => try!(self.inspect_object(result, value, depth, seen)),
+            ty @ _ => { result.push_str(&format!("[{:?}]", ty)); }
+        }
+        
+        Ok(())
+    }
+    
+    fn inspect_object(&mut self, result: &mut String, value: JsValue, depth: usize, seen: &mut Vec<JsValue>) -> JsResult<()> {
+        if seen.iter().any(|item| *item == value) {
+            result.push_str("[Circular]");
+            return Ok(());
+        }
+        
+        let object = value.unwrap_object();
+        let class = object.class();
+        let is_array = class == Some(name::ARRAY_CLASS);
+        
+        // Build the prefix for objects that have a special representation.
+        
+        let prefix = if value.is_callable() {
+            let name = self.inspect_data_property(value, name::NAME);
+            match name {
+                Some(ref name) if name.len() > 0 => Some(format!("[Function: {}]", name)),
+                _ => Some("[Function]".to_string())
+            }
+        } else if class == Some(name::ERROR_CLASS) {
+            Some(format!("[{}]", try!(value.to_string(self)).to_string()))
+        } else if class == Some(name::DATE_CLASS) || class == Some(name::REGEXP_CLASS) {
+            Some(try!(value.to_string(self)).to_string())
+        } else if
+            class == Some(name::NUMBER_CLASS) ||
+            class == Some(name::STRING_CLASS) ||
+            class == Some(name::BOOLEAN_CLASS)
+        {
+            let mut primitive = String::new();
+            let inner = object.value(self);
+            try!(self.inspect_value(&mut primitive, inner, depth, seen));
+            
+            let class = self.name_to_string(class.unwrap());
+            Some(format!("[{}: {}]", class, primitive))
+        } else {
+            None
+        };
+        
+        // Collect the enumerable own properties. Array indexes and string
+        // indexes of boxed strings are handled separately.
+        
+        let mut keys = Vec::new();
+        
+        for offset in 0.. {
+            match object.get_key(self, offset) {
+                JsStoreKey::Key(key, enumerable) => {
+                    if !enumerable {
+                        continue;
+                    }
+                    if key.is_index() && class == Some(name::STRING_CLASS) {
+                        continue;
+                    }
+                    keys.push(key);
+                }
+                JsStoreKey::End => break,
+                JsStoreKey::Missing => {}
+            }
+        }
+        
+        if keys.len() == 0 {
+            match prefix {
+                Some(prefix) => result.push_str(&prefix),
+                None => result.push_str(if is_array { "[]" } else { "{}" })
+            }
+            return Ok(());
+        }
+        
+        if depth >= MAX_DEPTH {
+            match prefix {
+                Some(prefix) => result.push_str(&prefix),
+                None => result.push_str(if is_array { "[Array]" } else { "[Object]" })
+            }
+            return Ok(());
+        }
+        
+        seen.push(value);
+        
+        if let Some(prefix) = prefix {
+            result.push_str(&prefix);
+            result.push(' ');
+        }
+        
+        result.push_str(if is_array { "[ " } else { "{ " });
+        
+        for i in 0..keys.len() {
+            if i == MAX_ITEMS {
+                result.push_str(&format!("... {} more items", keys.len() - MAX_ITEMS));
+                break;
+            }
+            
+            if i > 0 {
+                result.push_str(", ");
+            }
+            
+            let key = keys[i];
+            
+            if !(is_array && key.is_index()) {
+                let key = self.name_to_string(key);
+                if is_identifier(&key) {
+                    result.push_str(&key);
+                } else {
+                    push_quoted(result, &key);
+                }
+                result.push_str(": ");
+            }
+            
+            match object.get_own_property(self, key) {
+                Some(desc) => try!(self.inspect_descriptor(result, desc, depth, seen)),
+                None => result.push_str("undefined")
+            }
+        }
+        
+        result.push_str(if is_array { " ]" } else { " }" });
+        
+        seen.pop();
+        
+        Ok(())
+    }
+    
+    fn inspect_descriptor(&mut self, result: &mut String, desc: JsDescriptor, depth: usize, seen: &mut Vec<JsValue>) -> JsResult<()> {
+        if desc.is_accessor() {
+            let get = !desc.get().is_undefined();
+            let set = !desc.set().is_undefined();
+            
+            result.push_str(match (get, set) {
+                (true, true) => "[Getter/Setter]",
+                (true, false) => "[Getter]",
+                _ => "[Setter]"
+            });
+            
+            Ok(())
+        } else {
+            self.inspect_value(result, desc.value(), depth + 1, seen)
+        }
+    }
+    
+    fn inspect_data_property(&mut self, value: JsValue, property: Name) -> Option<String> {
+        match value.get_property(self, property) {
+            Some(ref desc) if desc.is_data() && desc.value().ty() == JsType::String => {
+                Some(desc.value().unwrap_string().to_string())
+            }
+            _ => None
+        }
+    }
+    
+    fn name_to_string(&self, name: Name) -> String {
+        if let Some(index) = name.index() {
+            index.to_string()
+        } else {
+            self.ir.interner().get(name).to_string()
+        }
+    }
+}
+
+fn is_identifier(string: &str) -> bool {
+    let mut first = true;
+    
+    for c in string.chars() {
+        let valid = c == '_' || c == '$' || c.is_alphabetic() || (!first && c.is_digit(10));
+        if !valid {
+            return false;
+        }
+        first = false;
+    }
+    
+    !first
+}
+
+fn push_quoted(result: &mut String, string: &str) {
+    result.push('\'');
+    
+    for c in string.chars() {
+        match c {
+            '\r' => result.push_str("\\r"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\\' => result.push_str("\\\\"),
+            '\'' => result.push_str("\\'"),
+            c @ _ => result.push(c)
+        }
+    }
+    
+    result.push('\'');
+}
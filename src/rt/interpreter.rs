@@ -1,11 +1,15 @@
 use rt::{JsEnv, JsRawValue, JsValue, JsString, JsItem, JsIterator, JsScope, JsType, JsArgs};
-use rt::{JsDescriptor, JsPreferredType, JsFnMode, JsHandle};
+use rt::{JsDescriptor, JsPreferredType, JsFnMode, JsHandle, JsBacktrace, JsPauseReason};
+use rt::PropertyCache;
+use rt::backtrace::FrameState;
 use rt::{GC_VALUE};
 use gc::*;
 use ::{JsResult, JsError};
 use ir::IrFunction;
 use ir::builder::{Block, Ir};
+use ir::register::{RegisterInstruction, RegisterOp, Operand, Target, BinaryOp, CompareOp};
 use std::rc::Rc;
+use std::cell::Cell;
 use rt::stack::StackFrame;
 use syntax::Name;
 use syntax::ast::{CastType, ScopeType};
@@ -14,6 +18,9 @@ use syntax::parser::ParseMode;
 
 const CALL_PROLOG : usize = 2;
 
+// Number of stack slots that must be free before running an instruction.
+const INSTRUCTION_RESERVE : usize = 4;
+
 enum Next {
     Next,
     Return,
@@ -63,14 +70,20 @@ struct Frame<'a> {
     ip: usize,
     locals: StackFrame,
     args: JsArgs,
-    thrown: Option<Root<JsRawValue>>,
+    thrown: Option<(Root<JsRawValue>, Rc<JsBacktrace>)>,
     strict: bool,
     scope: usize,
-    built_scope: bool
+    built_scope: bool,
+    caches: &'a [Cell<PropertyCache>]
 }
 
 impl JsEnv {
     pub fn call_block(&mut self, block: Rc<Block>, args: JsArgs, function: &IrFunction, scope: Local<JsScope>) -> JsResult<()> {
+        // Make sure the missing arguments, locals and scope below fit on the stack.
+        
+        let required = (function.args as usize).saturating_sub(args.argc) + block.locals.len() + 1;
+        try!(self.check_stack(required));
+        
         // Ensure that we have enough arguments on the stack. We will have the number
         // of arguments already on the stack that the caller pushed. However,
         // our load/store param calls will write directly into these locations.
@@ -115,6 +128,14 @@ impl JsEnv {
         
         let locals = self.stack.create_frame(locals);
         
+        self.set_frame_state(FrameState {
+            block: block.clone(),
+            args: args.frame,
+            locals: locals,
+            scope: scope,
+            strict: function.strict
+        });
+        
         let mut frame = Frame {
             env: self,
             ip: 0,
@@ -123,21 +144,78 @@ impl JsEnv {
             thrown: None,
             strict: function.strict,
             scope: scope,
-            built_scope: function.build_scope != ScopeType::None
+            built_scope: function.build_scope != ScopeType::None,
+            caches: &block.caches
         };
         
+        if frame.env.coverage.is_some() {
+            frame.env.cover_call();
+        }
+        
         loop {
             debugln!("IP: {}", frame.ip);
             
             let ir = &ir[frame.ip];
             
-            match frame.call_stmt(ir) {
+            frame.env.set_frame_ip(frame.ip);
+            
+            if frame.env.debug.is_active() {
+                frame.env.debug_instruction(&block, frame.ip);
+            }
+            
+            if frame.env.profiler.is_some() {
+                frame.env.profile_instruction();
+            }
+            
+            if frame.env.coverage.is_some() {
+                frame.env.cover_instruction(&block, frame.ip);
+            }
+            
+            // The debugger can stop at every instruction, so register
+            // instructions are only used while it is inactive.
+            
+            let register = if frame.env.debug.is_active() {
+                None
+            } else {
+                block.registers.get(frame.ip).and_then(|register| *register)
+            };
+            
+            // An instruction pushes at most a few values; the stack guard
+            // has room for those once the capacity check has passed.
+            
+            let checked = match frame.env.check_abort() {
+                Ok(_) => frame.env.check_stack(INSTRUCTION_RESERVE),
+                Err(error) => Err(error)
+            };
+            
+            let next = match checked {
+                Ok(_) => match register {
+                    Some(register) => frame.call_register(&register),
+                    None => frame.call_stmt(ir)
+                },
+                Err(error) => Next::Throw(error)
+            };
+            
+            match next {
                 Next::Next => {}
                 Next::Return => {
                     return Ok(());
                 }
                 Next::Throw(error) => {
-                    frame.thrown = Some(error.as_runtime(frame.env));
+                    // Aborts skip all try/catch and finally blocks.
+                    
+                    if error.is_uncatchable() {
+                        frame.env.stack.drop_frame(frame.args.frame);
+                        
+                        return Err(error);
+                    }
+                    
+                    let backtrace = match error {
+                        JsError::Runtime(_, ref backtrace) => backtrace.clone(),
+                        _ => Rc::new(frame.env.backtrace())
+                    };
+                    
+                    frame.thrown = Some((error.as_runtime(frame.env), backtrace));
                     
                     // Find the try/catch block that belongs to the current instruction.
                     
@@ -266,7 +344,7 @@ impl JsEnv {
                     // If we don't have an error in flight, we process the pending
                     // leave.
                     
-                    if let Some(ref error) = frame.thrown {
+                    if let Some((ref error, ref backtrace)) = frame.thrown {
                         // We have an error in flight and are exiting the finally
                         // block. We need to find the next frame that can process
                         // the error.
@@ -323,7 +401,7 @@ impl JsEnv {
                         if !found {
                             frame.env.stack.drop_frame(frame.args.frame);
                             
-                            return Err(JsError::Runtime(error.clone()));
+                            return Err(JsError::Runtime(error.clone(), backtrace.clone()));
                         }
                     } else if let Some(leave_) = leave {
                         // There are two cases for a pending leave. In the simple case,
@@ -444,7 +522,9 @@ impl<'a> Frame<'a> {
                 self.env.stack.push(result.as_raw());
             }
             Ir::Debugger => {
-                // Not implemented. Debugger support is not yet part of rjs.
+                if self.env.debug.is_active() {
+                    self.env.debug_pause(JsPauseReason::DebuggerStatement);
+                }
             }
             Ir::DeleteIndex => {
                 let _scope = self.env.new_local_scope();
@@ -607,6 +687,10 @@ impl<'a> Frame<'a> {
                 let jump = self.env.strict_eq(arg1, arg2);
                 self.env.stack.drop_frame(frame);
                 
+                if self.env.coverage.is_some() {
+                    self.env.cover_branch(self.ip, jump);
+                }
+                
                 if jump {
                     self.ip  = label.offset();
                     return Next::Next;
@@ -618,6 +702,9 @@ impl<'a> Frame<'a> {
                 let frame = self.env.stack.create_frame(1);
                 let jump = !frame.get(&self.env, 0).to_boolean();
                 self.env.stack.drop_frame(frame);
+                if self.env.coverage.is_some() {
+                    self.env.cover_branch(self.ip, jump);
+                }
                 if jump {
                     self.ip  = label.offset();
                     return Next::Next;
@@ -629,6 +716,9 @@ impl<'a> Frame<'a> {
                 let frame = self.env.stack.create_frame(1);
                 let jump = frame.get(&self.env, 0).to_boolean();
                 self.env.stack.drop_frame(frame);
+                if self.env.coverage.is_some() {
+                    self.env.cover_branch(self.ip, jump);
+                }
                 if jump {
                     self.ip  = label.offset();
                     return Next::Next;
@@ -659,7 +749,7 @@ impl<'a> Frame<'a> {
                 self.locals.set(self.scope, parent);
             }
             Ir::LoadException => {
-                if let Some(ref exception) = self.thrown {
+                if let Some((ref exception, _)) = self.thrown {
                     self.env.stack.push(**exception)
                 } else {
                     panic!("load exception statement without exception in flight");
@@ -693,13 +783,17 @@ impl<'a> Frame<'a> {
             Ir::LoadGlobal(name) => {
                 let _scope = self.env.new_local_scope();
                 
-                let global = self.env.handle(JsHandle::Global).as_value();
+                let global = self.env.handle(JsHandle::Global);
                 
-                if !global.has_property(self.env, name) {
+                if !global.as_value().has_property(self.env, name) {
                     return Next::Throw(JsError::new_reference(self.env));
                 }
                 
-                let result = local_try!(global.get(self.env, name));
+                let result = if self.env.inline_caches {
+                    local_try!(global.get_cached(self.env, name, &self.caches[self.ip]))
+                } else {
+                    local_try!(global.as_value().get(self.env, name))
+                };
                 
                 self.env.stack.push(result.as_raw());
             }
@@ -735,8 +829,13 @@ impl<'a> Frame<'a> {
                 let _scope = self.env.new_local_scope();
                 
                 let frame = self.env.stack.create_frame(1);
+                let target = frame.get(&self.env, 0);
                 
-                let result = local_try!(frame.get(&self.env, 0).get(self.env, name));
+                let result = if self.env.inline_caches && target.ty() == JsType::Object {
+                    local_try!(target.unwrap_object().get_cached(self.env, name, &self.caches[self.ip]))
+                } else {
+                    local_try!(target.get(self.env, name))
+                };
                 
                 self.env.stack.drop_frame(frame);
                 
@@ -753,9 +852,7 @@ impl<'a> Frame<'a> {
                 let flags = self.env.ir.interner().get(flags);
                 let flags = JsString::from_str(self.env, &*flags).as_value();
                 
-                let regexp = self.env.handle(JsHandle::RegExpClass);
-                
-                let result = local_try!(regexp.construct(self.env, vec![pattern, flags]));
+                let result = local_try!(self.env.new_regexp_object(vec![pattern, flags]));
                 
                 self.env.stack.push(result.as_raw());
             }
@@ -928,7 +1025,7 @@ impl<'a> Frame<'a> {
                 let frame = self.env.stack.create_frame(1);
                 
                 let mut scope = self.find_scope(depth, false);
-                scope.set(index as usize, frame.get(&self.env, 0));
+                scope.set(&self.env, index as usize, frame.get(&self.env, 0));
                 
                 self.env.stack.drop_frame(frame);
             }
@@ -943,7 +1040,12 @@ impl<'a> Frame<'a> {
                 let frame = self.env.stack.create_frame(2);
                 let mut target = frame.get(&self.env, 0);
                 let value = frame.get(&self.env, 1);
-                local_try!(target.put(self.env, name, value, self.strict));
+                
+                if self.env.inline_caches && target.ty() == JsType::Object {
+                    local_try!(target.unwrap_object().put_cached(self.env, name, value, self.strict, &self.caches[self.ip]));
+                } else {
+                    local_try!(target.put(self.env, name, value, self.strict));
+                }
                 
                 self.env.stack.drop_frame(frame);
             }
@@ -990,7 +1092,7 @@ impl<'a> Frame<'a> {
                 let arguments = frame.get(&self.env, 0);
                 
                 let mut scope = self.get_scope().unwrap();
-                scope.set_arguments(arguments);
+                scope.set_arguments(&self.env, arguments);
                 
                 self.env.stack.drop_frame(frame);
             }
@@ -1071,7 +1173,9 @@ impl<'a> Frame<'a> {
                 
                 self.env.stack.drop_frame(frame);
                 
-                return Next::Throw(JsError::Runtime(error));
+                let backtrace = Rc::new(self.env.backtrace());
+                
+                return Next::Throw(JsError::Runtime(error, backtrace));
             }
             Ir::ToPrimitive(JsPreferredType::String) => local_try!(self.cast(CastType::StringPrimitive)),
             Ir::ToPrimitive(JsPreferredType::Number) => local_try!(self.cast(CastType::NumberPrimitive)),
@@ -1164,6 +1268,161 @@ impl<'a> Frame<'a> {
         Next::Next
     }
     
+    fn call_register(&mut self, register: &RegisterInstruction) -> Next {
+        match register.op {
+            RegisterOp::Move(target, operand) => {
+                let value = self.get_operand(operand);
+                self.set_target(target, value);
+            }
+            RegisterOp::Binary(op, target, lhs, rhs) => {
+                let lhs = self.get_operand(lhs);
+                let rhs = self.get_operand(rhs);
+                
+                let result = local_try!(self.binary(op, lhs, rhs));
+                
+                self.set_target(target, result);
+            }
+            RegisterOp::Increment(target, operand, delta) => {
+                let value = self.get_operand(operand);
+                
+                let number = if value.ty() == JsType::Number {
+                    value.as_value(&*self.env).unwrap_number()
+                } else {
+                    let _scope = self.env.new_local_scope();
+                    
+                    local_try!(value.as_value(&*self.env).to_number(self.env))
+                };
+                
+                self.set_target(target, JsRawValue::new_number(number + delta));
+            }
+            RegisterOp::Compare(op, target, lhs, rhs) => {
+                let lhs = self.get_operand(lhs);
+                let rhs = self.get_operand(rhs);
+                
+                let result = local_try!(self.compare(op, lhs, rhs));
+                
+                self.set_target(target, JsRawValue::new_bool(result));
+            }
+            RegisterOp::CompareJump(op, lhs, rhs, jump_if, label) => {
+                let lhs = self.get_operand(lhs);
+                let rhs = self.get_operand(rhs);
+                
+                let jump = local_try!(self.compare(op, lhs, rhs)) == jump_if;
+                
+                // Branches are counted at the offset of the jump.
+                
+                if self.env.coverage.is_some() {
+                    self.env.cover_branch(self.ip + register.len - 1, jump);
+                }
+                
+                if jump {
+                    self.ip = label.offset();
+                    return Next::Next;
+                }
+            }
+        }
+        
+        self.ip += register.len;
+        
+        Next::Next
+    }
+    
+    fn get_operand(&mut self, operand: Operand) -> JsRawValue {
+        match operand {
+            Operand::Local(local) => self.locals.raw_get(local.offset()),
+            Operand::Param(index) => self.args.frame.raw_get(index as usize + CALL_PROLOG),
+            Operand::Number(value) => JsRawValue::new_number(value),
+            Operand::Stack => {
+                let frame = self.env.stack.create_frame(1);
+                let value = frame.raw_get(0);
+                self.env.stack.drop_frame(frame);
+                
+                value
+            }
+        }
+    }
+    
+    fn set_target(&mut self, target: Target, value: JsRawValue) {
+        match target {
+            Target::Local(local) => self.locals.set(local.offset(), value),
+            Target::Param(index) => self.args.frame.set(index as usize + CALL_PROLOG, value),
+            Target::Stack => self.env.stack.push(value)
+        }
+    }
+    
+    // The operands of a register instruction are not on the stack, so they
+    // are rooted in a local scope before anything can allocate. Operations
+    // on two numbers are done in place.
+    fn binary(&mut self, op: BinaryOp, lhs: JsRawValue, rhs: JsRawValue) -> JsResult<JsRawValue> {
+        if lhs.ty() == JsType::Number && rhs.ty() == JsType::Number {
+            let x = lhs.as_value(&*self.env).unwrap_number();
+            let y = rhs.as_value(&*self.env).unwrap_number();
+            
+            match op {
+                BinaryOp::Add => return Ok(JsRawValue::new_number(x + y)),
+                BinaryOp::Subtract => return Ok(JsRawValue::new_number(x - y)),
+                BinaryOp::Multiply => return Ok(JsRawValue::new_number(x * y)),
+                _ => {}
+            }
+        }
+        
+        let _scope = self.env.new_local_scope();
+        
+        let lhs = lhs.as_value(&*self.env);
+        let rhs = rhs.as_value(&*self.env);
+        
+        let result = match op {
+            BinaryOp::Add => return Ok(try!(self.env.add(lhs, rhs)).as_raw()),
+            BinaryOp::Subtract => try!(self.env.subtract(lhs, rhs)),
+            BinaryOp::Multiply => try!(self.env.multiply(lhs, rhs)),
+            BinaryOp::Divide => try!(self.env.divide(lhs, rhs)),
+            BinaryOp::Modulus => try!(self.env.modulus(lhs, rhs)),
+            BinaryOp::BitAnd => try!(self.env.bit_and(lhs, rhs)),
+            BinaryOp::BitOr => try!(self.env.bit_or(lhs, rhs)),
+            BinaryOp::BitXOr => try!(self.env.bit_xor(lhs, rhs)),
+            BinaryOp::Lsh => try!(self.env.lsh(lhs, rhs)),
+            BinaryOp::Rsh => try!(self.env.rsh(lhs, rhs)),
+            BinaryOp::RshZeroFill => try!(self.env.unsigned_rsh(lhs, rhs))
+        };
+        
+        Ok(JsRawValue::new_number(result))
+    }
+    
+    fn compare(&mut self, op: CompareOp, lhs: JsRawValue, rhs: JsRawValue) -> JsResult<bool> {
+        if lhs.ty() == JsType::Number && rhs.ty() == JsType::Number {
+            let x = lhs.as_value(&*self.env).unwrap_number();
+            let y = rhs.as_value(&*self.env).unwrap_number();
+            
+            // Comparisons with NaN are false, except for the not equal
+            // comparisons.
+            
+            return Ok(match op {
+                CompareOp::Lt => x < y,
+                CompareOp::Gt => x > y,
+                CompareOp::Le => x <= y,
+                CompareOp::Ge => x >= y,
+                CompareOp::Eq | CompareOp::StrictEq => x == y,
+                CompareOp::Ne | CompareOp::StrictNe => x != y
+            });
+        }
+        
+        let _scope = self.env.new_local_scope();
+        
+        let lhs = lhs.as_value(&*self.env);
+        let rhs = rhs.as_value(&*self.env);
+        
+        match op {
+            CompareOp::Lt => self.env.compare_lt(lhs, rhs),
+            CompareOp::Gt => self.env.compare_gt(lhs, rhs),
+            CompareOp::Le => self.env.compare_le(lhs, rhs),
+            CompareOp::Ge => self.env.compare_ge(lhs, rhs),
+            CompareOp::Eq => self.env.eq(lhs, rhs),
+            CompareOp::Ne => self.env.ne(lhs, rhs),
+            CompareOp::StrictEq => Ok(self.env.strict_eq(lhs, rhs)),
+            CompareOp::StrictNe => Ok(!self.env.strict_eq(lhs, rhs))
+        }
+    }
+    
     fn get_scope(&self) -> Option<Local<JsScope>> {
         let scope = self.locals.get(&self.env, self.scope);
         if scope.is_undefined() {
@@ -0,0 +1,76 @@
+use rt::{JsEnv, JsError, JsAbort};
+use ::JsResult;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Handle to interrupt a running environment from another thread. The
+// interpreter aborts with JsAbort::Interrupted at the next instruction.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    interrupt: Arc<AtomicBool>
+}
+
+impl InterruptHandle {
+    /// Interrupts the code that is running. An interrupt while no code is
+    /// running aborts the next run of the environment.
+    pub fn interrupt(&self) {
+        self.interrupt.store(true, Ordering::SeqCst);
+    }
+}
+
+impl JsEnv {
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupt: self.interrupt.clone()
+        }
+    }
+    
+    /// Limits the number of instructions that may still be executed. When the
+    /// budget is exhausted, execution aborts with JsAbort::BudgetExhausted and
+    /// a new budget must be set before running code again. None removes the
+    /// limit.
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+    }
+    
+    pub fn instruction_budget(&self) -> Option<u64> {
+        self.budget
+    }
+    
+    #[inline(always)]
+    pub fn check_abort(&mut self) -> JsResult<()> {
+        if let Some(budget) = self.budget {
+            if budget == 0 {
+                return Err(JsError::Abort(JsAbort::BudgetExhausted));
+            }
+            
+            self.budget = Some(budget - 1);
+        }
+        
+        // The interrupt is reset once it has been delivered. The out of
+        // memory condition stays until the next run starts, because native
+        // code can ignore the abort and continue allocating.
+        
+        try!(self.check_out_of_memory());
+        
+        if self.interrupt.load(Ordering::Relaxed) {
+            self.interrupt.store(false, Ordering::SeqCst);
+            
+            return Err(JsError::Abort(JsAbort::Interrupted));
+        }
+        
+        Ok(())
+    }
+    
+    /// Aborts when an allocation failed or had to use the reserve of the
+    /// heap. Native code that allocates without returning to the interpreter
+    /// goes through this on every property access and definition.
+    #[inline(always)]
+    pub fn check_out_of_memory(&self) -> JsResult<()> {
+        if self.heap.is_out_of_memory() {
+            Err(JsError::Abort(JsAbort::OutOfMemory))
+        } else {
+            Ok(())
+        }
+    }
+}
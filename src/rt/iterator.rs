@@ -40,6 +40,8 @@ impl JsIterator {
             len: 0
         };
         
+        env.heap.write_barrier(result.as_ptr().ptr());
+        
         result
     }
 }
@@ -76,6 +78,8 @@ impl Local<JsIterator> {
                         
                         self.target = target.as_ptr();
                         self.offset = 0;
+                        
+                        env.heap.write_barrier(self.as_ptr().ptr());
                     } else {
                         self.target = Ptr::null();
                         self.offset = 0;
@@ -106,6 +110,8 @@ impl Local<JsIterator> {
             
             self.seen = new_seen.as_ptr();
             seen = new_seen;
+            
+            env.heap.write_barrier(self.as_ptr().ptr());
         }
         
         seen[self.len] = name;
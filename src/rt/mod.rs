@@ -9,10 +9,13 @@ use syntax::parser::ParseMode;
 use std::i32;
 use std::mem::transmute;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::io;
 
 pub use self::value::{JsRawValue, JsValue};
-pub use self::object::{JsObject, JsStoreType};
+pub use self::object::{JsObject, JsStoreType, PropertyCache};
+use self::object::Shapes;
 pub use self::string::JsString;
 pub use self::null::JsNull;
 pub use self::undefined::JsUndefined;
@@ -21,6 +24,19 @@ pub use self::boolean::JsBoolean;
 pub use self::iterator::JsIterator;
 pub use self::scope::JsScope;
 pub use self::regexp::JsRegExp;
+pub use self::host::JsHostClass;
+pub use self::interrupt::InterruptHandle;
+pub use self::builder::{JsEnvBuilder, JsIntrinsics, DEFAULT_STACK_SIZE, DEFAULT_NATIVE_STACK_SIZE, DEFAULT_MAX_CALL_DEPTH};
+use self::builder::JsHooks;
+pub use self::convert::{FromJs, IntoJs, JsTypedFn};
+pub use self::backtrace::{JsBacktrace, JsStackFrame, JsLocation};
+use self::backtrace::CallFrame;
+pub use self::debugger::{Debugger, JsBreakpoint, JsPauseReason, JsResume};
+use self::debugger::DebugState;
+pub use self::profiler::JsProfile;
+use self::profiler::Profiler;
+pub use self::coverage::{JsCoverage, JsFileCoverage, JsFunctionCoverage, JsStatementCoverage, JsBranchCoverage};
+use self::coverage::Coverage;
 
 mod interpreter;
 mod utf;
@@ -40,6 +56,16 @@ mod walker;
 mod allocators;
 mod regexp;
 mod fmt;
+mod inspect;
+mod host;
+mod interrupt;
+mod builder;
+mod convert;
+mod backtrace;
+mod debugger;
+mod profiler;
+mod coverage;
+mod snapshot;
 
 const GC_ARRAY_STORE : u32 = 1;
 const GC_ENTRY : u32 = 2;
@@ -84,47 +110,27 @@ pub struct JsEnv {
     global_scope: Root<JsScope>,
     ir: IrContext,
     stack: Rc<stack::Stack>,
-    privileged: bool
+    privileged: bool,
+    strict: bool,
+    call_depth: usize,
+    max_call_depth: usize,
+    native_stack_base: usize,
+    native_stack_size: usize,
+    stack_overflow: bool,
+    budget: Option<u64>,
+    interrupt: Arc<AtomicBool>,
+    hooks: JsHooks,
+    frames: Vec<CallFrame>,
+    debug: DebugState,
+    profiler: Option<Box<Profiler>>,
+    coverage: Option<Box<Coverage>>,
+    shapes: Shapes,
+    inline_caches: bool
 }
 
 impl JsEnv {
     pub fn new() -> JsResult<JsEnv> {
-        let stack = Rc::new(stack::Stack::new());
-        
-        let walker = Box::new(walker::Walker::new(stack.clone()));
-        validate_walker(&*walker);
-        
-        let heap = GcHeap::new(walker, GcOpts::default());
-        
-        let global_scope = heap.alloc_root::<JsScope>(GC_SCOPE);
-        
-        let mut env = JsEnv {
-            heap: heap,
-            global_scope: global_scope,
-            ir: IrContext::new(),
-            stack: stack,
-            privileged: true,
-            handles: Vec::new()
-        };
-        
-        if let Err(error) = env::setup(&mut env) {
-            let _scope = env.new_local_scope();
-            
-            let error = error.as_runtime(&mut env).as_value(&env);
-            
-            let error = if let Ok(error) = error.to_string(&mut env) {
-                error.to_string()
-            } else {
-                "(cannot convert error to string)".to_string()
-            };
-            
-            panic!("(setup): uncaught {}", error);
-        }
-        
-        // Turn of privileged mode for normal code (i.e. not setup code).
-        env.privileged = false;
-        
-        Ok(env)
+        JsEnvBuilder::new().build()
     }
     
     pub fn handle(&self, handle: JsHandle) -> Local<JsObject> {
@@ -141,12 +147,31 @@ impl JsEnv {
     }
     
     pub fn run(&mut self, file_name: &str) -> JsResult<Root<JsRawValue>> {
-        self.run_strict(file_name, false)
+        let strict = self.strict;
+        self.run_strict(file_name, strict)
     }
     
     pub fn run_strict(&mut self, file_name: &str, strict: bool) -> JsResult<Root<JsRawValue>> {
         let function_ref = try!(self.ir.parse_file(file_name, strict, self.privileged));
         
+        self.run_file_program(function_ref)
+    }
+    
+    /// Runs the file using the bytecode in the cache file. When the cache
+    /// file was written for another version of the file, the file is parsed
+    /// and the cache file is written again.
+    pub fn run_cached(&mut self, file_name: &str, cache_file_name: &str) -> JsResult<Root<JsRawValue>> {
+        let strict = self.strict;
+        self.run_cached_strict(file_name, cache_file_name, strict)
+    }
+    
+    pub fn run_cached_strict(&mut self, file_name: &str, cache_file_name: &str, strict: bool) -> JsResult<Root<JsRawValue>> {
+        let function_ref = try!(self.ir.parse_file_cached(file_name, cache_file_name, strict, self.privileged));
+        
+        self.run_file_program(function_ref)
+    }
+    
+    fn run_file_program(&mut self, function_ref: FunctionRef) -> JsResult<Root<JsRawValue>> {
         let mut ir = String::new();
         try!(self.ir.print_ir(&mut ir));
         debugln!("{}", ir);
@@ -160,12 +185,28 @@ impl JsEnv {
     }
     
     pub fn eval(&mut self, js: &str) -> JsResult<Root<JsRawValue>> {
+        let strict = self.strict;
+        self.eval_strict(js, strict)
+    }
+    
+    pub fn eval_strict(&mut self, js: &str, strict: bool) -> JsResult<Root<JsRawValue>> {
         let _scope = self.new_local_scope();
         
         let global = self.handle(JsHandle::Global).as_value();
         let global_scope = self.global_scope.as_local(self);
         
-        self.eval_scoped(js, false, global, global_scope, ParseMode::Normal)
+        self.eval_scoped(js, strict, global, global_scope, ParseMode::Normal)
+    }
+    
+    /// Compiles the code without running it and returns the printed IR of
+    /// the global code and all functions declared in it.
+    pub fn print_ir(&mut self, js: &str, strict: bool) -> JsResult<String> {
+        try!(self.ir.parse_string(js, strict, ParseMode::Normal, self.privileged));
+        
+        let mut ir = String::new();
+        try!(self.ir.print_ir(&mut ir));
+        
+        Ok(ir)
     }
     
     fn eval_scoped(&mut self, js: &str, strict: bool, this: JsValue, scope: Local<JsScope>, mode: ParseMode) -> JsResult<Root<JsRawValue>> {
@@ -178,6 +219,13 @@ impl JsEnv {
         self.run_program(function_ref, this, scope)
     }
     
+    /// Gives access to the properties of the global object.
+    pub fn global(&mut self) -> JsGlobal {
+        JsGlobal {
+            env: self
+        }
+    }
+    
     fn run_program(&mut self, function_ref: FunctionRef, this: JsValue, scope: Local<JsScope>) -> JsResult<Root<JsRawValue>> {
         let function = self.ir.get_function(function_ref);
         
@@ -195,6 +243,14 @@ impl JsEnv {
         Ok(result)
     }
     
+    /// Roots the value so it stays alive outside of a local scope.
+    pub fn new_root(&self, value: JsValue) -> Root<JsRawValue> {
+        let mut result = self.heap.alloc_root::<JsRawValue>(GC_VALUE);
+        *result = value.as_raw();
+        
+        result
+    }
+    
     pub fn intern(&self, name: &str) -> Name {
         self.ir.interner().intern(name)
     }
@@ -211,6 +267,17 @@ impl JsEnv {
         Ok(self.intern(&index.to_string()))
     }
     
+    /// Creates a function object that calls the closure. The closure is
+    /// owned by the function object and is dropped when the function object
+    /// is collected. Closures cannot be used as a constructor. A closure is
+    /// called again when script it calls back into calls it, so mutable
+    /// state must be kept in e.g. a Cell or RefCell.
+    pub fn new_native_closure(&mut self, name: &str, args: u32, closure: Box<JsClosure>) -> JsValue {
+        let name = if name.len() == 0 { None } else { Some(self.intern(name)) };
+        
+        JsObject::new_closure(self, name, args, closure).as_value()
+    }
+    
     fn new_native_function<'a>(&mut self, name: Option<Name>, args: u32, function: JsFn) -> JsValue {
         let mut result = JsObject::new_function(self, JsFunction::Native(name, args, function, true), false).as_value();
         
@@ -225,6 +292,78 @@ impl JsEnv {
     pub fn new_local_scope(&self) -> LocalScope {
         self.heap.new_local_scope()
     }
+    
+    /// Gets the collection counts and pause times of the heap, and the
+    /// number and size of the live objects per GC type.
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+    
+    /// Runs a collection. The native closures and host payloads of the
+    /// objects that are collected are dropped.
+    pub fn gc(&self) {
+        self.heap.gc();
+    }
+}
+
+pub struct JsGlobal<'a> {
+    env: &'a mut JsEnv
+}
+
+impl<'a> JsGlobal<'a> {
+    pub fn get(&mut self, name: &str) -> JsResult<Root<JsRawValue>> {
+        let _scope = self.env.new_local_scope();
+        
+        let value = try!(self.get_value(name));
+        
+        Ok(self.env.new_root(value))
+    }
+    
+    pub fn set(&mut self, name: &str, value: JsValue) -> JsResult<()> {
+        let _scope = self.env.new_local_scope();
+        
+        let name = self.env.intern(name);
+        let mut global = self.env.handle(JsHandle::Global);
+        
+        global.put(self.env, name, value, true)
+    }
+    
+    /// Calls the global function with the provided arguments. The this
+    /// argument of the call is undefined.
+    pub fn call_function(&mut self, name: &str, args: &[JsValue]) -> JsResult<Root<JsRawValue>> {
+        let _scope = self.env.new_local_scope();
+        
+        let function = try!(self.get_value(name));
+        if !function.is_callable() {
+            return Err(JsError::new_type(self.env, ::errors::TYPE_NOT_A_FUNCTION));
+        }
+        
+        let result = try!(function.call(self.env, JsValue::new_undefined(), args.to_vec(), false));
+        
+        Ok(self.env.new_root(result))
+    }
+    
+    /// Calls the global constructor with the provided arguments, i.e. the
+    /// equivalent of new Ctor(args).
+    pub fn construct(&mut self, name: &str, args: &[JsValue]) -> JsResult<Root<JsRawValue>> {
+        let _scope = self.env.new_local_scope();
+        
+        let constructor = try!(self.get_value(name));
+        if !constructor.can_construct() {
+            return Err(JsError::new_type(self.env, ::errors::TYPE_NOT_A_CONSTRUCTOR));
+        }
+        
+        let result = try!(constructor.construct(self.env, args.to_vec()));
+        
+        Ok(self.env.new_root(result))
+    }
+    
+    fn get_value(&mut self, name: &str) -> JsResult<JsValue> {
+        let name = self.env.intern(name);
+        let global = self.env.handle(JsHandle::Global);
+        
+        global.get(self.env, name)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -257,6 +396,8 @@ pub trait JsItem {
     
     // 8.12.3 [[Get]] (P)
     fn get(&self, env: &mut JsEnv, property: Name) -> JsResult<JsValue> {
+        try!(env.check_out_of_memory());
+        
         if let Some(desc) = self.get_property(env, property) {
             return if desc.is_data() {
                 Ok(desc.value())
@@ -430,7 +571,7 @@ pub trait JsItem {
     }
     
     fn call(&self, env: &mut JsEnv, this: JsValue, args: Vec<JsValue>, strict: bool) -> JsResult<JsValue> {
-        let args = JsArgs::new(env, this, self.as_value(), &args);
+        let args = try!(JsArgs::new(env, this, self.as_value(), &args));
         
         try!(env.call(JsFnMode::new(false, strict), args));
         
@@ -446,7 +587,7 @@ pub trait JsItem {
     // 13.2.2 [[Construct]]
     // 15.3.4.5.2 [[Construct]]
     fn construct(&self, env: &mut JsEnv, args: Vec<JsValue>) -> JsResult<JsValue> {
-        let args = JsArgs::new(env, JsValue::new_undefined(), self.as_value(), &args);
+        let args = try!(JsArgs::new(env, JsValue::new_undefined(), self.as_value(), &args));
         
         try!(env.construct(args));
         
@@ -463,7 +604,7 @@ pub trait JsItem {
         panic!("prototype not supported on {:?}", self.as_value().ty());
     }
     
-    fn set_prototype(&mut self, prototype: Option<JsValue>) {
+    fn set_prototype(&mut self, env: &JsEnv, prototype: Option<JsValue>) {
         panic!("prototype not supported on {:?}", self.as_value().ty());
     }
     
@@ -491,7 +632,7 @@ pub trait JsItem {
         panic!("scope not supported");
     }
     
-    fn set_scope(&mut self, scope: Option<Local<JsScope>>) {
+    fn set_scope(&mut self, env: &JsEnv, scope: Option<Local<JsScope>>) {
         panic!("scope not supported");
     }
 }
@@ -760,7 +901,12 @@ pub struct JsArgs {
 }
 
 impl JsArgs {
-    pub fn new(env: &JsEnv, this: JsValue, function: JsValue, args: &[JsValue]) -> JsArgs {
+    pub fn new(env: &mut JsEnv, this: JsValue, function: JsValue, args: &[JsValue]) -> JsResult<JsArgs> {
+        // The arguments come from e.g. Function.prototype.apply, so their
+        // number isn't bounded.
+        
+        try!(env.check_stack(args.len() + 2));
+        
         let stack = &*env.stack;
         
         let frame = stack.create_frame(0);
@@ -772,10 +918,10 @@ impl JsArgs {
             stack.push(arg.as_raw());
         }
         
-        JsArgs {
+        Ok(JsArgs {
             frame: frame,
             argc: args.len()
-        }
+        })
     }
     
     pub fn arg(&self, env: &JsEnv, index: usize) -> JsValue {
@@ -841,9 +987,12 @@ impl JsArgs {
 
 pub type JsFn = fn(&mut JsEnv, JsFnMode, JsArgs) -> JsResult<JsValue>;
 
+pub type JsClosure = Fn(&mut JsEnv, JsFnMode, JsArgs) -> JsResult<JsValue>;
+
 pub enum JsFunction {
     Ir(FunctionRef),
     Native(Option<Name>, u32, JsFn, bool),
+    Closure(Option<Name>, u32),
     Bound
 }
 
@@ -853,11 +1002,40 @@ pub enum JsError {
     Io(io::Error),
     Lex(String),
     Parse(String),
+    // The input ended before the program was complete, e.g. because a block
+    // or comment wasn't closed. Scripts see this as a SyntaxError.
+    Incomplete(String),
     Reference(String),
-    Runtime(Root<JsRawValue>)
+    Runtime(Root<JsRawValue>, Rc<JsBacktrace>),
+    Abort(JsAbort)
+}
+
+// Reasons for aborting execution. Aborts cannot be caught by scripts; they
+// unwind all the way up to the host.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JsAbort {
+    Interrupted,
+    BudgetExhausted,
+    OutOfMemory
 }
 
 impl JsError {
+    pub fn is_uncatchable(&self) -> bool {
+        match *self {
+            JsError::Abort(..) => true,
+            _ => false
+        }
+    }
+    
+    /// Gets the call-frame chain at the point where the error was thrown.
+    /// Only runtime errors have a backtrace.
+    pub fn backtrace(&self) -> Option<&JsBacktrace> {
+        match *self {
+            JsError::Runtime(_, ref backtrace) => Some(backtrace),
+            _ => None
+        }
+    }
+    
     fn new_error(env: &mut JsEnv, handle: JsHandle, message: Option<&str>, file_name: Option<&str>, line_number: Option<usize>) -> JsResult<Root<JsRawValue>> {
         // If construction of the error fails, we simply propagate the error itself.
         
@@ -888,7 +1066,7 @@ impl JsError {
     
     pub fn new_runtime(env: &mut JsEnv, handle: JsHandle, message: Option<&str>, file_name: Option<&str>, line_number: Option<usize>) -> JsError {
         match Self::new_error(env, handle, message, file_name, line_number) {
-            Ok(error) => JsError::Runtime(error),
+            Ok(error) => JsError::Runtime(error, Rc::new(env.backtrace())),
             Err(error) => error
         }
     }
@@ -897,6 +1075,15 @@ impl JsError {
         Self::new_runtime(env, JsHandle::TypeError, Some(message), None, None)
     }
     
+    /// Creates the abort for an allocation that does not fit the heap. The
+    /// heap is flagged as out of memory, so code that ignores the error
+    /// still aborts at its next instruction or property access.
+    pub fn new_out_of_memory(env: &JsEnv) -> JsError {
+        env.heap.set_out_of_memory();
+        
+        JsError::Abort(JsAbort::OutOfMemory)
+    }
+    
     pub fn new_range(env: &mut JsEnv) -> JsError {
         Self::new_runtime(env, JsHandle::RangeError, None, None, None)
     }
@@ -915,7 +1102,7 @@ impl JsError {
     
     pub fn as_runtime(&self, env: &mut JsEnv) -> Root<JsRawValue> {
         match *self {
-            JsError::Lex(ref message) | JsError::Parse(ref message) => {
+            JsError::Lex(ref message) | JsError::Parse(ref message) | JsError::Incomplete(ref message) => {
                 match Self::new_error(env, JsHandle::SyntaxError, Some(&message), None, None) {
                     Ok(error) => error,
                     Err(error) => error.as_runtime(env)
@@ -927,7 +1114,7 @@ impl JsError {
                     Err(error) => error.as_runtime(env)
                 }
             }
-            JsError::Runtime(ref error) => error.clone(),
+            JsError::Runtime(ref error, _) => error.clone(),
             ref error @ _ => {
                 // TODO #73: This could be nicer.
                 let mut result = env.heap.alloc_root::<JsRawValue>(GC_VALUE);
@@ -946,8 +1133,10 @@ impl ::std::fmt::Debug for JsError {
             JsError::Io(ref err) => try!(err.fmt(formatter)),
             JsError::Lex(ref message) => try!(write!(formatter, "Lex {{ {} }}", message)),
             JsError::Parse(ref message) => try!(write!(formatter, "Parse {{ {} }}", message)),
+            JsError::Incomplete(ref message) => try!(write!(formatter, "Incomplete {{ {} }}", message)),
             JsError::Reference(ref message) => try!(write!(formatter, "Reference {{ {} }}", message)),
-            JsError::Runtime(..) => try!(write!(formatter, "Runtime {{ .. }}"))
+            JsError::Runtime(..) => try!(write!(formatter, "Runtime {{ .. }}")),
+            JsError::Abort(abort) => try!(write!(formatter, "Abort {{ {:?} }}", abort))
         }
         write!(formatter, " }}")
     }
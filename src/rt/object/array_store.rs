@@ -1,4 +1,5 @@
 use rt::{JsEnv, JsDescriptor, GC_ARRAY_STORE, GC_ENTRY};
+use ::JsResult;
 use rt::validate_walker_field;
 use rt::object::{Store, StoreKey, Entry};
 use rt::object::hash_store::HashStore;
@@ -25,6 +26,8 @@ impl ArrayStore {
             props: props.as_ptr()
         };
         
+        env.heap.write_barrier(store.as_ptr().ptr());
+        
         store
     }
 }
@@ -40,27 +43,27 @@ impl Local<ArrayStore> {
 }
 
 impl Store for Local<ArrayStore> {
-    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) {
+    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<()> {
         if let Some(index) = name.index() {
             let mut entry = env.heap.alloc_local(GC_ENTRY);
             
             *entry = Entry::from_descriptor(value, name, -1);
             
-            self.array(env).set_value(env, index, entry);
+            self.array(env).set_value(env, index, entry)
         } else {
-            self.props(env).add(env, name, value);
+            self.props(env).add(env, name, value)
         }
     }
     
-    fn remove(&mut self, env: &JsEnv, name: Name) {
+    fn remove(&mut self, env: &JsEnv, name: Name) -> JsResult<()> {
         if let Some(index) = name.index() {
             let mut entry = env.heap.alloc_local(GC_ENTRY);
             
             *entry = Entry::empty();
             
-            self.array(env).set_value(env, index, entry);
+            self.array(env).set_value(env, index, entry)
         } else {
-            self.props(env).remove(env, name);
+            self.props(env).remove(env, name)
         }
     }
     
@@ -80,18 +83,32 @@ impl Store for Local<ArrayStore> {
         }
     }
     
-    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> bool {
+    fn get_entry(&self, env: &JsEnv, name: Name) -> Option<Entry> {
+        if let Some(index) = name.index() {
+            let entry = self.array(env).get_value(index);
+            
+            if entry.is_valid() {
+                Some(entry)
+            } else {
+                None
+            }
+        } else {
+            self.props(env).get_entry(env, name)
+        }
+    }
+    
+    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<bool> {
         if let Some(index) = name.index() {
             let mut array = self.array(env);
             
             if array.get_value(index).is_valid() {
                 let mut entry = env.heap.alloc_local(GC_ENTRY);
                 *entry = Entry::from_descriptor(value, name, -1);
-                array.set_value(env, index, entry);
-                return true;
+                try!(array.set_value(env, index, entry));
+                return Ok(true);
             }
             
-            false
+            Ok(false)
         } else {
             self.props(env).replace(env, name, value)
         }
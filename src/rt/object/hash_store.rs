@@ -1,17 +1,20 @@
 const INITIAL_OBJECT : usize = 20;
 
-use rt::{JsEnv, JsDescriptor, GC_HASH_STORE, GC_ENTRY};
+use rt::{JsEnv, JsDescriptor, JsRawValue, JsError, GC_HASH_STORE, GC_ENTRY};
+use ::JsResult;
 use rt::validate_walker_field;
 use rt::object::{Store, StoreKey, Entry};
+use rt::object::shape::{NO_SHAPE, EMPTY_SHAPE};
 use syntax::Name;
-use gc::{Local, Array, GcWalker, ptr_t};
+use gc::{Local, Array, AsPtr, GcWalker, ptr_t};
 use std::mem::{transmute, zeroed, size_of};
 
 // Modifications to this struct must be synchronized with the GC walker.
 #[repr(C)]
 pub struct HashStore {
     entries: Array<Entry>,
-    count: u32
+    count: u32,
+    shape: u32
 }
 
 impl HashStore {
@@ -20,9 +23,12 @@ impl HashStore {
         
         *store = HashStore {
             entries: unsafe { env.heap.alloc_array::<Entry>(GC_ENTRY, primes::get_prime(INITIAL_OBJECT)) },
-            count: 0
+            count: 0,
+            shape: EMPTY_SHAPE
         };
         
+        env.heap.write_barrier(store.as_ptr().ptr());
+        
         store
     }
 }
@@ -64,6 +70,25 @@ impl Local<HashStore> {
         self.count as usize
     }
     
+    pub fn shape(&self) -> u32 {
+        self.shape
+    }
+    
+    /// Gets the index of the entry of the property. The index stays valid
+    /// for all stores with the same shape.
+    pub fn find(&self, name: Name) -> Option<usize> {
+        self.find_entry(name)
+    }
+    
+    pub fn entry(&self, index: usize) -> Entry {
+        self.entries[index]
+    }
+    
+    pub fn set_entry_value(&mut self, env: &JsEnv, index: usize, value: JsRawValue) {
+        self.entries[index].value1 = value;
+        env.heap.write_barrier(self.entries.ptr());
+    }
+    
     fn hash(&self, name: Name) -> u32 {
         name.value() as u32 % self.entries.len() as u32
     }
@@ -72,13 +97,16 @@ impl Local<HashStore> {
         (self.entries.len() * 7 / 10) as u32
     }
     
-    fn grow_entries(&mut self, env: &JsEnv) {
+    fn grow_entries(&mut self, env: &JsEnv) -> JsResult<()> {
         let entries = self.entries.as_local(env);
         
-        unsafe {
-            self.entries = env.heap.alloc_array(GC_ENTRY, primes::get_prime(entries.len() * 2));
+        match unsafe { env.heap.try_alloc_array(GC_ENTRY, primes::get_prime(entries.len() * 2)) } {
+            Some(new_entries) => self.entries = new_entries,
+            None => return Err(JsError::new_out_of_memory(env))
         }
         
+        env.heap.write_barrier(self.as_ptr().ptr());
+        
         self.count = 0;
         
         let mut entry = env.heap.alloc_local(GC_ENTRY);
@@ -86,22 +114,18 @@ impl Local<HashStore> {
         for i in 0..entries.len() {
             *entry = entries[i];
             if entry.is_valid() {
-                self.add(env, entry.name, &entry.as_property(env));
+                self.add_entry(env, entry.name, &entry.as_property(env));
             }
         }
+        
+        Ok(())
     }
-}
-
-impl Store for Local<HashStore> {
-    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) {
+    
+    // Adds an entry. The caller must have grown the entries when the store
+    // is over its load factor.
+    fn add_entry(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) {
         assert!(!self.find_entry(name).is_some());
         
-        // Grow the entries when we have to.
-        
-        if self.count > self.max_load_factor() {
-            self.grow_entries(env);
-        }
-        
         // If the entry at the ideal location doesn't have the correct has,
         // we're going to move that entry.
         
@@ -117,11 +141,12 @@ impl Store for Local<HashStore> {
             
             *copy = self.entries[hash as usize];
             
-            self.remove(env, copy.name);
+            self.remove_entry(copy.name);
             
             // Put the new entry at the ideal location.
             
             self.entries[hash as usize] = Entry::from_descriptor(value, name, -1);
+            env.heap.write_barrier(self.entries.ptr());
             
             // Increment the count.
             
@@ -129,7 +154,7 @@ impl Store for Local<HashStore> {
             
             // And now add the previous entry.
             
-            self.add(env, copy.name, &copy.as_property(env));
+            self.add_entry(env, copy.name, &copy.as_property(env));
         } else {
             // Find the end of the chain currently at the entry.
             
@@ -169,6 +194,7 @@ impl Store for Local<HashStore> {
             // Put the new entry into the free location.
             
             self.entries[free] = Entry::from_descriptor(value, name, -1);
+            env.heap.write_barrier(self.entries.ptr());
             
             // Fixup the chain if we have one.
             
@@ -182,7 +208,7 @@ impl Store for Local<HashStore> {
         }
     }
     
-    fn remove(&mut self, _: &JsEnv, name: Name) {
+    fn remove_entry(&mut self, name: Name) {
         // Find the position of the element.
         
         let mut last = -1;
@@ -226,6 +252,28 @@ impl Store for Local<HashStore> {
             self.count -= 1;
         }
     }
+}
+
+impl Store for Local<HashStore> {
+    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<()> {
+        if self.count > self.max_load_factor() {
+            try!(self.grow_entries(env));
+        }
+        
+        self.add_entry(env, name, value);
+        
+        self.shape = env.shapes.add(self.shape, name, self.count as usize);
+        
+        Ok(())
+    }
+    
+    fn remove(&mut self, _: &JsEnv, name: Name) -> JsResult<()> {
+        self.remove_entry(name);
+        
+        self.shape = NO_SHAPE;
+        
+        Ok(())
+    }
     
     fn get_value(&self, env: &JsEnv, name: Name) -> Option<JsDescriptor> {
         if let Some(index) = self.find_entry(name) {
@@ -239,14 +287,19 @@ impl Store for Local<HashStore> {
         }
     }
     
-    fn replace(&mut self, _: &JsEnv, name: Name, value: &JsDescriptor) -> bool {
+    fn get_entry(&self, _: &JsEnv, name: Name) -> Option<Entry> {
+        self.find_entry(name).map(|index| self.entries[index])
+    }
+    
+    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<bool> {
         if let Some(index) = self.find_entry(name) {
             let entry = self.entries[index];
             self.entries[index] = Entry::from_descriptor(value, entry.name, entry.next);
+            env.heap.write_barrier(self.entries.ptr());
             
-            true
+            Ok(true)
         } else {
-            false
+            Ok(false)
         }
     }
     
@@ -334,6 +387,10 @@ pub unsafe fn validate_walker_for_hash_store(walker: &GcWalker) {
     validate_walker_field(walker, GC_HASH_STORE, ptr, false);
     object.count = 0;
     
+    object.shape = 1;
+    validate_walker_field(walker, GC_HASH_STORE, ptr, false);
+    object.shape = 0;
+    
     assert_eq!(size_of::<HashStore>(), 16);
 }
 
@@ -2,7 +2,7 @@ use syntax::{Name, INVALID_NAME};
 use syntax::ast::FunctionRef;
 use syntax::token::name;
 use rt::{JsEnv, JsFunction, JsRawValue, JsValue, JsItem, JsDescriptor, JsScope, JsType, JsString};
-use rt::{JsArgs, JsFnMode, JsHandle, JsFn};
+use rt::{JsArgs, JsFnMode, JsHandle, JsFn, JsClosure};
 use rt::{GC_OBJECT, GC_ENTRY};
 use rt::validate_walker_field;
 use rt::value::validate_walker_for_embedded_value;
@@ -11,12 +11,18 @@ use ::{JsResult, JsError};
 use util::manualbox::ManualBox;
 use self::hash_store::HashStore;
 use self::array_store::ArrayStore;
+use self::shape::NO_SHAPE;
+pub use self::shape::Shapes;
 use std::str::FromStr;
 use std::mem::{zeroed, transmute, size_of};
+use std::rc::Rc;
+use std::cell::Cell;
+use std::any::Any;
 
 mod hash_store;
 mod array_store;
 mod sparse_array;
+mod shape;
 
 // Modifications to this struct must be synchronized with the GC walker.
 // The class name is optional, but not stored as an option, to save space.
@@ -58,6 +64,8 @@ impl JsObject {
         
         *result = Self::new(env, ty);
         
+        env.heap.write_barrier(result.as_ptr().ptr());
+        
         result
     }
     
@@ -71,6 +79,7 @@ impl JsObject {
         
         let (name, args, strict) = match function {
             JsFunction::Native(name, args, _, _) => (name, args, strict),
+            JsFunction::Closure(name, args) => (name, args, strict),
             JsFunction::Ir(function_ref) => {
                 let function = env.ir.get_function(function_ref);
                 (function.name, function.args, strict || function.strict)
@@ -110,9 +119,27 @@ impl JsObject {
         result
     }
     
-    pub fn finalize(&mut self) {
-        if let Function::Native(ref mut native) = self.function {
-            native.drop();
+    pub fn new_closure(env: &mut JsEnv, name: Option<Name>, args: u32, closure: Box<JsClosure>) -> Local<JsObject> {
+        let mut result = Self::new_function(env, JsFunction::Closure(name, args), false);
+        
+        result.function = Function::Native(ManualBox::new(NativeFunction {
+            name: name,
+            args: args,
+            function: NativeCallback::Closure(Rc::new(closure)),
+            can_construct: false
+        }));
+        
+        result
+    }
+    
+    /// Takes the native function or host payload out of the object when it
+    /// is collected. These can hold roots, which can't be dropped while
+    /// the collection runs, so the caller drops them after the collection.
+    pub fn finalize(&mut self) -> Option<Box<Any>> {
+        match self.function {
+            Function::Native(ref mut native) => Some(native.take() as Box<Any>),
+            Function::Host(ref mut payload) => Some(*payload.take()),
+            _ => None
         }
     }
 }
@@ -126,14 +153,39 @@ impl Local<JsObject> {
         self.value.as_value(env)
     }
     
-    pub fn set_value(&mut self, value: JsValue) {
+    pub fn set_value(&mut self, env: &JsEnv, value: JsValue) {
         self.value = value.as_raw();
+        env.heap.write_barrier(self.as_ptr().ptr());
     }
     
     pub fn extensible(&self) -> bool {
         self.extensible
     }
     
+    /// Calls the callback with the name and value of every own property of
+    /// the object. Accessors are reported with both their getter and setter.
+    /// This does not allocate, so it can be used while walking the heap.
+    pub fn walk_properties(&self, env: &JsEnv, callback: &mut FnMut(Name, JsValue)) {
+        let mut offset = 0;
+        
+        loop {
+            match self.store.get_key(env, offset) {
+                StoreKey::Key(name, _) => {
+                    if let Some(entry) = self.store.get_entry(env, name) {
+                        callback(name, entry.value1.as_value(env));
+                        if entry.is_accessor() {
+                            callback(name, entry.value2.as_value(env));
+                        }
+                    }
+                }
+                StoreKey::Missing => {},
+                StoreKey::End(..) => break
+            }
+            
+            offset += 1;
+        }
+    }
+    
     pub fn set_extensible(&mut self, extensible: bool) {
         self.extensible = extensible;
     }
@@ -142,6 +194,163 @@ impl Local<JsObject> {
         self.function.to_function()
     }
     
+    /// Gets the host payload of the object. The payload is borrowed for as
+    /// long as the environment is: the environment cannot collect the object
+    /// or hand out the payload again while the borrow is alive.
+    pub fn host_payload<'a>(&mut self, _env: &'a mut JsEnv) -> Option<&'a mut Any> {
+        match self.function {
+            // The payload is allocated outside of the GC heap, so it does not
+            // move with the object.
+            Function::Host(ref mut payload) => Some(unsafe { &mut *(&mut ***payload as *mut Any) }),
+            _ => None
+        }
+    }
+    
+    pub fn set_host_payload(&mut self, env: &mut JsEnv, payload: Box<Any>) -> JsResult<()> {
+        if !self.function.is_none() {
+            return Err(JsError::new_type(env, ::errors::TYPE_HAS_HOST_PAYLOAD));
+        }
+        
+        self.function = Function::Host(ManualBox::new(payload));
+        
+        Ok(())
+    }
+    
+    pub fn call_closure(&self, env: &mut JsEnv, mode: JsFnMode, args: JsArgs) -> JsResult<JsValue> {
+        // Take a reference to the closure so it stays alive when the object
+        // is moved or collected while the closure is running.
+        
+        let closure = match self.function {
+            Function::Native(ref native) => {
+                match native.function {
+                    NativeCallback::Closure(ref closure) => Some(closure.clone()),
+                    _ => None
+                }
+            }
+            _ => None
+        };
+        
+        match closure {
+            Some(closure) => (&**closure)(env, mode, args),
+            None => Err(JsError::new_type(env, ::errors::TYPE_NOT_A_FUNCTION))
+        }
+    }
+    
+    // Gets the shape of the object when the location of its properties can be
+    // cached. Properties of string objects are partly provided by the string.
+    fn cacheable_shape(&self, env: &JsEnv) -> u32 {
+        if self.store.ty != JsStoreType::Hash || self.value.ty() == JsType::String {
+            NO_SHAPE
+        } else {
+            self.store.as_hash(env).shape()
+        }
+    }
+    
+    // Finds the object that has the property and the index of its entry using
+    // the cache, filling the cache when it does not match. Properties are
+    // cached when they are found on the object itself or, unless own_only is
+    // set, on its prototype.
+    fn find_cached(&self, env: &JsEnv, property: Name, cache: &Cell<PropertyCache>, own_only: bool) -> Option<(Local<JsObject>, usize)> {
+        let shape = self.cacheable_shape(env);
+        if shape == NO_SHAPE {
+            return None;
+        }
+        
+        let mut cached = cache.get();
+        
+        if cached.shape != shape {
+            cached = PropertyCache::new();
+            
+            if let Some(index) = self.store.as_hash(env).find(property) {
+                cached = PropertyCache {
+                    shape: shape,
+                    holder_shape: NO_SHAPE,
+                    index: index as u32
+                };
+            } else if !own_only && !self.prototype.is_null() {
+                let prototype = self.prototype.as_local(env);
+                let holder_shape = prototype.cacheable_shape(env);
+                
+                if holder_shape != NO_SHAPE {
+                    if let Some(index) = prototype.store.as_hash(env).find(property) {
+                        cached = PropertyCache {
+                            shape: shape,
+                            holder_shape: holder_shape,
+                            index: index as u32
+                        };
+                    }
+                }
+            }
+            
+            cache.set(cached);
+            
+            if cached.shape == NO_SHAPE {
+                return None;
+            }
+        }
+        
+        let holder = if cached.holder_shape == NO_SHAPE {
+            *self
+        } else {
+            if self.prototype.is_null() {
+                return None;
+            }
+            
+            let prototype = self.prototype.as_local(env);
+            if prototype.cacheable_shape(env) != cached.holder_shape {
+                return None;
+            }
+            
+            prototype
+        };
+        
+        Some((holder, cached.index as usize))
+    }
+    
+    /// Gets the property like get, using the cache of the instruction that
+    /// does the get to find the property.
+    pub fn get_cached(&self, env: &mut JsEnv, property: Name, cache: &Cell<PropertyCache>) -> JsResult<JsValue> {
+        let entry = match self.find_cached(env, property, cache, false) {
+            Some((holder, index)) => holder.store.as_hash(env).entry(index),
+            None => return self.get(env, property)
+        };
+        
+        if entry.is_accessor() {
+            let get = entry.value1.as_value(env);
+            
+            if get.is_undefined() {
+                Ok(JsValue::new_undefined())
+            } else {
+                let this = self.as_value();
+                get.call(env, this, Vec::new(), false)
+            }
+        } else {
+            Ok(entry.value1.as_value(env))
+        }
+    }
+    
+    /// Puts the property like put, using the cache of the instruction that
+    /// does the put to find the property. Only writes to existing writable
+    /// data properties of the object itself are cached.
+    pub fn put_cached(&mut self, env: &mut JsEnv, property: Name, value: JsValue, throw: bool, cache: &Cell<PropertyCache>) -> JsResult<()> {
+        // Array lengths and indexes are never cached, but check the class
+        // anyway because arrays define their own properties differently.
+        
+        if self.class != name::ARRAY_CLASS {
+            if let Some((_, index)) = self.find_cached(env, property, cache, true) {
+                let mut store = self.store.as_hash(env);
+                let entry = store.entry(index);
+                
+                if !entry.is_accessor() && entry.is_writable() {
+                    store.set_entry_value(env, index, value.as_raw());
+                    return Ok(());
+                }
+            }
+        }
+        
+        self.put(env, property, value, throw)
+    }
+    
     pub fn get_key(&self, env: &JsEnv, offset: usize) -> JsStoreKey {
         match self.store.get_key(env, offset) {
             StoreKey::Key(name, enumerable) => JsStoreKey::Key(name, enumerable),
@@ -204,7 +413,7 @@ impl Local<JsObject> {
                         }
                     };
                     
-                    self.store.add(env, property, &descriptor);
+                    try!(self.store.add(env, property, &descriptor));
                     
                     Ok(true)
                 }
@@ -258,7 +467,7 @@ impl Local<JsObject> {
                     }
                 }
                 
-                self.store.replace(env, property, &descriptor.merge(current));
+                try!(self.store.replace(env, property, &descriptor.merge(current)));
                 
                 Ok(true)
             }
@@ -450,7 +659,7 @@ impl JsItem for Local<JsObject> {
     fn delete(&mut self, env: &mut JsEnv, property: Name, throw: bool) -> JsResult<bool> {
         if let Some(desc) = self.get_own_property(env, property) {
             if desc.is_configurable() {
-                self.store.remove(env, property);
+                try!(self.store.remove(env, property));
                 Ok(true)
             } else if throw {
                 Err(JsError::new_type(env, ::errors::TYPE_CANNOT_DELETE))
@@ -465,6 +674,8 @@ impl JsItem for Local<JsObject> {
     // 8.12.9 [[DefineOwnProperty]] (P, Desc, Throw)
     // 15.4.5.1 [[DefineOwnProperty]] ( P, Desc, Throw )
     fn define_own_property(&mut self, env: &mut JsEnv, property: Name, descriptor: JsDescriptor, throw: bool) -> JsResult<bool> {
+        try!(env.check_out_of_memory());
+        
         if self.class == name::ARRAY_CLASS {
             self.define_own_array_property(env, property, descriptor, throw)
         } else {
@@ -473,7 +684,7 @@ impl JsItem for Local<JsObject> {
     }
     
     fn is_callable(&self) -> bool {
-        !self.function.is_none()
+        self.function.is_callable()
     }
     
     fn can_construct(&self) -> bool {
@@ -481,7 +692,7 @@ impl JsItem for Local<JsObject> {
             Function::Ir(..) => true,
             Function::Native(ref native) => native.can_construct,
             Function::Bound => true,
-            Function::None => false
+            Function::Host(..) | Function::None => false
         }
     }
     
@@ -497,10 +708,11 @@ impl JsItem for Local<JsObject> {
         }
     }
     
-    fn set_prototype(&mut self, prototype: Option<JsValue>) {
+    fn set_prototype(&mut self, env: &JsEnv, prototype: Option<JsValue>) {
         if let Some(prototype) = prototype {
             if prototype.ty() == JsType::Object {
                 self.prototype = prototype.unwrap_object().as_ptr();
+                env.heap.write_barrier(self.as_ptr().ptr());
             }
         } else {
             self.prototype = Ptr::null();
@@ -533,7 +745,7 @@ impl JsItem for Local<JsObject> {
     // 15.3.5.3 [[HasInstance]] (V)
     // 15.3.4.5.3 [[HasInstance]] (V)
     fn has_instance(&self, env: &mut JsEnv, mut object: JsValue) -> JsResult<bool> {
-        if self.function.is_none() {
+        if !self.function.is_callable() {
             Err(JsError::new_type(env, ::errors::TYPE_CANNOT_HAS_INSTANCE))
         } else if object.ty() != JsType::Object {
             Ok(false)
@@ -573,9 +785,10 @@ impl JsItem for Local<JsObject> {
         }
     }
     
-    fn set_scope(&mut self, scope: Option<Local<JsScope>>) {
+    fn set_scope(&mut self, env: &JsEnv, scope: Option<Local<JsScope>>) {
         if let Some(scope) = scope {
             self.scope = scope.as_ptr();
+            env.heap.write_barrier(self.as_ptr().ptr());
         } else {
             self.scope = Ptr::null();
         }
@@ -586,6 +799,7 @@ enum Function {
     Ir(FunctionRef),
     Native(ManualBox<NativeFunction>),
     Bound,
+    Host(ManualBox<Box<Any>>),
     None
 }
 
@@ -597,27 +811,32 @@ impl Function {
                 let native = ManualBox::new(NativeFunction {
                     name: name,
                     args: args,
-                    function: function,
+                    function: NativeCallback::Fn(function),
                     can_construct: can_construct
                 });
                 
                 Function::Native(native)
             }
-            JsFunction::Bound => Function::Bound
+            JsFunction::Bound => Function::Bound,
+            // The closure itself is attached by JsObject::new_closure.
+            JsFunction::Closure(..) => Function::None
         }
     }
     
     fn to_function(&self) -> Option<JsFunction> {
         match *self {
             Function::Ir(ref function_ref) => Some(JsFunction::Ir(*function_ref)),
-            Function::Native(ref native) => Some(JsFunction::Native(
-                native.name,
-                native.args,
-                native.function,
-                native.can_construct
-            )),
+            Function::Native(ref native) => Some(match native.function {
+                NativeCallback::Fn(function) => JsFunction::Native(
+                    native.name,
+                    native.args,
+                    function,
+                    native.can_construct
+                ),
+                NativeCallback::Closure(..) => JsFunction::Closure(native.name, native.args)
+            }),
             Function::Bound => Some(JsFunction::Bound),
-            Function::None => None
+            Function::Host(..) | Function::None => None
         }
     }
     
@@ -627,29 +846,69 @@ impl Function {
             _ => false
         }
     }
+    
+    fn is_callable(&self) -> bool {
+        match *self {
+            Function::Host(..) | Function::None => false,
+            _ => true
+        }
+    }
 }
 
 struct NativeFunction {
     name: Option<Name>,
     args: u32,
-    function: JsFn,
+    function: NativeCallback,
     can_construct: bool
 }
 
+enum NativeCallback {
+    Fn(JsFn),
+    Closure(Rc<Box<JsClosure>>)
+}
+
+// Adding and replacing properties can grow the store, which fails with an
+// out of memory abort when the heap is full.
 trait Store {
-    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor);
+    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<()>;
     
-    fn remove(&mut self, env: &JsEnv, name: Name);
+    fn remove(&mut self, env: &JsEnv, name: Name) -> JsResult<()>;
     
     fn get_value(&self, env: &JsEnv, name: Name) -> Option<JsDescriptor>;
     
-    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> bool;
+    // Gets the entry of the property. Unlike get_value, this does not
+    // allocate.
+    fn get_entry(&self, env: &JsEnv, name: Name) -> Option<Entry>;
+    
+    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<bool>;
     
     fn get_key(&self, env: &JsEnv, offset: usize) -> StoreKey;
     
     fn capacity(&self, env: &JsEnv) -> usize;
 }
 
+/// Inline cache of an instruction that accesses a named property. It holds
+/// the location of the property for objects with a specific shape.
+#[derive(Copy, Clone)]
+pub struct PropertyCache {
+    // Shape of the object, or NO_SHAPE when the cache is empty.
+    shape: u32,
+    // Shape of the prototype that has the property, or NO_SHAPE when the
+    // object itself has it.
+    holder_shape: u32,
+    index: u32
+}
+
+impl PropertyCache {
+    pub fn new() -> PropertyCache {
+        PropertyCache {
+            shape: NO_SHAPE,
+            holder_shape: NO_SHAPE,
+            index: 0
+        }
+    }
+}
+
 pub enum StoreKey {
     Key(Name, bool),
     Missing,
@@ -708,11 +967,11 @@ macro_rules! delegate {
 }
 
 impl Store for StorePtr {
-    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) {
+    fn add(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<()> {
         delegate!(self, env, add(env, name, value))
     }
     
-    fn remove(&mut self, env: &JsEnv, name: Name) {
+    fn remove(&mut self, env: &JsEnv, name: Name) -> JsResult<()> {
         delegate!(self, env, remove(env, name))
     }
     
@@ -720,7 +979,11 @@ impl Store for StorePtr {
         delegate!(self, env, get_value(env, name))
     }
     
-    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> bool {
+    fn get_entry(&self, env: &JsEnv, name: Name) -> Option<Entry> {
+        delegate!(self, env, get_entry(env, name))
+    }
+    
+    fn replace(&mut self, env: &JsEnv, name: Name, value: &JsDescriptor) -> JsResult<bool> {
         delegate!(self, env, replace(env, name, value))
     }
     
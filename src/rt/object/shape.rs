@@ -0,0 +1,98 @@
+// Shapes (hidden classes) of hash stores.
+//
+// The layout of a hash store only depends on the properties that were added
+// to it and the order they were added in. Stores that had the same properties
+// added in the same order share a shape, so the entry a property was found at
+// in one store is valid for all stores with the same shape. The interpreter
+// uses this to cache the location of named properties per instruction.
+//
+// Removing a property makes the layout depend on the removed entry, and
+// stores with many properties or with index properties are used as
+// dictionaries. These stores get NO_SHAPE, which never matches a cache.
+
+use syntax::Name;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::u32;
+
+pub const NO_SHAPE : u32 = 0;
+pub const EMPTY_SHAPE : u32 = 1;
+
+const MAX_PROPERTIES : usize = 128;
+
+pub struct Shapes {
+    transitions: RefCell<HashMap<(u32, Name), u32>>,
+    next: Cell<u32>
+}
+
+impl Shapes {
+    pub fn new() -> Shapes {
+        Shapes {
+            transitions: RefCell::new(HashMap::new()),
+            next: Cell::new(EMPTY_SHAPE + 1)
+        }
+    }
+    
+    /// Gets the shape of a store with the shape after the property was added
+    /// to it; count is the number of properties after the add.
+    pub fn add(&self, shape: u32, name: Name, count: usize) -> u32 {
+        if shape == NO_SHAPE || name.is_index() || count > MAX_PROPERTIES {
+            return NO_SHAPE;
+        }
+        
+        let mut transitions = self.transitions.borrow_mut();
+        
+        if let Some(&next) = transitions.get(&(shape, name)) {
+            return next;
+        }
+        
+        // When we run out of shapes, new layouts are not cached anymore.
+        
+        let next = self.next.get();
+        if next == u32::MAX {
+            return NO_SHAPE;
+        }
+        
+        self.next.set(next + 1);
+        transitions.insert((shape, name), next);
+        
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rt::{JsEnvBuilder, JsItem};
+    
+    fn eval(js: &str, inline_caches: bool) -> String {
+        let mut env = JsEnvBuilder::new().inline_caches(inline_caches).build().ok().unwrap();
+        let result = env.eval(js).ok().unwrap();
+        let result = result.as_value(&env);
+        
+        result.to_string(&mut env).ok().unwrap().to_string()
+    }
+    
+    #[test]
+    fn same_results() {
+        let tests = [
+            // Own and inherited properties.
+            "function P(x) { this.x = x; } P.prototype.get = function () { return this.x; }; var s = 0; for (var i = 0; i < 10; i++) { s += new P(i).get(); } s",
+            // Objects with the same properties added in another order.
+            "var a = { x: 1, y: 2 }; var b = { y: 3, x: 4 }; var s = ''; for (var i = 0; i < 4; i++) { var o = i % 2 ? a : b; s += o.x + o.y; } s",
+            // A property is shadowed after the lookup was cached.
+            "var p = { f: 1 }; function C() {} C.prototype = p; var c = new C(); var s = ''; for (var i = 0; i < 3; i++) { s += c.f; if (i == 1) { c.f = 2; } } s",
+            // A property is removed and added again.
+            "var o = { a: 1, b: 2 }; var s = ''; for (var i = 0; i < 3; i++) { s += o.b; delete o.a; o.b = i; } s",
+            // Getters and read only properties.
+            "var o = {}; Object.defineProperty(o, 'x', { get: function () { return 7; } }); Object.defineProperty(o, 'y', { value: 1, writable: false }); var s = ''; for (var i = 0; i < 3; i++) { o.y = i; s += o.x + o.y; } s",
+            // String objects and arrays.
+            "var s = ''; var t = [new String('ab'), [1, 2, 3]]; for (var i = 0; i < 4; i++) { s += t[i % 2].length; } s",
+            // Globals.
+            "var g = 1; function f() { return g; } var s = 0; for (var i = 0; i < 3; i++) { g = i; s += f(); } s"
+        ];
+        
+        for js in &tests {
+            assert_eq!(eval(js, true), eval(js, false));
+        }
+    }
+}
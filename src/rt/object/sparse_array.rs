@@ -1,5 +1,6 @@
-use gc::{Array, Local, ptr_t, GcWalker};
-use rt::JsEnv;
+use gc::{Array, Local, AsPtr, ptr_t, GcWalker};
+use rt::{JsEnv, JsError};
+use ::JsResult;
 use rt::object::{StoreKey, Entry};
 use std::cmp::{min, max};
 use rt::{GC_ENTRY, GC_ARRAY_CHUNK, GC_SPARSE_ARRAY, validate_walker_field};
@@ -69,6 +70,8 @@ impl SparseArray {
             used: 0
         };
         
+        env.heap.write_barrier(array.as_ptr().ptr());
+        
         array
     }
 }
@@ -104,7 +107,7 @@ impl Local<SparseArray> {
         }
     }
     
-    pub fn set_value(&mut self, env: &JsEnv, index: usize, value: Local<Entry>) {
+    pub fn set_value(&mut self, env: &JsEnv, index: usize, value: Local<Entry>) -> JsResult<()> {
         if !self.items.is_null() {
             self.used += 1;
             
@@ -112,7 +115,8 @@ impl Local<SparseArray> {
             
             if index < len {
                 self.items[index] = *value;
-                return;
+                env.heap.write_barrier(self.items.ptr());
+                return Ok(());
             }
             
             // If someone is specifically hitting our growth strategy, we
@@ -133,9 +137,10 @@ impl Local<SparseArray> {
                 // We allow the array to double in size every time
                 // we grow it.
                 
-                self.grow_items(env);
+                try!(self.grow_items(env));
                 self.items[index] = *value;
-                return;
+                env.heap.write_barrier(self.items.ptr());
+                return Ok(());
             }
             
             // We have a real array, but not enough room. Transfer the
@@ -146,7 +151,11 @@ impl Local<SparseArray> {
         
         let offset = Self::get_offset_from_index(index);
         let chunk = self.find_or_create_chunk(env, offset);
-        self.chunks[chunk.index()].items[index - offset] = *value;
+        let mut items = self.chunks[chunk.index()].items;
+        items[index - offset] = *value;
+        env.heap.write_barrier(items.ptr());
+        
+        Ok(())
     }
     
     fn get_offset_from_index(index: usize) -> usize {
@@ -156,10 +165,12 @@ impl Local<SparseArray> {
     fn transfer_to_chunks(&mut self, env: &JsEnv) {
         let chunk_count = (self.items.len() >> CHUNK_SHIFT) + 1;
         self.chunks = unsafe { env.heap.alloc_array(GC_ARRAY_CHUNK, max(chunk_count, INITIAL_CHUNK_COUNT)) };
+        env.heap.write_barrier(self.as_ptr().ptr());
         
         for i in 0..chunk_count {
             let offset = i * CHUNK_SIZE;
             self.chunks[i] = Chunk::new(env, offset);
+            env.heap.write_barrier(self.chunks.ptr());
             
             let to_copy = if i < chunk_count - 1 {
                 CHUNK_SIZE
@@ -174,11 +185,19 @@ impl Local<SparseArray> {
         self.chunk_count = chunk_count;
     }
     
-    fn grow_items(&mut self, env: &JsEnv) {
+    fn grow_items(&mut self, env: &JsEnv) -> JsResult<()> {
         let len = self.items.len();
-        let items = unsafe { env.heap.alloc_array(GC_ENTRY, len * 2) };
+        
+        let items = match unsafe { env.heap.try_alloc_array(GC_ENTRY, len * 2) } {
+            Some(items) => items,
+            None => return Err(JsError::new_out_of_memory(env))
+        };
+        
         Array::copy(self.items, 0, items, 0, len);
         self.items = items;
+        env.heap.write_barrier(self.as_ptr().ptr());
+        
+        Ok(())
     }
     
     fn find_or_create_chunk(&mut self, env: &JsEnv, offset: usize) -> ChunkIndex {
@@ -211,9 +230,11 @@ impl Local<SparseArray> {
             Array::copy(self.chunks, index, destination, index + 1, chunk_count - index);
             
             self.chunks = destination;
+            env.heap.write_barrier(self.as_ptr().ptr());
         } else {
             Array::copy(self.chunks, index, self.chunks, index + 1, chunk_count - index);
             self.chunks[index] = entry;
+            env.heap.write_barrier(self.chunks.ptr());
         }
         
         self.chunk_count += 1;
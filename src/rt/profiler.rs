@@ -0,0 +1,282 @@
+extern crate time;
+extern crate rustc_serialize;
+
+use rt::JsEnv;
+use syntax::Name;
+use syntax::ast::FunctionRef;
+use self::rustc_serialize::json::{Json, ToJson};
+use std::collections::{BTreeMap, HashMap};
+use std::io::prelude::*;
+use std::io;
+
+// Profiler state of an environment. The profile is a tree of call frames;
+// every sample is attributed to the node of the call chain at the time of
+// the sample and to the line of the instruction being executed. Samples are
+// aggregated per node, so the profile does not grow with the run time.
+pub struct Profiler {
+    interval: u32,
+    countdown: u32,
+    start_time: u64,
+    last_time: u64,
+    nodes: Vec<ProfileNode>
+}
+
+#[derive(PartialEq)]
+struct FrameKey {
+    name: Option<Name>,
+    function: Option<FunctionRef>
+}
+
+struct ProfileNode {
+    key: FrameKey,
+    function_name: String,
+    url: String,
+    line: i32,
+    column: i32,
+    children: Vec<usize>,
+    hits: u64,
+    // Time spent in the node itself, in nanoseconds.
+    self_time: u64,
+    line_hits: HashMap<i32, u64>
+}
+
+/// Profile collected by JsEnv::start_profiler.
+pub struct JsProfile {
+    nodes: Vec<ProfileNode>,
+    start_time: u64,
+    end_time: u64
+}
+
+impl Profiler {
+    fn new(interval: u32) -> Profiler {
+        let now = time::precise_time_ns();
+        
+        let root = ProfileNode {
+            key: FrameKey {
+                name: None,
+                function: None
+            },
+            function_name: "(root)".to_string(),
+            url: String::new(),
+            line: -1,
+            column: -1,
+            children: Vec::new(),
+            hits: 0,
+            self_time: 0,
+            line_hits: HashMap::new()
+        };
+        
+        Profiler {
+            interval: interval,
+            countdown: interval,
+            start_time: now,
+            last_time: now,
+            nodes: vec![root]
+        }
+    }
+}
+
+impl JsEnv {
+    /// Starts profiling the code run in this environment. A sample is taken
+    /// every interval instructions; with an interval of 1 every instruction
+    /// is counted, which slows down the script considerably. A running
+    /// profile is discarded.
+    pub fn start_profiler(&mut self, interval: u32) {
+        assert!(interval > 0);
+        
+        self.profiler = Some(Box::new(Profiler::new(interval)));
+    }
+    
+    /// Stops profiling and returns the collected profile.
+    pub fn stop_profiler(&mut self) -> Option<JsProfile> {
+        self.profiler.take().map(|profiler| {
+            JsProfile {
+                nodes: profiler.nodes,
+                start_time: profiler.start_time,
+                end_time: time::precise_time_ns()
+            }
+        })
+    }
+    
+    // Called by the interpreter before every instruction while profiling.
+    pub fn profile_instruction(&mut self) {
+        let mut profiler = match self.profiler.take() {
+            Some(profiler) => profiler,
+            None => return
+        };
+        
+        profiler.countdown -= 1;
+        
+        if profiler.countdown == 0 {
+            profiler.countdown = profiler.interval;
+            
+            self.take_sample(&mut profiler);
+        }
+        
+        self.profiler = Some(profiler);
+    }
+    
+    fn take_sample(&self, profiler: &mut Profiler) {
+        let now = time::precise_time_ns();
+        let delta = now - profiler.last_time;
+        profiler.last_time = now;
+        
+        // Walk the tree from the root along the call chain, adding nodes
+        // for frames that have not been seen before.
+        
+        let mut node = 0;
+        
+        for frame in &self.frames {
+            let key = FrameKey {
+                name: frame.name,
+                function: frame.function
+            };
+            
+            let child = profiler.nodes[node].children.iter().cloned().find(|&child| profiler.nodes[child].key == key);
+            
+            node = match child {
+                Some(child) => child,
+                None => {
+                    let child = profiler.nodes.len();
+                    let node_ = self.new_profile_node(key);
+                    profiler.nodes.push(node_);
+                    profiler.nodes[node].children.push(child);
+                    child
+                }
+            };
+        }
+        
+        let line = match self.frames.last() {
+            Some(frame) => frame.function.map(|function| self.ir.get_span(function, frame.ip).start_line),
+            None => None
+        };
+        
+        let profile_node = &mut profiler.nodes[node];
+        profile_node.hits += 1;
+        profile_node.self_time += delta;
+        if let Some(line) = line {
+            *profile_node.line_hits.entry(line).or_insert(0) += 1;
+        }
+    }
+    
+    fn new_profile_node(&self, key: FrameKey) -> ProfileNode {
+        let function_name = match key.name {
+            Some(name) => self.ir.interner().get(name).to_string(),
+            None => String::new()
+        };
+        
+        let (url, line, column) = match key.function {
+            Some(function) => {
+                let span = self.ir.get_function(function).span;
+                (self.ir.interner().get(span.file).to_string(), span.start_line, span.start_col)
+            }
+            None => (String::new(), -1, -1)
+        };
+        
+        ProfileNode {
+            key: key,
+            function_name: function_name,
+            url: url,
+            line: line,
+            column: column,
+            children: Vec::new(),
+            hits: 0,
+            self_time: 0,
+            line_hits: HashMap::new()
+        }
+    }
+}
+
+impl JsProfile {
+    /// Writes the profile as collapsed stacks, one line per call chain with
+    /// the number of samples, as consumed by flamegraph tools.
+    pub fn write_collapsed(&self, writer: &mut Write) -> io::Result<()> {
+        let mut stack = Vec::new();
+        
+        for &child in &self.nodes[0].children {
+            try!(self.write_collapsed_node(writer, child, &mut stack));
+        }
+        
+        Ok(())
+    }
+    
+    fn write_collapsed_node(&self, writer: &mut Write, node: usize, stack: &mut Vec<String>) -> io::Result<()> {
+        let profile_node = &self.nodes[node];
+        
+        let name = if profile_node.url.len() == 0 {
+            format!("{} (native)", profile_node.function_name)
+        } else if profile_node.function_name.len() == 0 {
+            format!("(global) ({}:{})", profile_node.url, profile_node.line)
+        } else {
+            format!("{} ({}:{})", profile_node.function_name, profile_node.url, profile_node.line)
+        };
+        
+        // Semicolons separate the frames, so they cannot appear in the names.
+        
+        stack.push(name.replace(";", ":"));
+        
+        if profile_node.hits > 0 {
+            try!(writeln!(writer, "{} {}", stack.join(";"), profile_node.hits));
+        }
+        
+        for &child in &profile_node.children {
+            try!(self.write_collapsed_node(writer, child, stack));
+        }
+        
+        stack.pop();
+        
+        Ok(())
+    }
+    
+    /// Writes the profile in the .cpuprofile format of the Chrome developer
+    /// tools. Samples are not kept individually, so every node that was hit
+    /// gets a single sample covering all of its time: the totals are right,
+    /// but the timeline does not show when the samples were taken.
+    pub fn write_cpuprofile(&self, writer: &mut Write) -> io::Result<()> {
+        let mut nodes = Vec::new();
+        
+        for i in 0..self.nodes.len() {
+            let node = &self.nodes[i];
+            
+            let mut call_frame = BTreeMap::new();
+            call_frame.insert("functionName".to_string(), node.function_name.to_json());
+            call_frame.insert("scriptId".to_string(), "0".to_json());
+            call_frame.insert("url".to_string(), node.url.to_json());
+            // Line and column numbers of call frames are zero based.
+            call_frame.insert("lineNumber".to_string(), (if node.line > 0 { node.line - 1 } else { -1 }).to_json());
+            call_frame.insert("columnNumber".to_string(), (if node.column > 0 { node.column - 1 } else { -1 }).to_json());
+            
+            let mut lines = node.line_hits.iter().collect::<Vec<_>>();
+            lines.sort();
+            
+            let position_ticks = lines.into_iter().map(|(&line, &ticks)| {
+                let mut tick = BTreeMap::new();
+                tick.insert("line".to_string(), line.to_json());
+                tick.insert("ticks".to_string(), ticks.to_json());
+                Json::Object(tick)
+            }).collect::<Vec<_>>();
+            
+            let mut object = BTreeMap::new();
+            object.insert("id".to_string(), ((i + 1) as u64).to_json());
+            object.insert("callFrame".to_string(), Json::Object(call_frame));
+            object.insert("hitCount".to_string(), node.hits.to_json());
+            object.insert("children".to_string(), Json::Array(node.children.iter().map(|&child| ((child + 1) as u64).to_json()).collect()));
+            object.insert("positionTicks".to_string(), Json::Array(position_ticks));
+            
+            nodes.push(Json::Object(object));
+        }
+        
+        // Times are in microseconds.
+        
+        let mut profile = BTreeMap::new();
+        profile.insert("nodes".to_string(), Json::Array(nodes));
+        profile.insert("startTime".to_string(), (self.start_time / 1000).to_json());
+        profile.insert("endTime".to_string(), (self.end_time / 1000).to_json());
+        let hit = (0..self.nodes.len()).filter(|&node| self.nodes[node].hits > 0).collect::<Vec<_>>();
+        
+        profile.insert("samples".to_string(), Json::Array(hit.iter().map(|&node| ((node + 1) as u64).to_json()).collect()));
+        profile.insert("timeDeltas".to_string(), Json::Array(hit.iter().map(|&node| (self.nodes[node].self_time / 1000).to_json()).collect()));
+        
+        write!(writer, "{}", Json::Object(profile))
+    }
+}
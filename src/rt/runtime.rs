@@ -1,10 +1,12 @@
 use rt::*;
+use rt::backtrace::CallFrame;
 use gc::*;
 use syntax::Name;
 use syntax::ast::FunctionRef;
 use syntax::token::name;
 use std::f64;
 use std::cmp;
+use std::sync::atomic::Ordering;
 
 trait SignedZero {
     #[inline(always)]
@@ -26,6 +28,10 @@ impl SignedZero for f64 {
     }
 }
 
+// Number of stack slots that must be free when entering a function. This
+// leaves room for the temporaries of native functions.
+const STACK_RESERVE : usize = 256;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ComparisonResult {
     Undefined,
@@ -42,7 +48,7 @@ impl JsEnv {
         if lprim.ty() == JsType::String || rprim.ty() == JsType::String {
             let lhs = try!(lprim.to_string(self));
             let rhs = try!(rprim.to_string(self));
-            let result = JsString::concat(self, &[lhs, rhs]);
+            let result = try!(JsString::concat(self, &[lhs, rhs]));
             
             Ok(result.as_value())
         } else {
@@ -97,8 +103,60 @@ impl JsEnv {
         
         let frame = args.frame;
         
+        // Every call recurses on the native stack. The address of a local
+        // tells how much of the native stack is in use since the outermost
+        // call, assuming the native stack grows down.
+        
+        let marker = 0u8;
+        let native_sp = &marker as *const u8 as usize;
+        
+        if self.call_depth == 0 {
+            self.native_stack_base = native_sp;
+            
+            // This is the outermost call, so a new run starts. An interrupt
+            // requested while nothing was running is delivered to this run
+            // instead of being lost. Taking the flag clears it, so it
+            // doesn't abort later runs.
+            
+            if self.interrupt.swap(false, Ordering::SeqCst) {
+                self.stack.drop_frame(frame);
+                
+                return Err(JsError::Abort(JsAbort::Interrupted));
+            }
+            
+            // The heap may have run out of memory outside of a run. The
+            // garbage can be collected now, so the run gets a new chance.
+            
+            self.heap.take_out_of_memory();
+        }
+        
+        let native_used = self.native_stack_base.saturating_sub(native_sp);
+        
+        if !self.stack_overflow && (self.call_depth >= self.max_call_depth || native_used >= self.native_stack_size) {
+            self.stack.drop_frame(frame);
+            
+            return Err(self.new_stack_overflow_error());
+        }
+        
+        if let Err(error) = self.check_stack(STACK_RESERVE) {
+            self.stack.drop_frame(frame);
+            
+            return Err(error);
+        }
+        
+        self.call_depth += 1;
+        
         let result = self.do_call(mode, args, function_obj, &function);
         
+        self.call_depth -= 1;
+        
+        if self.call_depth == 0 {
+            // A run that ran out of memory has been aborted, and its garbage
+            // can be collected now.
+            
+            self.heap.take_out_of_memory();
+        }
+        
         // If we're throwing an exception, we need to pop the stack here. Otherwise
         // the stack will have been popped by the callee.
         
@@ -109,6 +167,30 @@ impl JsEnv {
         result
     }
     
+    /// Checks that the given number of values can be pushed onto the stack,
+    /// and returns a RangeError when they can't.
+    pub fn check_stack(&mut self, values: usize) -> JsResult<()> {
+        if self.stack_overflow || self.stack.has_capacity(values) {
+            Ok(())
+        } else {
+            Err(self.new_stack_overflow_error())
+        }
+    }
+    
+    pub fn new_stack_overflow_error(&mut self) -> JsError {
+        // Creating the RangeError calls into its constructor, so the stack
+        // limits are lifted while the error is being created. The stack
+        // guard leaves room for this.
+        
+        self.stack_overflow = true;
+        
+        let error = JsError::new_runtime(self, JsHandle::RangeError, Some(::errors::RANGE_STACK_OVERFLOW), None, None);
+        
+        self.stack_overflow = false;
+        
+        error
+    }
+    
     fn do_call(&mut self, mode: JsFnMode, args: JsArgs, function_obj: JsValue, function: &JsFunction) -> JsResult<()> {
         match *function {
             JsFunction::Ir(function_ref) => {
@@ -139,16 +221,44 @@ impl JsEnv {
                 let scope = function_obj.scope(self)
                     .unwrap_or_else(|| self.global_scope.as_local(self));
                 
-                try!(self.call_block(block, args, &function, scope));
+                self.frames.push(CallFrame::new(function.name, Some(function_ref)));
+                
+                let result = self.call_block(block, args, &function, scope);
+                
+                self.frames.pop();
+                
+                try!(result);
                 
                 debugln!("EXIT {}", location);
                 
                 Ok(())
             }
-            JsFunction::Native(_, _, ref callback, _) => {
+            JsFunction::Native(name, _, ref callback, _) => {
+                let frame = args.frame;
+                
+                self.frames.push(CallFrame::new(name, None));
+                
+                let result = callback(self, mode, args);
+                
+                self.frames.pop();
+                
+                let result = try!(result);
+                
+                self.stack.drop_frame(frame);
+                self.stack.push(result.as_raw());
+                
+                Ok(())
+            }
+            JsFunction::Closure(name, _) => {
                 let frame = args.frame;
                 
-                let result = try!(callback(self, mode, args));
+                self.frames.push(CallFrame::new(name, None));
+                
+                let result = function_obj.unwrap_object().call_closure(self, mode, args);
+                
+                self.frames.pop();
+                
+                let result = try!(result);
                 
                 self.stack.drop_frame(frame);
                 self.stack.push(result.as_raw());
@@ -172,7 +282,7 @@ impl JsEnv {
                     target_args.push(args.arg(self, i));
                 }
                 
-                let args = JsArgs::new(self, bound_this, target, &target_args);
+                let args = try!(JsArgs::new(self, bound_this, target, &target_args));
                 
                 self.call(mode, args)
             }
@@ -227,10 +337,10 @@ impl JsEnv {
         
         let proto = try!(function.get(self, name::PROTOTYPE));
         if proto.ty() == JsType::Object {
-            obj.set_prototype(Some(proto));
+            obj.set_prototype(self, Some(proto));
         } else {
             let proto = self.handle(JsHandle::Object).as_value();
-            obj.set_prototype(Some(proto));
+            obj.set_prototype(self, Some(proto));
         }
         
         let obj = obj.as_value();
@@ -381,7 +491,7 @@ impl JsEnv {
         
         let function = self.ir.get_function(function_ref);
         if function.take_scope {
-            result.set_scope(scope);
+            result.set_scope(self, scope);
         }
         
         let mut proto = self.create_object();
@@ -497,7 +607,7 @@ impl JsEnv {
     pub fn create_object(&self) -> Local<JsObject> {
         let mut obj = JsObject::new_local(self, JsStoreType::Hash);
         
-        obj.set_prototype(Some(self.handle(JsHandle::Object).as_value()));
+        obj.set_prototype(self, Some(self.handle(JsHandle::Object).as_value()));
         obj.set_class(Some(name::OBJECT_CLASS));
         
         obj
@@ -520,7 +630,7 @@ impl JsEnv {
             false
         ).ok();
         
-        obj.set_prototype(Some(self.handle(JsHandle::Array).as_value()));
+        obj.set_prototype(self, Some(self.handle(JsHandle::Array).as_value()));
         obj.set_class(Some(name::ARRAY_CLASS));
         
         obj
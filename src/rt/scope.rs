@@ -14,8 +14,10 @@ impl JsScope {
             result.items = env.heap.alloc_array(GC_VALUE, size + 1);
         }
         
+        env.heap.write_barrier(result.as_ptr().ptr());
+        
         if let Some(parent) = parent {
-            result.raw_set(0, parent.as_value());
+            result.raw_set(env, 0, parent.as_value());
         }
         
         result
@@ -30,10 +32,12 @@ impl JsScope {
             result.items = env.heap.alloc_array(GC_VALUE, size);
         }
         
+        env.heap.write_barrier(result.as_ptr().ptr());
+        
         if let Some(parent) = parent {
-            result.raw_set(0, parent.as_value());
+            result.raw_set(env, 0, parent.as_value());
         }
-        result.raw_set(1, scope_object.as_value());
+        result.raw_set(env, 1, scope_object.as_value());
         
         result
     }
@@ -62,12 +66,12 @@ impl Local<JsScope> {
         }
     }
     
-    pub fn set_arguments(&mut self, arguments: JsValue) {
+    pub fn set_arguments(&mut self, env: &JsEnv, arguments: JsValue) {
         if self.items.len() == 2 {
             panic!("scope does not have a slot to store arguments");
         }
         
-        self.raw_set(2, arguments);
+        self.raw_set(env, 2, arguments);
     }
     
     pub fn len(&self) -> usize {
@@ -78,15 +82,16 @@ impl Local<JsScope> {
         self.raw_get(env, index + 1)
     }
     
-    pub fn set(&mut self, index: usize, value: JsValue) {
-        self.raw_set(index + 1, value)
+    pub fn set(&mut self, env: &JsEnv, index: usize, value: JsValue) {
+        self.raw_set(env, index + 1, value)
     }
     
     fn raw_get(&self, env: &JsEnv, index: usize) -> JsValue {
         self.items[index].as_value(env)
     }
     
-    fn raw_set(&mut self, index: usize, value: JsValue) {
+    fn raw_set(&mut self, env: &JsEnv, index: usize, value: JsValue) {
         self.items[index] = value.as_raw();
+        env.heap.write_barrier(self.items.ptr());
     }
 }
@@ -0,0 +1,360 @@
+extern crate rustc_serialize;
+
+use rt::{JsEnv, JsValue, JsType, JsItem, JsObject, JsString, JsScope, JsError, JsResult};
+use rt::{GC_ARRAY_STORE, GC_ENTRY, GC_HASH_STORE, GC_OBJECT, GC_SCOPE, GC_STRING};
+use rt::{GC_SPARSE_ARRAY, GC_ARRAY_CHUNK, GC_REGEXP};
+use gc::{AsPtr, Ptr, ptr_t};
+use self::rustc_serialize::json::Json;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io;
+
+// Node and edge types as listed in the meta data of the snapshot.
+const NODE_HIDDEN : usize = 0;
+const NODE_STRING : usize = 2;
+const NODE_OBJECT : usize = 3;
+const NODE_CLOSURE : usize = 5;
+const NODE_REGEXP : usize = 6;
+const NODE_SYNTHETIC : usize = 9;
+
+const EDGE_CONTEXT : usize = 0;
+const EDGE_ELEMENT : usize = 1;
+const EDGE_PROPERTY : usize = 2;
+const EDGE_INTERNAL : usize = 3;
+const EDGE_HIDDEN : usize = 4;
+
+const META : &'static str = r#"{"node_fields":["type","name","id","self_size","edge_count","trace_node_id"],"node_types":[["hidden","array","string","object","code","closure","regexp","number","native","synthetic","concatenated string","sliced string"],"string","number","number","number","number"],"edge_fields":["type","name_or_index","to_node"],"edge_types":[["context","element","property","internal","hidden","shortcut","weak"],"string_or_number","node"],"trace_function_info_fields":[],"trace_node_fields":[],"sample_fields":[],"location_fields":[]}"#;
+
+const NODE_FIELDS : usize = 6;
+
+struct Node {
+    ty: usize,
+    name: usize,
+    size: usize,
+    edges: Vec<Edge>
+}
+
+struct Edge {
+    ty: usize,
+    // An index into the strings for context, property and internal edges,
+    // and an element index otherwise.
+    name: usize,
+    target: ptr_t
+}
+
+struct Snapshot {
+    nodes: Vec<Node>,
+    // Maps every block to the node it is part of. Stores of objects, the
+    // characters of strings and the items of scopes are part of the node
+    // of their owner.
+    blocks: HashMap<ptr_t, usize>,
+    strings: Vec<String>,
+    string_indexes: HashMap<String, usize>
+}
+
+impl Snapshot {
+    fn new() -> Snapshot {
+        Snapshot {
+            nodes: Vec::new(),
+            blocks: HashMap::new(),
+            strings: Vec::new(),
+            string_indexes: HashMap::new()
+        }
+    }
+    
+    fn string(&mut self, string: &str) -> usize {
+        if let Some(&index) = self.string_indexes.get(string) {
+            return index;
+        }
+        
+        let index = self.strings.len();
+        self.strings.push(string.to_string());
+        self.string_indexes.insert(string.to_string(), index);
+        
+        index
+    }
+    
+    fn add_node(&mut self, ty: usize, name: &str) -> usize {
+        let name = self.string(name);
+        
+        self.nodes.push(Node {
+            ty: ty,
+            name: name,
+            size: 0,
+            edges: Vec::new()
+        });
+        
+        self.nodes.len() - 1
+    }
+    
+    fn add_edge(&mut self, node: usize, ty: usize, name: &str, target: ptr_t) {
+        let name = self.string(name);
+        self.add_index_edge(node, ty, name, target);
+    }
+    
+    fn add_index_edge(&mut self, node: usize, ty: usize, index: usize, target: ptr_t) {
+        self.nodes[node].edges.push(Edge {
+            ty: ty,
+            name: index,
+            target: target
+        });
+    }
+    
+    fn write(&self, writer: &mut Write) -> io::Result<()> {
+        let edge_count = self.nodes.iter().fold(0, |count, node| count + node.edges.len());
+        
+        try!(write!(writer, "{{\"snapshot\":{{\"meta\":{},\"node_count\":{},\"edge_count\":{},\"trace_function_count\":0}},\n", META, self.nodes.len(), edge_count));
+        
+        try!(write!(writer, "\"nodes\":["));
+        for i in 0..self.nodes.len() {
+            let node = &self.nodes[i];
+            
+            if i > 0 {
+                try!(write!(writer, ",\n"));
+            }
+            try!(write!(writer, "{},{},{},{},{},0", node.ty, node.name, i * 2 + 1, node.size, node.edges.len()));
+        }
+        try!(write!(writer, "],\n"));
+        
+        try!(write!(writer, "\"edges\":["));
+        let mut first = true;
+        for node in &self.nodes {
+            for edge in &node.edges {
+                if !first {
+                    try!(write!(writer, ",\n"));
+                }
+                first = false;
+                
+                let target = self.blocks[&edge.target];
+                try!(write!(writer, "{},{},{}", edge.ty, edge.name, target * NODE_FIELDS));
+            }
+        }
+        try!(write!(writer, "],\n"));
+        
+        try!(write!(writer, "\"trace_function_infos\":[],\"trace_tree\":[],\"samples\":[],\"locations\":[],\n"));
+        
+        try!(write!(writer, "\"strings\":["));
+        for i in 0..self.strings.len() {
+            if i > 0 {
+                try!(write!(writer, ",\n"));
+            }
+            try!(write!(writer, "{}", Json::String(self.strings[i].clone())));
+        }
+        try!(write!(writer, "]}}\n"));
+        
+        Ok(())
+    }
+}
+
+fn value_ptr(value: JsValue) -> Option<ptr_t> {
+    match value.ty() {
+        JsType::Object => Some(value.unwrap_object().as_ptr().ptr()),
+        JsType::String => Some(value.unwrap_string().as_ptr().ptr()),
+        JsType::Iterator => Some(value.unwrap_iterator().as_ptr().ptr()),
+        JsType::Scope => Some(value.unwrap_scope().as_ptr().ptr()),
+        JsType::RegExp => Some(value.unwrap_regexp().as_ptr().ptr()),
+        _ => None
+    }
+}
+
+impl JsEnv {
+    /// Writes a snapshot of the live heap to the file in the .heapsnapshot
+    /// format of V8, which can be loaded into the memory tab of the Chrome
+    /// developer tools.
+    pub fn write_heap_snapshot(&self, path: &str) -> JsResult<()> {
+        let mut file = match File::create(path) {
+            Ok(file) => file,
+            Err(error) => return Err(JsError::Io(error))
+        };
+        
+        match self.write_heap_snapshot_to(&mut file) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(JsError::Io(error))
+        }
+    }
+    
+    /// Writes a snapshot of the live heap in the .heapsnapshot format of V8.
+    pub fn write_heap_snapshot_to(&self, writer: &mut Write) -> io::Result<()> {
+        // Nothing may be allocated on the heap while the snapshot is taken,
+        // because a collection would move the blocks. Locals are fine; they
+        // are released with this scope.
+        
+        let _scope = self.new_local_scope();
+        
+        let mut roots = Vec::new();
+        self.heap.walk_roots(&mut |ptr| roots.push(ptr));
+        
+        // Find all live blocks.
+        
+        let mut blocks = Vec::new();
+        let mut seen = HashSet::new();
+        
+        for &root in &roots {
+            if seen.insert(root) {
+                blocks.push(root);
+            }
+        }
+        
+        let mut offset = 0;
+        while offset < blocks.len() {
+            let ptr = blocks[offset];
+            offset += 1;
+            
+            unsafe {
+                self.heap.walk_children(ptr, &mut |child| {
+                    if seen.insert(child) {
+                        blocks.push(child);
+                    }
+                });
+            }
+        }
+        
+        // Find the blocks that are part of the node of their owner.
+        
+        let mut owners = HashMap::new();
+        
+        for &ptr in &blocks {
+            match unsafe { self.heap.type_id(ptr) } {
+                GC_OBJECT => self.find_owned(ptr, ptr, &mut owners),
+                GC_STRING | GC_SCOPE => {
+                    unsafe {
+                        self.heap.walk_children(ptr, &mut |child| { owners.insert(child, ptr); });
+                    }
+                }
+                _ => {}
+            }
+        }
+        
+        // Create the nodes. The first node is the root of the snapshot.
+        
+        let mut snapshot = Snapshot::new();
+        
+        let root = snapshot.add_node(NODE_SYNTHETIC, "(GC roots)");
+        
+        let mut seen = HashSet::new();
+        for &ptr in &roots {
+            let ptr = *owners.get(&ptr).unwrap_or(&ptr);
+            if seen.insert(ptr) {
+                let index = snapshot.nodes[root].edges.len();
+                snapshot.add_index_edge(root, EDGE_ELEMENT, index, ptr);
+            }
+        }
+        
+        for &ptr in &blocks {
+            if !owners.contains_key(&ptr) {
+                let node = self.add_block_node(&mut snapshot, ptr);
+                snapshot.blocks.insert(ptr, node);
+            }
+        }
+        
+        for &ptr in &blocks {
+            let size = unsafe { self.heap.block_size(ptr) };
+            
+            let node = match owners.get(&ptr) {
+                Some(owner) => snapshot.blocks[owner],
+                None => snapshot.blocks[&ptr]
+            };
+            
+            snapshot.nodes[node].size += size;
+            snapshot.blocks.insert(ptr, node);
+        }
+        
+        snapshot.write(writer)
+    }
+    
+    // Marks the blocks of the store of an object as owned by the object.
+    fn find_owned(&self, owner: ptr_t, ptr: ptr_t, owners: &mut HashMap<ptr_t, ptr_t>) {
+        let mut children = Vec::new();
+        unsafe { self.heap.walk_children(ptr, &mut |child| children.push(child)) };
+        
+        for child in children {
+            let owned = match unsafe { self.heap.type_id(child) } {
+                GC_HASH_STORE | GC_ARRAY_STORE | GC_SPARSE_ARRAY | GC_ARRAY_CHUNK | GC_ENTRY => true,
+                _ => false
+            };
+            
+            if owned && !owners.contains_key(&child) {
+                owners.insert(child, owner);
+                self.find_owned(owner, child, owners);
+            }
+        }
+    }
+    
+    fn add_block_node(&self, snapshot: &mut Snapshot, ptr: ptr_t) -> usize {
+        match unsafe { self.heap.type_id(ptr) } {
+            GC_OBJECT => {
+                let object = Ptr::<JsObject>::from_ptr(ptr).as_local(self);
+                
+                let class = match object.class() {
+                    Some(class) => self.ir.interner().get(class).to_string(),
+                    None => "Object".to_string()
+                };
+                
+                let ty = if object.is_callable() { NODE_CLOSURE } else { NODE_OBJECT };
+                let node = snapshot.add_node(ty, &class);
+                
+                object.walk_properties(self, &mut |name, value| {
+                    if let Some(target) = value_ptr(value) {
+                        if let Some(index) = name.index() {
+                            snapshot.add_index_edge(node, EDGE_ELEMENT, index, target);
+                        } else {
+                            snapshot.add_edge(node, EDGE_PROPERTY, &*self.ir.interner().get(name), target);
+                        }
+                    }
+                });
+                
+                if let Some(prototype) = object.prototype(self) {
+                    snapshot.add_edge(node, EDGE_PROPERTY, "__proto__", prototype.unwrap_object().as_ptr().ptr());
+                }
+                if let Some(scope) = object.scope(self) {
+                    snapshot.add_edge(node, EDGE_INTERNAL, "scope", scope.as_ptr().ptr());
+                }
+                if let Some(value) = value_ptr(object.value(self)) {
+                    snapshot.add_edge(node, EDGE_INTERNAL, "value", value);
+                }
+                
+                node
+            }
+            GC_STRING => {
+                let string = Ptr::<JsString>::from_ptr(ptr).as_local(self);
+                
+                snapshot.add_node(NODE_STRING, &string.to_string())
+            }
+            GC_SCOPE => {
+                let scope = Ptr::<JsScope>::from_ptr(ptr).as_local(self);
+                
+                let node = snapshot.add_node(NODE_HIDDEN, "(scope)");
+                
+                if let Some(parent) = scope.parent(self) {
+                    snapshot.add_edge(node, EDGE_CONTEXT, "parent", parent.as_ptr().ptr());
+                }
+                for i in 0..scope.len() {
+                    if let Some(target) = value_ptr(scope.get(self, i)) {
+                        snapshot.add_edge(node, EDGE_CONTEXT, &i.to_string(), target);
+                    }
+                }
+                
+                node
+            }
+            ty => {
+                let node = if ty == GC_REGEXP {
+                    snapshot.add_node(NODE_REGEXP, "RegExp")
+                } else {
+                    snapshot.add_node(NODE_HIDDEN, &format!("({})", self.heap.type_name(ty)))
+                };
+                
+                let mut index = 0;
+                
+                unsafe {
+                    self.heap.walk_children(ptr, &mut |child| {
+                        snapshot.add_index_edge(node, EDGE_HIDDEN, index, child);
+                        index += 1;
+                    });
+                }
+                
+                node
+            }
+        }
+    }
+}
@@ -5,22 +5,38 @@ use std::mem::{size_of, transmute};
 use std::ptr;
 use std::cell::Cell;
 
-const STACK : usize = 8192;
+// Number of values allocated past the end of the stack. The capacity checks
+// are made against the end of the stack, so the guard leaves room for the
+// values pushed by the instruction that is running and for creating the
+// RangeError when the stack overflows.
+const STACK_GUARD : usize = 1024;
 
 pub struct Stack {
     stack: Memory,
     sp: Cell<ptr_t>,
+    limit: ptr_t,
     end: ptr_t
 }
 
 impl Stack {
-    pub fn new() -> Stack {
-        let stack = Memory::alloc(STACK).unwrap();
-        let (sp, end) = unsafe { (stack.ptr(), stack.ptr().offset(STACK as isize)) };
+    pub fn new(size: usize) -> Stack {
+        // Round the size down to a whole number of values.
+        let size = size / size_of::<JsRawValue>() * size_of::<JsRawValue>();
+        let guard = STACK_GUARD * size_of::<JsRawValue>();
+        
+        let stack = Memory::alloc(size + guard).unwrap();
+        let (sp, limit, end) = unsafe {
+            (
+                stack.ptr(),
+                stack.ptr().offset(size as isize),
+                stack.ptr().offset((size + guard) as isize)
+            )
+        };
         
         Stack {
             stack: stack,
             sp: Cell::new(sp),
+            limit: limit,
             end: end
         }
     }
@@ -49,9 +65,21 @@ impl Stack {
         self.sp.set(frame.sp);
     }
     
+    pub fn has_capacity(&self, values: usize) -> bool {
+        let sp = self.sp.get() as usize;
+        let limit = self.limit as usize;
+        
+        sp <= limit && limit - sp >= values * size_of::<JsRawValue>()
+    }
+    
+    /// Pushes a value onto the stack. Callers make sure there is room by
+    /// checking the capacity through JsEnv::check_stack before pushing a
+    /// number of values that isn't bounded by the stack guard. Running into
+    /// the end of the guard means one of these checks is missing, so this
+    /// still panics instead of writing past the stack.
     pub fn push(&self, value: JsRawValue) {
-        if self.sp.get() == self.end {
-            panic!("stack overflow");
+        if self.sp.get() >= self.end {
+            panic!("stack guard exceeded");
         }
         
         unsafe {
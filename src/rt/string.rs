@@ -1,5 +1,6 @@
-use gc::{Array, Local};
-use rt::{JsEnv, JsValue, JsItem, JsDescriptor, JsHandle, GC_STRING, GC_U16};
+use gc::{Array, Local, AsPtr};
+use rt::{JsEnv, JsValue, JsItem, JsDescriptor, JsHandle, JsError, GC_STRING, GC_U16};
+use ::JsResult;
 use rt::utf;
 use syntax::Name;
 use syntax::token::name;
@@ -17,9 +18,27 @@ impl JsString {
             result.chars = env.heap.alloc_array(GC_U16, size);
         }
         
+        env.heap.write_barrier(result.as_ptr().ptr());
+        
         result
     }
     
+    // Allocates a string of which the size is controlled by the script, e.g.
+    // the result of a concatenation. Fails with an out of memory abort
+    // instead of using the reserve of the heap.
+    pub fn try_new_local(env: &JsEnv, size: usize) -> JsResult<Local<JsString>> {
+        let mut result = env.heap.alloc_local::<JsString>(GC_STRING);
+        
+        match unsafe { env.heap.try_alloc_array(GC_U16, size) } {
+            Some(chars) => result.chars = chars,
+            None => return Err(JsError::new_out_of_memory(env))
+        }
+        
+        env.heap.write_barrier(result.as_ptr().ptr());
+        
+        Ok(result)
+    }
+    
     pub fn from_str<'a>(env: &'a JsEnv, string: &str) -> Local<JsString> {
         let chars = utf::utf32_to_utf16(
             &string.chars().map(|c| c as u32).collect::<Vec<_>>()[..],
@@ -39,6 +58,25 @@ impl JsString {
         result
     }
     
+    pub fn try_from_str(env: &JsEnv, string: &str) -> JsResult<Local<JsString>> {
+        let chars = utf::utf32_to_utf16(
+            &string.chars().map(|c| c as u32).collect::<Vec<_>>()[..],
+            false
+        );
+        
+        let mut result = try!(Self::try_new_local(env, chars.len()));
+        
+        {
+            let result_chars = &mut *result.chars;
+            
+            for i in 0..chars.len() {
+                result_chars[i] = chars[i];
+            }
+        }
+        
+        Ok(result)
+    }
+    
     pub fn from_u16(env: &JsEnv, chars: &[u16]) -> Local<JsString> {
         // TODO #84: Most of the calls to this function take the chars from the GC
         // heap. Because of this we create a copy of chars. However, this must
@@ -64,13 +102,13 @@ impl JsString {
         &*self.chars
     }
     
-    pub fn concat<'a>(env: &'a JsEnv, strings: &[Local<JsString>]) -> Local<JsString> {
+    pub fn concat<'a>(env: &'a JsEnv, strings: &[Local<JsString>]) -> JsResult<Local<JsString>> {
         let mut len = 0;
         for string in strings {
             len += string.chars().len();
         }
         
-        let mut result = Self::new_local(&env, len);
+        let mut result = try!(Self::try_new_local(&env, len));
         
         {
             let chars = &mut *result.chars;
@@ -85,7 +123,7 @@ impl JsString {
             }
         }
         
-        result
+        Ok(result)
     }
     
     pub fn equals(x: Local<JsString>, y: Local<JsString>) -> bool {
@@ -647,7 +647,7 @@ impl JsValue {
                 
                 let constructor = try!(env.handle(JsHandle::Global).get(env, class));
                 let object = try!(constructor.construct(env, Vec::new()));
-                object.unwrap_object().set_value(*self);
+                object.unwrap_object().set_value(env, *self);
                 Ok(object)
             }
             JsType::Object => Ok(*self),
@@ -755,8 +755,8 @@ impl JsItem for JsValue {
         delegate!(self, env, prototype(env))
     }
     
-    fn set_prototype(&mut self, prototype: Option<JsValue>)  {
-        delegate!(self, set_prototype(prototype))
+    fn set_prototype(&mut self, env: &JsEnv, prototype: Option<JsValue>)  {
+        delegate!(self, env, set_prototype(env, prototype))
     }
     
     fn has_class(&self) -> bool  {
@@ -783,8 +783,8 @@ impl JsItem for JsValue {
         delegate!(self, env, scope(env))
     }
     
-    fn set_scope(&mut self, scope: Option<Local<JsScope>>) {
-        delegate!(self, set_scope(scope))
+    fn set_scope(&mut self, env: &JsEnv, scope: Option<Local<JsScope>>) {
+        delegate!(self, env, set_scope(env, scope))
     }
 }
 
@@ -1,10 +1,12 @@
 use gc::{GcWalker, GcWalk, GcFinalize, GcRootWalker, ptr_t};
-use rt::{JsType, JsRegExp, JsObject};
+use rt::{JsType, JsRegExp, JsObject, validate_walker};
 use rt::{GC_ARRAY_STORE, GC_ENTRY, GC_HASH_STORE, GC_ITERATOR, GC_OBJECT, GC_REGEXP};
 use rt::{GC_SCOPE, GC_STRING, GC_U16, GC_U32, GC_VALUE, GC_SPARSE_ARRAY, GC_ARRAY_CHUNK};
 use rt::stack::Stack;
-use std::mem::transmute;
+use std::mem::{replace, transmute};
 use std::rc::Rc;
+use std::cell::RefCell;
+use std::any::Any;
 
 #[inline(always)]
 unsafe fn is_value_ptr(ptr: ptr_t, offset: usize) -> bool {
@@ -15,13 +17,15 @@ unsafe fn is_value_ptr(ptr: ptr_t, offset: usize) -> bool {
 }
 
 pub struct Walker {
-    stack: Rc<Stack>
+    stack: Rc<Stack>,
+    finalized: RefCell<Vec<Box<Any>>>
 }
 
 impl Walker {
     pub fn new(stack: Rc<Stack>) -> Walker {
         Walker {
-            stack: stack
+            stack: stack,
+            finalized: RefCell::new(Vec::new())
         }
     }
 }
@@ -121,7 +125,9 @@ impl GcWalker for Walker {
                 }
                 GC_OBJECT => {
                     let object = transmute::<_, *mut JsObject>(ptr);
-                    (&mut *object).finalize();
+                    if let Some(finalized) = (&mut *object).finalize() {
+                        self.finalized.borrow_mut().push(finalized);
+                    }
                     
                     GcFinalize::Finalized
                 }
@@ -133,4 +139,33 @@ impl GcWalker for Walker {
     fn create_root_walkers(&self) -> Vec<Box<GcRootWalker>> {
         vec![self.stack.create_walker()]
     }
+    
+    fn drop_finalized(&self) {
+        let finalized = replace(&mut *self.finalized.borrow_mut(), Vec::new());
+        
+        drop(finalized);
+    }
+    
+    fn type_name(&self, ty: u32) -> &'static str {
+        match ty {
+            GC_ARRAY_STORE => "array_store",
+            GC_ENTRY => "entry",
+            GC_HASH_STORE => "hash_store",
+            GC_ITERATOR => "iterator",
+            GC_OBJECT => "object",
+            GC_SCOPE => "scope",
+            GC_STRING => "string",
+            GC_U16 => "u16",
+            GC_U32 => "u32",
+            GC_VALUE => "value",
+            GC_ARRAY_CHUNK => "array_chunk",
+            GC_SPARSE_ARRAY => "sparse_array",
+            GC_REGEXP => "regexp",
+            _ => "unknown"
+        }
+    }
+    
+    fn validate(&self) {
+        validate_walker(self);
+    }
 }
@@ -104,10 +104,19 @@ pub enum ScopeType {
 
 #[derive(Debug)]
 pub struct Block {
-    pub stmts: Vec<Item>,
+    pub stmts: Vec<Stmt>,
     pub locals: HashMap<Name, SlotRef>
 }
 
+/// A statement of a statement list together with its location in the
+/// source. The IR generator uses the location to map instructions back
+/// to the source.
+#[derive(Debug)]
+pub struct Stmt {
+    pub item: Item,
+    pub span: Span
+}
+
 #[derive(Debug)]
 pub enum Item {
     Block(Option<Label>, Block),
@@ -193,8 +202,8 @@ pub struct ExprSeq {
 
 #[derive(Debug)]
 pub enum SwitchClause {
-    Case(ExprSeq, Vec<Item>),
-    Default(Vec<Item>)
+    Case(ExprSeq, Vec<Stmt>),
+    Default(Vec<Stmt>)
 }
 
 #[derive(Debug)]
@@ -11,7 +11,7 @@ pub trait AstVisitor<'a> {
     
     fn visit_block(&mut self, block: &'a Block) {
         for stmt in &block.stmts {
-            self.visit_item(stmt);
+            self.visit_item(&stmt.item);
         }
     }
     
@@ -179,18 +179,18 @@ pub trait AstVisitor<'a> {
     }
     
     fn visit_switch_clause_case(&mut self, switch_clause: &'a SwitchClause) {
-        if let SwitchClause::Case(ref exprs, ref items) = *switch_clause {
+        if let SwitchClause::Case(ref exprs, ref stmts) = *switch_clause {
             self.visit_expr_seq(exprs);
-            for item in items {
-                self.visit_item(item);
+            for stmt in stmts {
+                self.visit_item(&stmt.item);
             }
         }
     }
     
     fn visit_switch_clause_default(&mut self, switch_clause: &'a SwitchClause) {
-        if let SwitchClause::Default(ref items) = *switch_clause {
-            for item in items {
-                self.visit_item(item);
+        if let SwitchClause::Default(ref stmts) = *switch_clause {
+            for stmt in stmts {
+                self.visit_item(&stmt.item);
             }
         }
     }
@@ -84,7 +84,7 @@ impl<'a> Lexer<'a> {
             
             Ok(Span::new(0, 0, 0, 0, self.file))
         } else {
-            self.fatal("Unexpected end of input")
+            self.fatal_eof()
         }
     }
     
@@ -105,7 +105,7 @@ impl<'a> Lexer<'a> {
             let hidden = if let Some(token) = try!(self.peek_any(0)) {
                 token.token.is_hidden()
             } else {
-                return self.fatal("Unexpected end of input");
+                return self.fatal_eof();
             };
             
             try!(self.next_any());
@@ -120,7 +120,7 @@ impl<'a> Lexer<'a> {
     
     pub fn bump_any(&mut self) -> JsResult<()> {
         if try!(self.is_any_eof()) {
-            self.fatal("Unexpected end of input")
+            self.fatal_eof()
         } else {
             try!(self.next_any());
             
@@ -147,7 +147,7 @@ impl<'a> Lexer<'a> {
             
             Ok(token)
         } else {
-            self.fatal("Unexpected end of input")
+            self.fatal_eof()
         }
     }
     
@@ -199,6 +199,12 @@ impl<'a> Lexer<'a> {
         Err(JsError::Lex(format!("{}:{}: {}", line, col, message.to_string())))
     }
     
+    fn fatal_eof<T>(&self) -> JsResult<T> {
+        let (line, col) = self.reader.pos();
+        
+        Err(JsError::Incomplete(format!("{}:{}: Unexpected end of input", line, col)))
+    }
+    
     fn parse(&mut self) -> JsResult<TokenAndSpan> {
         let (start_line, start_col) = self.reader.pos();
         
@@ -442,7 +448,7 @@ impl<'a> Lexer<'a> {
             }
         }
         
-        Err(JsError::Parse("Unmatched block comment".to_string()))
+        Err(JsError::Incomplete("Unmatched block comment".to_string()))
     }
     
     fn skip_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
@@ -10,7 +10,7 @@ pub mod parser;
 pub mod token;
 pub mod ast;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct Span {
     pub start_line: i32,
     pub start_col: i32,
@@ -33,7 +33,7 @@ struct BlockScope {
 }
 
 impl RootBlock {
-    fn new(stmts: Vec<Item>, args: Vec<Name>, strict: bool, scope: Scope, locals: HashMap<Name, SlotRef>) -> RootBlock {
+    fn new(stmts: Vec<Stmt>, args: Vec<Name>, strict: bool, scope: Scope, locals: HashMap<Name, SlotRef>) -> RootBlock {
         // When the scope is deopt, the scope builds a tick scope. This implies the scope taking
         // a scope (which is a thick scope too).
         
@@ -215,7 +215,15 @@ impl<'a> Parser<'a> {
             _ => Span::new(-1, -1, -1, -1, self.interner.intern(""))
         };
         
-        Err(JsError::Parse(format!("{}:{}: {}", span.start_line, span.start_col, message.to_string())))
+        let message = format!("{}:{}: {}", span.start_line, span.start_col, message.to_string());
+        
+        // Errors at the end of the input mean that the program isn't complete.
+        
+        if span.start_line == -1 {
+            Err(JsError::Incomplete(message))
+        } else {
+            Err(JsError::Parse(message))
+        }
     }
     
     fn expect_eos(&mut self) -> JsResult<()> {
@@ -307,14 +315,17 @@ impl<'a> Parser<'a> {
         try!(parser.parse_strict());
         
         while !try!(parser.is_eof()) {
-            items.push(try!(parser.parse_stmt(None)));
+            items.push(try!(parser.parse_located_stmt()));
         }
         // Turn the last statement into a return.
         
         let len = items.len();
         if len > 0 {
-            let item = items.pop().unwrap();
-            items.push(Item::Leave(Box::new(item)));
+            let stmt = items.pop().unwrap();
+            items.push(Stmt {
+                item: Item::Leave(Box::new(stmt.item)),
+                span: stmt.span
+            });
         }
         
         let end = parser.lexer.last_span().unwrap_or(start);
@@ -447,7 +458,7 @@ impl<'a> Parser<'a> {
         }
         
         while !try!(self.consume(Token::CloseBrace)) {
-            stmts.push(try!(self.parse_stmt(None)));
+            stmts.push(try!(self.parse_located_stmt()));
         }
         
         self.lexer.set_strict(was_strict);
@@ -693,7 +704,20 @@ impl<'a> Parser<'a> {
         }
     }
     
-    fn parse_stmt_list(&mut self) -> JsResult<Vec<Item>> {
+    fn parse_located_stmt(&mut self) -> JsResult<Stmt> {
+        let start = try!(self.lexer.span());
+        
+        let item = try!(self.parse_stmt(None));
+        
+        let end = self.lexer.last_span().unwrap_or(start);
+        
+        Ok(Stmt {
+            item: item,
+            span: Span::from_range(start, end)
+        })
+    }
+    
+    fn parse_stmt_list(&mut self) -> JsResult<Vec<Stmt>> {
         let mut stmts = Vec::new();
         
         loop {
@@ -705,7 +729,7 @@ impl<'a> Parser<'a> {
                 _ => {}
             }
             
-            stmts.push(try!(self.parse_stmt(None)));
+            stmts.push(try!(self.parse_located_stmt()));
         }
     }
     
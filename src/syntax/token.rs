@@ -471,4 +471,5 @@ declare_idents! {
     ( "localeCompare", LOCALE_COMPARE, 254 );
     ( "match", MATCH, 255 );
     ( "replace", REPLACE, 256 );
+    ( "stack", STACK, 257 );
 }
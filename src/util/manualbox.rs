@@ -27,6 +27,20 @@ impl<T> ManualBox<T> {
         
         self.ptr = ptr::null_mut();
     }
+    
+    /// Moves the value out into a Box, leaving the ManualBox empty. The
+    /// value is then freed when the Box is dropped.
+    pub fn take(&mut self) -> Box<T> {
+        if self.ptr.is_null() {
+            panic!("taking from empty ManualBox");
+        }
+        
+        let result = unsafe { transmute::<_, Box<T>>(self.ptr) };
+        
+        self.ptr = ptr::null_mut();
+        
+        result
+    }
 }
 
 impl<T> Deref for ManualBox<T> {
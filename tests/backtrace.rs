@@ -0,0 +1,72 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsItem};
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+#[test]
+fn error_stack() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let stack = eval(&mut env, "function inner() { return new Error('boom').stack; }\nfunction outer() { return inner(); }\nouter()");
+    let lines = stack.lines().collect::<Vec<_>>();
+    
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "Error: boom");
+    assert!(lines[1].starts_with("    at inner ((global):1:"));
+    assert!(lines[2].starts_with("    at outer ((global):2:"));
+    assert!(lines[3].starts_with("    at (global):3:"));
+}
+
+#[test]
+fn error_stack_without_getters() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    // Creating an error does not run the name getter; converting it to a
+    // string does.
+    
+    eval(&mut env, "Object.defineProperty(Error.prototype, 'name', { get: function () { throw 'getter'; } }); var e = new Error('boom');");
+    
+    assert_eq!(eval(&mut env, "e.stack.split('\\n')[0]"), "Error: boom");
+    assert_eq!(eval(&mut env, "try { String(e); } catch (x) { x }"), "getter");
+}
+
+#[test]
+fn uncaught_error_backtrace() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let error = env.eval("function fail() { null.x; }\nfail()").err().unwrap();
+    let backtrace = error.backtrace().unwrap();
+    let frames = backtrace.frames();
+    
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].function, Some("fail".to_string()));
+    assert_eq!(frames[1].function, None);
+    assert_eq!(frames[1].location.as_ref().unwrap().file, "(global)");
+}
+
+#[test]
+fn error_location() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    // Every statement maps to its own line, also within a single function.
+    
+    let error = env.eval("function fail() {\n    var a = 1;\n    a.b.c;\n}\n\nfail();").err().unwrap();
+    let backtrace = error.backtrace().unwrap();
+    let frames = backtrace.frames();
+    
+    let location = frames[0].location.as_ref().unwrap();
+    assert_eq!((location.line, location.column), (3, 5));
+    
+    let location = frames[1].location.as_ref().unwrap();
+    assert_eq!((location.line, location.column), (6, 1));
+}
@@ -0,0 +1,73 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsEnvBuilder, JsIntrinsics, JsItem};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+#[test]
+fn no_intrinsics() {
+    let mut env = JsEnvBuilder::new().intrinsics(JsIntrinsics::none()).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert_eq!(eval(&mut env, "[typeof console, typeof Date, typeof eval, typeof JSON, typeof Math, typeof RegExp].join()"), "undefined,undefined,undefined,undefined,undefined,undefined");
+    
+    // Regular expressions cannot be created without RegExp.
+    
+    assert_eq!(eval(&mut env, "try { /a/; } catch (e) { e instanceof TypeError }"), "true");
+    assert_eq!(eval(&mut env, "try { 'abc'.match('b'); } catch (e) { e instanceof TypeError }"), "true");
+    assert_eq!(eval(&mut env, "'a,b'.split(',').length"), "2");
+}
+
+#[test]
+fn some_intrinsics() {
+    let mut intrinsics = JsIntrinsics::none();
+    intrinsics.regexp = true;
+    intrinsics.math = true;
+    
+    let mut env = JsEnvBuilder::new().intrinsics(intrinsics).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert_eq!(eval(&mut env, "[typeof Date, typeof JSON, typeof Math, typeof RegExp].join()"), "undefined,undefined,object,function");
+    assert_eq!(eval(&mut env, "/b+/.exec('abbc')[0]"), "bb");
+}
+
+#[test]
+fn hooks() {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let console_output = output.clone();
+    
+    let mut env = JsEnvBuilder::new()
+        .console(Box::new(move |message: &str| console_output.borrow_mut().push(message.to_string())))
+        .time(Box::new(|| 1000.0))
+        .random(Box::new(|| 0.5))
+        .build()
+        .ok()
+        .unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert_eq!(eval(&mut env, "Date.now()"), "1000");
+    assert_eq!(eval(&mut env, "Math.random()"), "0.5");
+    
+    eval(&mut env, "console.log('hello')");
+    assert_eq!(*output.borrow(), vec!["hello".to_string()]);
+}
+
+#[test]
+fn strict() {
+    let mut env = JsEnvBuilder::new().strict(true).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert_eq!(eval(&mut env, "(function () { return this; })() === undefined"), "true");
+    
+    let mut env = JsEnvBuilder::new().build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert_eq!(eval(&mut env, "(function () { return this; })() === undefined"), "false");
+}
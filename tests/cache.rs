@@ -0,0 +1,83 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsItem};
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+
+fn run_cached(file_name: &str, cache_file_name: &str) -> String {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let result = env.run_cached(file_name, cache_file_name).ok().unwrap();
+    let result = result.as_value(&env);
+    
+    result.to_string(&mut env).ok().unwrap().to_string()
+}
+
+#[test]
+fn cache_file() {
+    let dir = env::temp_dir();
+    let file_name = dir.join("rjs-cache-test.js");
+    let cache_file_name = dir.join("rjs-cache-test.rjsc");
+    
+    File::create(&file_name).unwrap().write_all(b"function square(x) { return x * x; } square(7)").unwrap();
+    let _ = fs::remove_file(&cache_file_name);
+    
+    let file_name = file_name.to_str().unwrap();
+    let cache_file_name = cache_file_name.to_str().unwrap();
+    
+    assert_eq!(run_cached(file_name, cache_file_name), "49");
+    assert!(fs::metadata(cache_file_name).is_ok());
+    assert!(fs::metadata(&format!("{}.tmp", cache_file_name)).is_err());
+    
+    // The second run loads the functions from the cache.
+    
+    assert_eq!(run_cached(file_name, cache_file_name), "49");
+    
+    fs::remove_file(cache_file_name).unwrap();
+    fs::remove_file(file_name).unwrap();
+}
+
+#[test]
+fn unwritable_cache_file() {
+    let dir = env::temp_dir();
+    let file_name = dir.join("rjs-cache-unwritable-test.js");
+    let cache_file_name = dir.join("rjs-missing-directory").join("test.rjsc");
+    
+    File::create(&file_name).unwrap().write_all(b"1 + 2").unwrap();
+    
+    let file_name = file_name.to_str().unwrap();
+    
+    assert_eq!(run_cached(file_name, cache_file_name.to_str().unwrap()), "3");
+    
+    fs::remove_file(file_name).unwrap();
+}
+
+#[test]
+fn damaged_cache_file() {
+    let dir = env::temp_dir();
+    let file_name = dir.join("rjs-cache-damaged-test.js");
+    let cache_file_name = dir.join("rjs-cache-damaged-test.rjsc");
+    
+    File::create(&file_name).unwrap().write_all(b"'a' + 'b'").unwrap();
+    
+    let file_name = file_name.to_str().unwrap();
+    let cache_file_name = cache_file_name.to_str().unwrap();
+    
+    assert_eq!(run_cached(file_name, cache_file_name), "ab");
+    
+    // Damage everything after the header; the file is parsed again.
+    
+    let mut cache = Vec::new();
+    File::open(cache_file_name).unwrap().read_to_end(&mut cache).unwrap();
+    for i in 16..cache.len() {
+        cache[i] = 0xFF;
+    }
+    File::create(cache_file_name).unwrap().write_all(&cache).unwrap();
+    
+    assert_eq!(run_cached(file_name, cache_file_name), "ab");
+    
+    fs::remove_file(cache_file_name).unwrap();
+    fs::remove_file(file_name).unwrap();
+}
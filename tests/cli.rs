@@ -0,0 +1,58 @@
+extern crate rjs;
+
+use rjs::rt::JsEnv;
+use std::env;
+use std::process::{Command, Output};
+
+// The rjs binary is built next to the deps directory of the test.
+fn rjs(args: &[&str]) -> Output {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.push(format!("rjs{}", env::consts::EXE_SUFFIX));
+    
+    Command::new(path).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn eval_strict() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert!(env.eval_strict("undeclared = 1", true).is_err());
+    assert!(env.eval_strict("undeclared = 1", false).is_ok());
+}
+
+#[test]
+fn eval_option() {
+    let output = rjs(&["-e", "console.log(1 + 2)"]);
+    
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3");
+}
+
+#[test]
+fn strict_option() {
+    let js = "console.log(typeof function () { return this; }())";
+    
+    assert_eq!(stdout(&rjs(&["-e", js])), "object");
+    assert_eq!(stdout(&rjs(&["--strict", "-e", js])), "undefined");
+    
+    let output = rjs(&["--strict", "-e", "undeclared = 1"]);
+    
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ReferenceError"));
+}
+
+#[test]
+fn missing_eval_code() {
+    let output = rjs(&["-e"]);
+    
+    assert_eq!(output.status.code(), Some(2));
+}
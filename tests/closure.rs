@@ -0,0 +1,92 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsArgs, JsFnMode, JsValue, JsItem};
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+#[test]
+fn reentrant_closure() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let calls = Rc::new(Cell::new(0));
+    let closure_calls = calls.clone();
+    
+    // Calls the callback passed as the first argument with the second
+    // argument.
+    
+    let invoke = env.new_native_closure("invoke", 1, Box::new(move |env: &mut JsEnv, _mode: JsFnMode, args: JsArgs| {
+        closure_calls.set(closure_calls.get() + 1);
+        
+        let callback = args.arg(env, 0);
+        let arg = args.arg(env, 1);
+        
+        callback.call(env, JsValue::new_undefined(), vec![arg], false)
+    }));
+    
+    env.global().set("invoke", invoke).ok().unwrap();
+    
+    assert_eq!(eval(&mut env, "function count(n) { return n == 0 ? 0 : 1 + invoke(count, n - 1); } invoke(count, 5)"), "5");
+    assert_eq!(calls.get(), 6);
+    
+    assert_eq!(eval(&mut env, "var sum = 0; invoke(function () { [1, 2].forEach(function (x) { invoke(function (y) { sum += y; }, x); }); }); sum"), "3");
+}
+
+#[test]
+fn closure_errors() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let fail = env.new_native_closure("fail", 0, Box::new(|env: &mut JsEnv, _mode: JsFnMode, _args: JsArgs| {
+        env.eval("null.x").map(|_| JsValue::new_undefined())
+    }));
+    
+    env.global().set("fail", fail).ok().unwrap();
+    
+    assert_eq!(eval(&mut env, "try { fail(); } catch (e) { e instanceof TypeError }"), "true");
+    assert_eq!(eval(&mut env, "try { new fail(); } catch (e) { e instanceof TypeError }"), "true");
+}
+
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+fn drop_on_collection() {
+    let mut env = JsEnv::new().ok().unwrap();
+    
+    let dropped = Rc::new(Cell::new(false));
+    
+    {
+        let _scope = env.new_local_scope();
+        
+        // The closure keeps a JavaScript object alive through a root, which
+        // is released when the closure is dropped.
+        
+        let flag = DropFlag(dropped.clone());
+        let object = env.eval("({ answer: 42 })").ok().unwrap();
+        
+        env.new_native_closure("captured", 0, Box::new(move |env: &mut JsEnv, _mode: JsFnMode, _args: JsArgs| {
+            let _ = &flag;
+            
+            Ok(object.as_value(env))
+        }));
+    }
+    
+    assert!(!dropped.get());
+    
+    env.gc();
+    
+    assert!(dropped.get());
+}
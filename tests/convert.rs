@@ -0,0 +1,126 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsValue, JsItem, FromJs, IntoJs};
+use rjs::JsResult;
+use std::collections::HashMap;
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+fn from_js<T: FromJs>(env: &mut JsEnv, js: &str) -> T {
+    let value = env.eval(js).ok().unwrap();
+    let value = value.as_value(env);
+    
+    T::from_js(env, value).ok().unwrap()
+}
+
+#[test]
+fn from_js_values() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert_eq!(from_js::<f64>(&mut env, "'1.5'"), 1.5);
+    assert_eq!(from_js::<i32>(&mut env, "-7.9"), -7);
+    assert_eq!(from_js::<u32>(&mut env, "-1"), 4294967295);
+    assert_eq!(from_js::<bool>(&mut env, "''"), false);
+    assert_eq!(from_js::<String>(&mut env, "[1, 2]"), "1,2");
+    assert_eq!(from_js::<Option<f64>>(&mut env, "null"), None);
+    assert_eq!(from_js::<Option<f64>>(&mut env, "2"), Some(2.0));
+    assert_eq!(from_js::<Vec<String>>(&mut env, "({ length: 2, 0: 'a', 1: 'b' })"), vec!["a".to_string(), "b".to_string()]);
+    
+    let map = from_js::<HashMap<String, f64>>(&mut env, "var o = Object.create({ inherited: 1 }); o.a = 1; o.b = 2; Object.defineProperty(o, 'hidden', { value: 3 }); o");
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["a"], 1.0);
+    assert_eq!(map["b"], 2.0);
+}
+
+#[test]
+fn from_js_huge_length() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    // The length is not trusted to reserve memory; the first item throws.
+    
+    let value = env.eval("({ length: 4294967295, get 0() { throw 'stop'; } })").ok().unwrap();
+    let value = value.as_value(&env);
+    
+    assert!(Vec::<f64>::from_js(&mut env, value).is_err());
+}
+
+#[test]
+fn from_js_with_getters() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    // The getters add and delete properties while the object is converted.
+    
+    let map = from_js::<HashMap<String, f64>>(&mut env, "
+        var o = {
+            get a() { delete this.b; for (var i = 0; i < 100; i++) { this['x' + i] = i; } return 1; },
+            b: 2,
+            c: 3
+        };
+        o
+    ");
+    
+    assert_eq!(map.get("a"), Some(&1.0));
+    assert_eq!(map.get("b"), None);
+    assert_eq!(map.get("c"), Some(&3.0));
+}
+
+#[test]
+fn into_js_values() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let mut map = HashMap::new();
+    map.insert("list".to_string(), vec![Some(1.0), None]);
+    
+    let value = map.into_js(&mut env).ok().unwrap();
+    env.global().set("value", value).ok().unwrap();
+    
+    assert_eq!(eval(&mut env, "JSON.stringify(value)"), "{\"list\":[1,null]}");
+    
+    let value = "text".into_js(&mut env).ok().unwrap();
+    env.global().set("value", value).ok().unwrap();
+    
+    assert_eq!(eval(&mut env, "typeof value + ':' + value"), "string:text");
+}
+
+#[test]
+fn typed_functions() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let add = env.new_typed_function("add", |x: f64, y: f64| x + y);
+    env.global().set("add", add).ok().unwrap();
+    
+    let join = env.new_typed_function("join", |items: Vec<String>, separator: Option<String>| {
+        items.join(&separator.unwrap_or(",".to_string()))
+    });
+    env.global().set("join", join).ok().unwrap();
+    
+    let mut total = 0.0;
+    let accumulate = env.new_typed_function("accumulate", move |x: f64| { total += x; total });
+    env.global().set("accumulate", accumulate).ok().unwrap();
+    
+    let check = env.new_typed_function("check", |x: JsValue| -> JsResult<bool> { Ok(x.is_undefined()) });
+    env.global().set("check", check).ok().unwrap();
+    
+    assert_eq!(eval(&mut env, "add(1, '2')"), "3");
+    assert_eq!(eval(&mut env, "add(1)"), "NaN");
+    assert_eq!(eval(&mut env, "add.length"), "2");
+    assert_eq!(eval(&mut env, "join(['a', 'b'])"), "a,b");
+    assert_eq!(eval(&mut env, "join(['a', 'b'], '-')"), "a-b");
+    assert_eq!(eval(&mut env, "accumulate(1); accumulate(2)"), "3");
+    assert_eq!(eval(&mut env, "check()"), "true");
+    assert_eq!(eval(&mut env, "try { add({ valueOf: function () { throw 'error'; } }, 1); } catch (e) { e }"), "error");
+    
+    // Converting the arguments can call the same function again.
+    
+    assert_eq!(eval(&mut env, "accumulate({ valueOf: function () { return accumulate(10); } })"), "26");
+}
@@ -0,0 +1,54 @@
+extern crate rjs;
+
+use rjs::rt::JsEnv;
+
+const SCRIPT : &'static str = "\
+function f(a) {
+    if (a) {
+        return 1;
+    }
+    return 2;
+}
+function g() {
+    return 3;
+}
+f(true);
+";
+
+#[test]
+fn statements_branches_and_functions() {
+    let mut env = JsEnv::new().ok().unwrap();
+    
+    env.start_coverage();
+    env.eval(SCRIPT).ok().unwrap();
+    let coverage = env.stop_coverage().unwrap();
+    
+    let file = &coverage.files()["(global)"];
+    
+    let f = file.functions.iter().find(|function| function.name == "f").unwrap();
+    assert_eq!(f.line, 1);
+    assert_eq!(f.calls, 1);
+    
+    let g = file.functions.iter().find(|function| function.name == "g").unwrap();
+    assert_eq!(g.calls, 0);
+    
+    let lines = file.lines();
+    assert_eq!(lines[&3], 1);
+    assert_eq!(lines[&5], 0);
+    assert_eq!(lines[&8], 0);
+    assert_eq!(lines[&10], 1);
+    
+    let branches = file.branches.iter().filter(|branch| branch.line == 2).collect::<Vec<_>>();
+    assert_eq!(branches.len(), 1);
+    assert_eq!(branches[0].taken + branches[0].not_taken, 1);
+    
+    let mut lcov = Vec::new();
+    coverage.write_lcov(&mut lcov).unwrap();
+    let lcov = String::from_utf8(lcov).unwrap();
+    
+    assert!(lcov.starts_with("TN:\nSF:(global)\n"));
+    assert!(lcov.contains("FNDA:1,f\n"));
+    assert!(lcov.contains("FNDA:0,g\n"));
+    assert!(lcov.contains("DA:5,0\n"));
+    assert!(lcov.ends_with("end_of_record\n"));
+}
@@ -0,0 +1,198 @@
+extern crate rjs;
+extern crate rustc_serialize;
+
+use rjs::contrib::dap::DapServer;
+use rustc_serialize::json::Json;
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, Cursor};
+use std::rc::Rc;
+
+const SCRIPT : &'static str = "\
+var x = 1;
+function f(a) {
+    var b = a + 1;
+    return b;
+}
+var y = f(x);
+console.log(y);
+";
+
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend(buf.iter().cloned());
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn request(input: &mut String, seq: u32, command: &str, arguments: &str) {
+    let content = format!("{{\"seq\":{},\"type\":\"request\",\"command\":\"{}\",\"arguments\":{}}}", seq, command, arguments);
+    input.push_str(&format!("Content-Length: {}\r\n\r\n{}", content.len(), content));
+}
+
+fn parse_messages(output: &str) -> Vec<Json> {
+    let mut messages = Vec::new();
+    let mut rest = output;
+    
+    while let Some(offset) = rest.find("\r\n\r\n") {
+        let length = rest[..offset]["Content-Length: ".len()..].parse::<usize>().unwrap();
+        let start = offset + 4;
+        
+        messages.push(Json::from_str(&rest[start..start + length]).unwrap());
+        
+        rest = &rest[start + length..];
+    }
+    
+    messages
+}
+
+fn find<'a>(messages: &'a [Json], request_seq: u64) -> &'a Json {
+    messages.iter()
+        .find(|message| message.find("request_seq").and_then(|seq| seq.as_u64()) == Some(request_seq))
+        .unwrap()
+}
+
+fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+    messages.iter()
+        .filter(|message| message.find("event").and_then(|name| name.as_string()) == Some(event))
+        .collect()
+}
+
+fn string<'a>(message: &'a Json, path: &[&str]) -> &'a str {
+    message.find_path(path).and_then(|value| value.as_string()).unwrap()
+}
+
+// The client is scripted: all requests are sent up front and the server
+// processes requests that arrive while the script runs once it pauses.
+// Variable references are handed out in order, so the locals of the first
+// scopes request get reference 1.
+#[test]
+fn breakpoint_step_and_inspect() {
+    let file = env::temp_dir().join("rjs-dap-test.js");
+    let file = file.to_str().unwrap().to_string();
+    
+    File::create(&file).unwrap().write_all(SCRIPT.as_bytes()).unwrap();
+    
+    let mut input = String::new();
+    
+    request(&mut input, 1, "initialize", "{\"adapterID\":\"rjs\"}");
+    request(&mut input, 2, "launch", "{}");
+    request(&mut input, 3, "setBreakpoints", &format!("{{\"source\":{{\"path\":{}}},\"breakpoints\":[{{\"line\":3}}]}}", Json::String(file.clone())));
+    request(&mut input, 4, "configurationDone", "{}");
+    request(&mut input, 5, "stackTrace", "{\"threadId\":1}");
+    request(&mut input, 6, "scopes", "{\"frameId\":0}");
+    request(&mut input, 7, "variables", "{\"variablesReference\":1}");
+    request(&mut input, 8, "evaluate", "{\"expression\":\"a * 10\",\"frameId\":0}");
+    request(&mut input, 9, "next", "{\"threadId\":1}");
+    request(&mut input, 10, "stackTrace", "{\"threadId\":1}");
+    request(&mut input, 11, "evaluate", "{\"expression\":\"b\",\"frameId\":0}");
+    request(&mut input, 12, "continue", "{\"threadId\":1}");
+    request(&mut input, 13, "disconnect", "{}");
+    
+    let output = Rc::new(RefCell::new(Vec::new()));
+    
+    {
+        let mut server = DapServer::new(&file, false, Box::new(Cursor::new(input.into_bytes())), Box::new(Output(output.clone()))).ok().unwrap();
+        server.run();
+    }
+    
+    let output = String::from_utf8(output.borrow().clone()).unwrap();
+    let messages = parse_messages(&output);
+    
+    for seq in 1..14 {
+        assert_eq!(find(&messages, seq).find("success").and_then(|success| success.as_boolean()), Some(true));
+    }
+    
+    assert_eq!(events(&messages, "initialized").len(), 1);
+    
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped.len(), 2);
+    assert_eq!(string(stopped[0], &["body", "reason"]), "breakpoint");
+    assert_eq!(string(stopped[1], &["body", "reason"]), "step");
+    
+    let frames = find(&messages, 5).find_path(&["body", "stackFrames"]).unwrap().as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(string(&frames[0], &["name"]), "f");
+    assert_eq!(frames[0].find("line").unwrap().as_u64(), Some(3));
+    assert_eq!(string(&frames[1], &["name"]), "(global)");
+    assert_eq!(frames[1].find("line").unwrap().as_u64(), Some(6));
+    
+    let variables = find(&messages, 7).find_path(&["body", "variables"]).unwrap().as_array().unwrap();
+    let a = variables.iter().find(|variable| string(variable, &["name"]) == "a").unwrap();
+    assert_eq!(string(a, &["value"]), "1");
+    
+    assert_eq!(string(find(&messages, 8), &["body", "result"]), "10");
+    
+    let frames = find(&messages, 10).find_path(&["body", "stackFrames"]).unwrap().as_array().unwrap();
+    assert_eq!(frames[0].find("line").unwrap().as_u64(), Some(4));
+    
+    assert_eq!(string(find(&messages, 11), &["body", "result"]), "2");
+    
+    let output = events(&messages, "output");
+    assert_eq!(output.len(), 1);
+    assert_eq!(string(output[0], &["body", "output"]), "2\n");
+    
+    assert_eq!(events(&messages, "terminated").len(), 1);
+    assert_eq!(events(&messages, "exited")[0].find_path(&["body", "exitCode"]).unwrap().as_i64(), Some(0));
+}
+
+// Messages that cannot be read are reported and skipped; the session ends
+// only at the end of the input.
+#[test]
+fn invalid_messages() {
+    let file = env::temp_dir().join("rjs-dap-invalid-test.js");
+    let file = file.to_str().unwrap().to_string();
+    
+    File::create(&file).unwrap().write_all(b"var x = 1;\n").unwrap();
+    
+    let mut input = String::new();
+    
+    request(&mut input, 1, "initialize", "{\"adapterID\":\"rjs\"}");
+    input.push_str("Content-Length: 5\r\n\r\n{bad}");
+    input.push_str("Content-Length: many\r\n\r\n");
+    request(&mut input, 2, "evaluate", "{\"expression\":\"({ a: 1 })\"}");
+    
+    let mut input = input.into_bytes();
+    input.extend(b"Content-Length: 2\r\n\r\n\xff\xfe".iter().cloned());
+    
+    let mut rest = String::new();
+    
+    request(&mut rest, 3, "configurationDone", "{}");
+    request(&mut rest, 4, "disconnect", "{}");
+    
+    input.extend(rest.into_bytes());
+    
+    let output = Rc::new(RefCell::new(Vec::new()));
+    
+    {
+        let mut server = DapServer::new(&file, false, Box::new(Cursor::new(input)), Box::new(Output(output.clone()))).ok().unwrap();
+        server.run();
+    }
+    
+    let output = String::from_utf8(output.borrow().clone()).unwrap();
+    let messages = parse_messages(&output);
+    
+    for seq in 1..5 {
+        assert_eq!(find(&messages, seq).find("success").and_then(|success| success.as_boolean()), Some(true));
+    }
+    
+    // No references are handed out while not paused.
+    
+    assert_eq!(find(&messages, 2).find_path(&["body", "variablesReference"]).unwrap().as_u64(), Some(0));
+    
+    let output = events(&messages, "output");
+    assert_eq!(output.len(), 3);
+    for event in output {
+        assert!(string(event, &["body", "output"]).starts_with("Invalid message"));
+    }
+    
+    assert_eq!(events(&messages, "terminated").len(), 1);
+}
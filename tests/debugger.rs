@@ -0,0 +1,146 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsEnvBuilder, JsItem, Debugger, JsPauseReason, JsResume};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Records every pause with the line and the value of an expression, and
+// resumes with the next of the given resumes.
+struct TestDebugger {
+    resumes: Vec<(JsResume, &'static str)>,
+    pauses: Rc<RefCell<Vec<String>>>
+}
+
+impl Debugger for TestDebugger {
+    fn paused(&mut self, env: &mut JsEnv, reason: JsPauseReason) -> JsResume {
+        let _scope = env.new_local_scope();
+        
+        let (resume, expression) = self.resumes.remove(0);
+        
+        let line = env.backtrace().frames()[0].location.as_ref().unwrap().line;
+        
+        let value = env.debug_eval(0, expression).ok().unwrap();
+        let value = value.as_value(env);
+        let value = value.to_string(env).ok().unwrap().to_string();
+        
+        let reason = match reason {
+            JsPauseReason::Breakpoint(..) => "breakpoint",
+            JsPauseReason::Step => "step",
+            JsPauseReason::DebuggerStatement => "debugger"
+        };
+        
+        self.pauses.borrow_mut().push(format!("{} {} {}={}", reason, line, expression, value));
+        
+        resume
+    }
+}
+
+#[test]
+fn breakpoint_and_steps() {
+    let mut env = JsEnvBuilder::new().optimize(false).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let pauses = Rc::new(RefCell::new(Vec::new()));
+    
+    env.set_debugger(Some(Box::new(TestDebugger {
+        resumes: vec![
+            (JsResume::StepInto, "x"),
+            (JsResume::StepOver, "a"),
+            (JsResume::StepOut, "sum"),
+            (JsResume::Continue, "y")
+        ],
+        pauses: pauses.clone()
+    })));
+    
+    env.set_breakpoint("(global)", 6);
+    
+    let js = "\
+function add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+var x = 1;
+var y = add(x, 2);
+var z = y * 2;
+z";
+    
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(&env);
+    assert_eq!(result.to_string(&mut env).ok().unwrap().to_string(), "6");
+    
+    assert_eq!(*pauses.borrow(), vec![
+        "breakpoint 6 x=1".to_string(),
+        "step 2 a=1".to_string(),
+        "step 3 sum=3".to_string(),
+        "step 7 y=3".to_string()
+    ]);
+}
+
+#[test]
+fn debugger_statement() {
+    let mut env = JsEnvBuilder::new().optimize(false).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let pauses = Rc::new(RefCell::new(Vec::new()));
+    
+    env.set_debugger(Some(Box::new(TestDebugger {
+        resumes: vec![(JsResume::Continue, "i")],
+        pauses: pauses.clone()
+    })));
+    
+    env.eval("for (var i = 0; i < 3; i++) {\n    if (i == 2) {\n        debugger;\n    }\n}").ok().unwrap();
+    
+    assert_eq!(*pauses.borrow(), vec!["debugger 3 i=2".to_string()]);
+}
+
+#[test]
+fn breakpoint_in_loop_on_one_line() {
+    let mut env = JsEnvBuilder::new().optimize(false).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let pauses = Rc::new(RefCell::new(Vec::new()));
+    
+    env.set_debugger(Some(Box::new(TestDebugger {
+        resumes: vec![(JsResume::Continue, "i"); 4],
+        pauses: pauses.clone()
+    })));
+    
+    env.set_breakpoint("(global)", 2);
+    
+    // The loop pauses when it starts and then once for every iteration of
+    // the body.
+    
+    env.eval("var n = 0;\nfor (var i = 0; i < 3; i++) { n += i; }\nn").ok().unwrap();
+    
+    assert_eq!(*pauses.borrow(), vec![
+        "breakpoint 2 i=undefined".to_string(),
+        "breakpoint 2 i=0".to_string(),
+        "breakpoint 2 i=1".to_string(),
+        "breakpoint 2 i=2".to_string()
+    ]);
+}
+
+#[test]
+fn step_on_one_line() {
+    let mut env = JsEnvBuilder::new().optimize(false).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let pauses = Rc::new(RefCell::new(Vec::new()));
+    
+    env.set_debugger(Some(Box::new(TestDebugger {
+        resumes: vec![
+            (JsResume::StepOver, "a"),
+            (JsResume::StepOver, "a"),
+            (JsResume::Continue, "a")
+        ],
+        pauses: pauses.clone()
+    })));
+    
+    env.eval("debugger; var a = 1; var b = a + 1;\nb").ok().unwrap();
+    
+    assert_eq!(*pauses.borrow(), vec![
+        "debugger 1 a=undefined".to_string(),
+        "step 1 a=undefined".to_string(),
+        "step 1 a=1".to_string()
+    ]);
+}
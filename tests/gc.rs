@@ -0,0 +1,174 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnvBuilder, JsItem, JsAbort, IntoJs};
+use rjs::gc::{GcOpts, GcStrategy};
+use rjs::JsError;
+
+// Builds a linked list that stays alive while many short lived objects are
+// allocated, and checks the list afterwards.
+const SCRIPT : &'static str = "\
+var list = null;
+for (var i = 0; i < 2000; i++) {
+    list = { value: i, name: 'item' + i, next: list };
+    for (var j = 0; j < 20; j++) {
+        var garbage = { a: [j, j + 1], b: 'x' + j };
+    }
+}
+var sum = 0;
+var ok = true;
+for (var item = list, k = 1999; item !== null; item = item.next, k--) {
+    ok = ok && item.value === k && item.name === 'item' + k;
+    sum += item.value;
+}
+ok + ',' + sum
+";
+
+fn run(strategy: GcStrategy, compact_threshold: f64) -> String {
+    let mut opts = GcOpts::default();
+    opts.strategy = strategy;
+    opts.initial_heap = 256 * 1024;
+    opts.compact_threshold = compact_threshold;
+    opts.nursery_size = 64 * 1024;
+    
+    let mut env = JsEnvBuilder::new().gc_opts(opts).build().ok().unwrap();
+    
+    let result = env.eval(SCRIPT).ok().unwrap();
+    let result = result.as_value(&env);
+    
+    result.to_string(&mut env).ok().unwrap().to_string()
+}
+
+#[test]
+fn copying() {
+    assert_eq!(run(GcStrategy::Copying, 0.25), "true,1999000");
+}
+
+#[test]
+fn mark_sweep() {
+    assert_eq!(run(GcStrategy::MarkSweep, 1.0), "true,1999000");
+}
+
+#[test]
+fn mark_sweep_compact() {
+    assert_eq!(run(GcStrategy::MarkSweep, 0.0), "true,1999000");
+}
+
+#[test]
+fn generational() {
+    assert_eq!(run(GcStrategy::Generational, 0.25), "true,1999000");
+}
+
+#[test]
+fn heap_stats() {
+    let mut opts = GcOpts::default();
+    opts.initial_heap = 256 * 1024;
+    
+    let mut env = JsEnvBuilder::new().gc_opts(opts).build().ok().unwrap();
+    
+    env.eval(SCRIPT).ok().unwrap();
+    
+    let stats = env.heap_stats();
+    
+    assert!(stats.collections > 0);
+    assert!(stats.total_pause_ns >= stats.max_pause_ns);
+    assert!(stats.max_pause_ns >= stats.last_pause_ns);
+    
+    // The list is still referenced from the global object.
+    
+    assert!(stats.get("object").unwrap().count >= 2000);
+    assert!(stats.get("string").unwrap().count >= 2000);
+    assert!(stats.live_bytes() <= stats.mem_used);
+}
+
+#[test]
+fn out_of_memory() {
+    let mut opts = GcOpts::default();
+    opts.initial_heap = 256 * 1024;
+    opts.max_heap = Some(1024 * 1024);
+    
+    let mut env = JsEnvBuilder::new().gc_opts(opts).build().ok().unwrap();
+    
+    // A string that is too large for the heap, an array whose items are too
+    // large for the heap and many small objects.
+    
+    let scripts = [
+        "(function () { var s = 'x'; while (true) { s = s + s; } })()",
+        "(function () { var a = []; while (true) { a.push(a.length); } })()",
+        "(function () { var list = null; while (true) { list = { next: list }; } })()"
+    ];
+    
+    for script in scripts.iter() {
+        match env.eval(script) {
+            Err(JsError::Abort(JsAbort::OutOfMemory)) => {},
+            _ => panic!("expected out of memory")
+        }
+        
+        // The environment can be used again after the garbage is collected.
+        
+        let result = env.eval("1 + 1").ok().unwrap().as_value(&env);
+        assert_eq!(result.to_string(&mut env).ok().unwrap().to_string(), "2");
+        assert!(env.heap_stats().mem_allocated <= 2 * 1024 * 1024);
+    }
+}
+
+#[test]
+fn json_out_of_memory() {
+    let mut opts = GcOpts::default();
+    opts.initial_heap = 256 * 1024;
+    opts.max_heap = Some(4 * 1024 * 1024);
+    
+    let mut env = JsEnvBuilder::new().gc_opts(opts).build().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    // The input fits the heap, but the objects it parses into don't. The
+    // parser allocates them without returning to the interpreter.
+    
+    let mut input = "[".to_string();
+    for _ in 0..50000 {
+        input.push_str("{\"a\":1},");
+    }
+    input.push_str("1]");
+    
+    let input = input.into_js(&mut env).ok().unwrap();
+    env.global().set("input", input).ok().unwrap();
+    
+    match env.eval("JSON.parse(input)") {
+        Err(JsError::Abort(JsAbort::OutOfMemory)) => {},
+        _ => panic!("expected out of memory")
+    }
+    
+    env.global().set("input", input).ok().unwrap();
+    
+    let result = env.eval("input.length").ok().unwrap().as_value(&env);
+    assert_eq!(result.to_string(&mut env).ok().unwrap().to_string(), "400002");
+}
+
+// Under gcstress every allocation collects and verifies the heap, so the
+// script is kept small.
+#[cfg(feature = "gcstress")]
+#[test]
+fn gcstress_verify() {
+    let script = "\
+var list = null;
+for (var i = 0; i < 50; i++) {
+    list = { value: i, name: 'item' + i, items: [i, [i]], next: list };
+}
+var sum = 0;
+for (var item = list; item !== null; item = item.next) {
+    sum += item.items[1][0];
+}
+sum
+";
+    
+    for &strategy in &[GcStrategy::Copying, GcStrategy::MarkSweep, GcStrategy::Generational] {
+        let mut opts = GcOpts::default();
+        opts.strategy = strategy;
+        
+        let mut env = JsEnvBuilder::new().gc_opts(opts).build().ok().unwrap();
+        
+        let result = env.eval(script).ok().unwrap();
+        let result = result.as_value(&env);
+        
+        assert_eq!(result.to_string(&mut env).ok().unwrap().to_string(), "1225");
+    }
+}
@@ -0,0 +1,63 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsValue, JsItem};
+use rjs::JsResult;
+
+fn to_string(env: &mut JsEnv, result: JsResult<rjs::gc::Root<rjs::rt::JsRawValue>>) -> String {
+    let result = match result {
+        Ok(result) => result,
+        Err(error) => error.as_runtime(env)
+    };
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+#[test]
+fn get_and_set() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    env.global().set("answer", JsValue::new_number(42.0)).ok().unwrap();
+    
+    let result = env.eval("answer + 1");
+    assert_eq!(to_string(&mut env, result), "43");
+    
+    env.eval("var greeting = 'hello'").ok().unwrap();
+    
+    let result = env.global().get("greeting");
+    assert_eq!(to_string(&mut env, result), "hello");
+}
+
+#[test]
+fn call_function() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    env.eval("function add(a, b) { return a + b; }").ok().unwrap();
+    
+    let result = env.global().call_function("add", &[JsValue::new_number(1.0), JsValue::new_number(2.0)]);
+    assert_eq!(to_string(&mut env, result), "3");
+    
+    let result = env.global().call_function("Math", &[]);
+    assert_eq!(to_string(&mut env, result), "TypeError: Value is not a function");
+}
+
+#[test]
+fn construct() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    env.eval("function Point(x, y) { this.x = x; this.y = y; } Point.prototype.toString = function () { return this.x + ',' + this.y; }").ok().unwrap();
+    
+    let result = env.global().construct("Point", &[JsValue::new_number(1.0), JsValue::new_number(2.0)]);
+    assert_eq!(to_string(&mut env, result), "1,2");
+    
+    // Functions that are not constructors cannot be constructed, even
+    // though they can be called.
+    
+    env.eval("var max = Math.max").ok().unwrap();
+    
+    let result = env.global().construct("max", &[]);
+    assert_eq!(to_string(&mut env, result), "TypeError: Not a constructor");
+}
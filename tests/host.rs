@@ -0,0 +1,94 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsArgs, JsFnMode, JsValue, JsRawValue, JsItem};
+use rjs::gc::Root;
+use std::cell::Cell;
+use std::rc::Rc;
+
+struct Counter {
+    count: u32
+}
+
+struct Connection {
+    _callback: Root<JsRawValue>,
+    closed: Rc<Cell<bool>>
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.closed.set(true);
+    }
+}
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+#[test]
+fn this_host() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let class = env.register_host_class("Counter");
+    
+    let increment = env.new_native_closure("increment", 0, Box::new(|env: &mut JsEnv, _mode: JsFnMode, args: JsArgs| {
+        let count = {
+            let counter = try!(args.this_host::<Counter>(env));
+            counter.count += 1;
+            counter.count
+        };
+        
+        Ok(JsValue::new_number(count as f64))
+    }));
+    
+    let name = env.intern("increment");
+    let mut prototype = class.prototype(&env);
+    prototype.put(&mut env, name, increment, true).ok().unwrap();
+    
+    let counter = env.new_host_object(&class, Box::new(Counter { count: 0 })).ok().unwrap();
+    env.global().set("counter", counter.as_value()).ok().unwrap();
+    
+    assert_eq!(eval(&mut env, "counter.increment(); counter.increment()"), "2");
+    assert_eq!(eval(&mut env, "String(counter)"), "[object Counter]");
+    assert_eq!(eval(&mut env, "try { counter.increment.call({}); } catch (e) { e instanceof TypeError }"), "true");
+}
+
+#[test]
+fn set_host_payload_on_function() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let function = env.eval("(function () {})").ok().unwrap();
+    let mut function = function.as_value(&env).unwrap_object();
+    
+    assert!(function.set_host_payload(&mut env, Box::new(Counter { count: 0 })).is_err());
+}
+
+#[test]
+fn drop_payload_on_collection() {
+    let mut env = JsEnv::new().ok().unwrap();
+    
+    let closed = Rc::new(Cell::new(false));
+    
+    {
+        let _scope = env.new_local_scope();
+        
+        let class = env.register_host_class("Connection");
+        
+        // The payload keeps a callback alive through a root, which is
+        // released when the payload is dropped.
+        
+        let callback = env.eval("(function () {})").ok().unwrap();
+        
+        env.new_host_object(&class, Box::new(Connection { _callback: callback, closed: closed.clone() })).ok().unwrap();
+    }
+    
+    assert!(!closed.get());
+    
+    env.gc();
+    
+    assert!(closed.get());
+}
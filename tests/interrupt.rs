@@ -0,0 +1,75 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsAbort, JsItem};
+use rjs::JsError;
+use std::thread;
+use std::time::Duration;
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+fn aborted(env: &mut JsEnv, js: &str) -> Option<JsAbort> {
+    match env.eval(js) {
+        Err(JsError::Abort(abort)) => Some(abort),
+        _ => None
+    }
+}
+
+#[test]
+fn instruction_budget() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    env.set_instruction_budget(Some(10000));
+    
+    assert_eq!(aborted(&mut env, "while (true) {}"), Some(JsAbort::BudgetExhausted));
+    assert_eq!(env.instruction_budget(), Some(0));
+    
+    // Aborts cannot be caught by scripts.
+    
+    env.set_instruction_budget(Some(10000));
+    
+    assert_eq!(aborted(&mut env, "try { while (true) {} } catch (e) {} finally { this.x = 1; }"), Some(JsAbort::BudgetExhausted));
+    
+    env.set_instruction_budget(None);
+    
+    assert_eq!(eval(&mut env, "typeof x"), "undefined");
+}
+
+#[test]
+fn interrupt() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let handle = env.interrupt_handle();
+    
+    let thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        handle.interrupt();
+    });
+    
+    assert_eq!(aborted(&mut env, "while (true) {}"), Some(JsAbort::Interrupted));
+    
+    thread.join().unwrap();
+    
+    // The environment can be used after an interrupt.
+    
+    assert_eq!(eval(&mut env, "1 + 1"), "2");
+}
+
+#[test]
+fn interrupt_while_idle() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    // The interrupt is not lost, but only aborts the next run.
+    
+    env.interrupt_handle().interrupt();
+    
+    assert_eq!(aborted(&mut env, "var i = 0; while (i < 1000) { i++; } i"), Some(JsAbort::Interrupted));
+    assert_eq!(eval(&mut env, "var i = 0; while (i < 1000) { i++; } i"), "1000");
+}
@@ -0,0 +1,61 @@
+extern crate rjs;
+extern crate rustc_serialize;
+
+use rjs::rt::JsEnv;
+use rustc_serialize::json::Json;
+use std::str;
+
+const SCRIPT : &'static str = "\
+function square(x) {
+    return x * x;
+}
+var sum = 0;
+for (var i = 0; i < 20000; i++) {
+    sum += square(i);
+}
+";
+
+#[test]
+fn collapsed_stacks() {
+    let mut env = JsEnv::new().ok().unwrap();
+    
+    env.start_profiler(1);
+    env.eval(SCRIPT).ok().unwrap();
+    let profile = env.stop_profiler().unwrap();
+    
+    let mut buffer = Vec::new();
+    profile.write_collapsed(&mut buffer).unwrap();
+    let collapsed = String::from_utf8(buffer).unwrap();
+    
+    // Every line is a call chain followed by its number of samples.
+    
+    let square = collapsed.lines().find(|line| line.contains("square (")).unwrap();
+    let hits = square.rsplit(' ').next().unwrap().parse::<u64>().unwrap();
+    assert!(hits >= 20000);
+}
+
+#[test]
+fn cpuprofile_is_aggregated() {
+    let mut env = JsEnv::new().ok().unwrap();
+    
+    env.start_profiler(1);
+    env.eval(SCRIPT).ok().unwrap();
+    let profile = env.stop_profiler().unwrap();
+    
+    let mut buffer = Vec::new();
+    profile.write_cpuprofile(&mut buffer).unwrap();
+    
+    let json = Json::from_str(str::from_utf8(&buffer).unwrap()).unwrap();
+    
+    let nodes = json.find("nodes").unwrap().as_array().unwrap();
+    let samples = json.find("samples").unwrap().as_array().unwrap();
+    let time_deltas = json.find("timeDeltas").unwrap().as_array().unwrap();
+    
+    // The samples do not grow with the number of instructions that ran.
+    
+    assert!(samples.len() <= nodes.len());
+    assert_eq!(samples.len(), time_deltas.len());
+    
+    let hit_count = nodes.iter().fold(0, |count, node| count + node.find("hitCount").unwrap().as_u64().unwrap());
+    assert!(hit_count >= 20000);
+}
@@ -0,0 +1,47 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsItem};
+use rjs::JsError;
+
+fn is_incomplete(env: &mut JsEnv, js: &str) -> bool {
+    match env.eval(js) {
+        Err(JsError::Incomplete(..)) => true,
+        _ => false
+    }
+}
+
+#[test]
+fn incomplete_input() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    assert!(is_incomplete(&mut env, "function f() {"));
+    assert!(is_incomplete(&mut env, "var x = [1, 2"));
+    assert!(is_incomplete(&mut env, "1 +"));
+    assert!(is_incomplete(&mut env, "/* comment"));
+    
+    assert!(!is_incomplete(&mut env, "1 + + * 2"));
+    assert!(!is_incomplete(&mut env, "}"));
+    
+    // Incomplete input is a SyntaxError to scripts.
+    
+    let result = env.eval("try { eval('function f() {'); } catch (e) { e instanceof SyntaxError }").ok().unwrap();
+    let result = result.as_value(&env);
+    assert_eq!(result.to_string(&mut env).ok().unwrap().to_string(), "true");
+}
+
+#[test]
+fn inspect() {
+    let mut env = JsEnv::new().ok().unwrap();
+    let _scope = env.new_local_scope();
+    
+    let result = env.eval("({ a: [1, 'x'], 'b c': null, get d() { return 1; }, e: function f() {} })").ok().unwrap();
+    let result = result.as_value(&env);
+    
+    assert_eq!(env.inspect(result).ok().unwrap(), "{ a: [ 1, 'x' ], 'b c': null, d: [Getter], e: [Function: f] }");
+    
+    let result = env.eval("var o = { x: -0 }; o.self = o; o").ok().unwrap();
+    let result = result.as_value(&env);
+    
+    assert_eq!(env.inspect(result).ok().unwrap(), "{ x: -0, self: [Circular] }");
+}
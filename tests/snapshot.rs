@@ -0,0 +1,56 @@
+extern crate rjs;
+extern crate rustc_serialize;
+
+use rjs::rt::JsEnv;
+use rustc_serialize::json::Json;
+use std::str;
+
+fn numbers(json: &Json, key: &str) -> Vec<u64> {
+    json.find(key).unwrap().as_array().unwrap().iter().map(|value| value.as_u64().unwrap()).collect()
+}
+
+#[test]
+fn nodes_edges_and_names() {
+    let mut env = JsEnv::new().ok().unwrap();
+    
+    env.eval("var leak = { payload: 'leaked string', items: [1, 2, 3] };").ok().unwrap();
+    
+    let mut buffer = Vec::new();
+    env.write_heap_snapshot_to(&mut buffer).unwrap();
+    
+    let json = Json::from_str(str::from_utf8(&buffer).unwrap()).unwrap();
+    
+    let meta = json.find_path(&["snapshot", "meta"]).unwrap();
+    let node_fields = meta.find("node_fields").unwrap().as_array().unwrap().len();
+    let edge_fields = meta.find("edge_fields").unwrap().as_array().unwrap().len();
+    
+    let nodes = numbers(&json, "nodes");
+    let edges = numbers(&json, "edges");
+    
+    let node_count = json.find_path(&["snapshot", "node_count"]).unwrap().as_u64().unwrap() as usize;
+    let edge_count = json.find_path(&["snapshot", "edge_count"]).unwrap().as_u64().unwrap() as usize;
+    
+    assert_eq!(nodes.len(), node_count * node_fields);
+    assert_eq!(edges.len(), edge_count * edge_fields);
+    
+    // The edge counts of the nodes add up and every edge points to a node.
+    
+    let edge_counts = (0..node_count).fold(0, |count, node| count + nodes[node * node_fields + 4]);
+    assert_eq!(edge_counts as usize, edge_count);
+    
+    for edge in 0..edge_count {
+        let to_node = edges[edge * edge_fields + 2] as usize;
+        assert_eq!(to_node % node_fields, 0);
+        assert!(to_node < nodes.len());
+    }
+    
+    let strings = json.find("strings").unwrap().as_array().unwrap();
+    let has_string = |string: &str| strings.iter().any(|value| value.as_string() == Some(string));
+    
+    assert!(has_string("(GC roots)"));
+    assert!(has_string("leak"));
+    assert!(has_string("payload"));
+    assert!(has_string("leaked string"));
+    assert!(has_string("Object"));
+    assert!(has_string("Array"));
+}
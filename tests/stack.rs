@@ -0,0 +1,71 @@
+extern crate rjs;
+
+use rjs::rt::{JsEnv, JsEnvBuilder, JsItem};
+use std::thread;
+
+fn eval(env: &mut JsEnv, js: &str) -> String {
+    let result = env.eval(js).ok().unwrap();
+    let result = result.as_value(env);
+    
+    result.to_string(env).ok().unwrap().to_string()
+}
+
+// Runs the test on a thread with the given stack size.
+fn run<F: FnOnce() + Send + 'static>(stack_size: usize, f: F) {
+    thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn deep_recursion() {
+    run(64 * 1024 * 1024, || {
+        let mut env = JsEnvBuilder::new()
+            .native_stack_size(48 * 1024 * 1024)
+            .build()
+            .ok()
+            .unwrap();
+        let _scope = env.new_local_scope();
+        
+        assert_eq!(eval(&mut env, "function sum(n) { return n == 0 ? 0 : n + sum(n - 1); } sum(1000)"), "500500");
+    });
+}
+
+#[test]
+fn infinite_recursion() {
+    run(8 * 1024 * 1024, || {
+        let mut env = JsEnv::new().ok().unwrap();
+        let _scope = env.new_local_scope();
+        
+        assert_eq!(eval(&mut env, "function f() { return f() + 1; } try { f(); } catch (e) { e instanceof RangeError }"), "true");
+        
+        // The environment can be used after the stack overflowed.
+        
+        assert_eq!(eval(&mut env, "function g(n) { return n == 0 ? 0 : 1 + g(n - 1); } g(100)"), "100");
+    });
+}
+
+#[test]
+fn apply_with_many_arguments() {
+    run(8 * 1024 * 1024, || {
+        let mut env = JsEnvBuilder::new().stack_size(64 * 1024).build().ok().unwrap();
+        let _scope = env.new_local_scope();
+        
+        assert_eq!(eval(&mut env, "try { Math.max.apply(null, new Array(100000)); } catch (e) { e instanceof RangeError }"), "true");
+    });
+}
+
+#[test]
+fn max_call_depth() {
+    run(8 * 1024 * 1024, || {
+        let mut env = JsEnvBuilder::new().max_call_depth(50).build().ok().unwrap();
+        let _scope = env.new_local_scope();
+        
+        // The global code is the first call.
+        
+        assert_eq!(eval(&mut env, "function depth() { try { return 1 + depth(); } catch (e) { return 1; } } depth()"), "49");
+    });
+}